@@ -0,0 +1,130 @@
+use crate::gamedata::{
+    consumable::TimedBuff,
+    gamedata::ItemName,
+    item::Buffs,
+    status::Stacking,
+};
+use serde::{Deserialize, Serialize};
+
+/// buffs someone has from consuming an item, until they run out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveBuff {
+    pub item: ItemName,
+    /// the buffs for each stack.
+    pub buffs: Buffs,
+    pub stacks: u64,
+    pub turns_left: Option<u64>,
+    /// the time (in seconds) it runs out at.
+    pub expires_at: Option<f64>,
+}
+
+impl ActiveBuff {
+    /// the buffs from every stack, multiplied together.
+    pub fn total(&self) -> Buffs {
+        let mut buffs = Buffs::new();
+        for _ in 0..self.stacks {
+            buffs.stack(&self.buffs);
+        }
+        buffs
+    }
+
+    pub fn secs_left(&self, now: f64) -> Option<f64> {
+        self.expires_at.map(|t| (t - now).max(0.0))
+    }
+}
+
+/// every buff someone has from consuming items, in the order they got them.
+/// The buffs from each item are tracked separately, and follow the item's
+/// stacking rules when it's consumed again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveBuffs {
+    active: Vec<ActiveBuff>,
+}
+
+impl Default for ActiveBuffs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ActiveBuffs {
+    pub fn new() -> Self {
+        ActiveBuffs { active: vec![] }
+    }
+
+    pub fn active(&self) -> &[ActiveBuff] {
+        &self.active
+    }
+
+    /// all the active buffs, multiplied together.
+    pub fn buffs(&self) -> Buffs {
+        let mut buffs = Buffs::new();
+        for active in &self.active {
+            buffs.stack(&active.total());
+        }
+        buffs
+    }
+
+    /// start the buffs from consuming `item` at time `now`. Returns the buff
+    /// as it now stands, or `None` if its stacking rules say to ignore it.
+    pub fn add(&mut self, item: &ItemName, timed: &TimedBuff, now: f64) -> Option<&ActiveBuff> {
+        let expires_at = timed.secs.map(|s| now + s);
+        let i = match self.active.iter().position(|a| &a.item == item) {
+            Some(i) => {
+                let active = &mut self.active[i];
+                match timed.stacking {
+                    Stacking::Stack => {
+                        active.stacks = (active.stacks + 1).min(timed.max_stacks);
+                        active.turns_left = timed.turns;
+                        active.expires_at = expires_at;
+                    }
+                    Stacking::Refresh => {
+                        active.turns_left = timed.turns;
+                        active.expires_at = expires_at;
+                    }
+                    Stacking::Extend => {
+                        active.turns_left = active.turns_left.and_then(|t| timed.turns.map(|d| t + d));
+                        active.expires_at = active
+                            .expires_at
+                            .and_then(|t| timed.secs.map(|s| t.max(now) + s));
+                    }
+                    Stacking::Ignore => return None,
+                }
+                i
+            }
+            None => {
+                self.active.push(ActiveBuff {
+                    item: item.clone(),
+                    buffs: timed.buffs.clone(),
+                    stacks: 1,
+                    turns_left: timed.turns,
+                    expires_at,
+                });
+                self.active.len() - 1
+            }
+        };
+        Some(&self.active[i])
+    }
+
+    /// count down a turn on every buff that lasts for some turns. Returns the
+    /// ones that ran out.
+    pub fn tick(&mut self) -> Vec<ActiveBuff> {
+        for active in &mut self.active {
+            if let Some(turns) = &mut active.turns_left {
+                *turns = turns.saturating_sub(1);
+            }
+        }
+        self.remove_where(|a| a.turns_left == Some(0))
+    }
+
+    /// take away every buff that's run out by time `now`, and return them.
+    pub fn expire(&mut self, now: f64) -> Vec<ActiveBuff> {
+        self.remove_where(|a| a.expires_at.map(|t| t <= now).unwrap_or(false))
+    }
+
+    fn remove_where(&mut self, expired: impl Fn(&ActiveBuff) -> bool) -> Vec<ActiveBuff> {
+        let (gone, kept) = self.active.drain(..).partition(|a| expired(a));
+        self.active = kept;
+        gone
+    }
+}
@@ -0,0 +1,306 @@
+use crate::{
+    gamedata::{
+        gamedata::{BlockName, GameData},
+        item::Area,
+    },
+    vector3::Vector3,
+    world::World,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// the part of the world a battle is fought in: which block is on each tile
+/// around the fight, and which of them are solid, on the layer the fight is
+/// on. The battle keeps its own copy, so that it (and its replay) doesn't need
+/// the world. Anything outside the arena counts as solid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Arena {
+    origin: Vector3,
+    radius: usize,
+    side: usize,
+    solid: Vec<bool>,
+    /// the block on each tile, or `None` for tiles outside the world.
+    #[serde(default = "Vec::new")]
+    blocks: Vec<Option<BlockName>>,
+}
+
+/// whether `(dx, dy)` is within `r` tiles. Half a tile of leeway makes
+/// diagonal neighbours count as 1 tile away, and gives rounder shapes.
+fn within(dx: i64, dy: i64, r: u64) -> bool {
+    let r = r as f64 + 0.5;
+    ((dx * dx + dy * dy) as f64) <= r * r
+}
+
+/// the tiles on a straight line from `from` to `to`, not including `from`.
+fn line(from: (i64, i64), to: (i64, i64)) -> Vec<(i64, i64)> {
+    let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+    let (sx, sy) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let (mut x, mut y) = from;
+    let mut err = dx + dy;
+    let mut tiles = vec![];
+    while (x, y) != to {
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+        tiles.push((x, y));
+    }
+    tiles
+}
+
+impl Arena {
+    /// copy everything within `radius` tiles of `origin` from the world.
+    pub fn capture(world: &World, g: &GameData, origin: Vector3, radius: usize) -> Result<Self> {
+        world.get_block(origin, g)?;
+        let side = radius * 2 + 1;
+        let mut arena = Arena {
+            origin,
+            radius,
+            side,
+            solid: vec![true; side * side],
+            blocks: vec![None; side * side],
+        };
+        let dim = world.dim();
+        for i in 0..arena.solid.len() {
+            let (x, y) = arena.pos(i);
+            if x >= 0 && y >= 0 && (x as usize) < dim.x() && (y as usize) < dim.y() {
+                let loc = Vector3::new(x as usize, y as usize, origin.z());
+                let block = world.get_block(loc, g)?;
+                arena.solid[i] = block.solid;
+                arena.blocks[i] = Some(block.name.clone());
+            }
+        }
+        Ok(arena)
+    }
+
+    pub fn origin(&self) -> Vector3 {
+        self.origin
+    }
+
+    fn pos(&self, i: usize) -> (i64, i64) {
+        (
+            self.origin.x() as i64 + (i % self.side) as i64 - self.radius as i64,
+            self.origin.y() as i64 + (i / self.side) as i64 - self.radius as i64,
+        )
+    }
+
+    fn index(&self, (x, y): (i64, i64)) -> Option<usize> {
+        let x = x - self.origin.x() as i64 + self.radius as i64;
+        let y = y - self.origin.y() as i64 + self.radius as i64;
+        if x < 0 || y < 0 || x as usize >= self.side || y as usize >= self.side {
+            None
+        } else {
+            Some(y as usize * self.side + x as usize)
+        }
+    }
+
+    /// where `loc` is in the arena, if it's on the arena's layer.
+    fn flat(&self, loc: Vector3) -> Option<(i64, i64)> {
+        if loc.z() == self.origin.z() {
+            Some((loc.x() as i64, loc.y() as i64))
+        } else {
+            None
+        }
+    }
+
+    fn solid_at(&self, tile: (i64, i64)) -> bool {
+        self.index(tile).map(|i| self.solid[i]).unwrap_or(true)
+    }
+
+    /// the block at `loc`, if it's in the arena.
+    pub fn block(&self, loc: Vector3) -> Option<&BlockName> {
+        let i = self.index(self.flat(loc)?)?;
+        self.blocks.get(i)?.as_ref()
+    }
+
+    pub fn is_solid(&self, loc: Vector3) -> bool {
+        self.flat(loc).map(|t| self.solid_at(t)).unwrap_or(true)
+    }
+
+    /// whether there's nothing solid between two tiles. The tiles themselves
+    /// can be solid.
+    pub fn clear_path(&self, from: Vector3, to: Vector3) -> bool {
+        match (self.flat(from), self.flat(to)) {
+            (Some(from), Some(to)) => line(from, to)
+                .into_iter()
+                .filter(|t| *t != to)
+                .all(|t| !self.solid_at(t)),
+            _ => false,
+        }
+    }
+
+    /// whether something at `from` can reach `to` with an ability with the
+    /// given range, that may need a clear line of sight.
+    pub fn reaches(&self, from: Vector3, to: Vector3, range: u64, line_of_sight: bool) -> bool {
+        match (self.flat(from), self.flat(to)) {
+            (Some(f), Some(t)) => {
+                within(t.0 - f.0, t.1 - f.1, range) && (!line_of_sight || self.clear_path(from, to))
+            }
+            _ => false,
+        }
+    }
+
+    /// every tile someone at `from` could run to: tiles that aren't solid,
+    /// are at most `max_distance` tiles away, and have nothing solid in the
+    /// way. In a fixed order, not including `from` itself.
+    pub fn escape_tiles(&self, from: Vector3, max_distance: f64) -> Vec<Vector3> {
+        let f = match self.flat(from) {
+            Some(f) => f,
+            None => return vec![],
+        };
+        (0..self.solid.len())
+            .filter(|i| !self.solid[*i])
+            .map(|i| self.pos(i))
+            .filter(|t| {
+                let (dx, dy) = ((t.0 - f.0) as f64, (t.1 - f.1) as f64);
+                *t != f && dx * dx + dy * dy <= max_distance * max_distance
+            })
+            .map(|t| Vector3::new(t.0 as usize, t.1 as usize, self.origin.z()))
+            .filter(|loc| self.clear_path(from, *loc))
+            .collect()
+    }
+
+    /// whether the tile `loc` is hit by an ability with the given area, used
+    /// from `from` and aimed at `aim`.
+    pub fn in_area(&self, area: &Area, from: Vector3, aim: Vector3, loc: Vector3) -> bool {
+        let (f, a, l) = match (self.flat(from), self.flat(aim), self.flat(loc)) {
+            (Some(f), Some(a), Some(l)) => (f, a, l),
+            _ => return false,
+        };
+        match area {
+            Area::Single => l == a,
+            Area::Radius(r) => within(l.0 - a.0, l.1 - a.1, *r) && self.clear_path(aim, loc),
+            Area::Line(length) => {
+                let (dx, dy) = (a.0 - f.0, a.1 - f.1);
+                let steps = dx.abs().max(dy.abs());
+                if steps == 0 {
+                    return l == a;
+                }
+                // stretch the line out to its full length
+                let length = *length as i64;
+                let end = (f.0 + dx * length / steps, f.1 + dy * length / steps);
+                for tile in line(f, end) {
+                    if self.solid_at(tile) {
+                        return false;
+                    }
+                    if tile == l {
+                        return true;
+                    }
+                }
+                false
+            }
+            Area::Cone { length, angle } => {
+                if l == f || !within(l.0 - f.0, l.1 - f.1, *length) {
+                    return false;
+                }
+                let towards = ((a.1 - f.1) as f64).atan2((a.0 - f.0) as f64);
+                let to_tile = ((l.1 - f.1) as f64).atan2((l.0 - f.0) as f64);
+                let mut diff = (to_tile - towards).abs().to_degrees() % 360.0;
+                if diff > 180.0 {
+                    diff = 360.0 - diff;
+                }
+                diff <= angle / 2.0 && self.clear_path(from, loc)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamedata::gamedata::pvp;
+
+    fn at(x: usize, y: usize) -> Vector3 {
+        Vector3::new(x, y, 0)
+    }
+
+    /// a field of grass with a rock two tiles north of the middle.
+    fn arena(g: &GameData) -> Arena {
+        let mut world = World::filled(Vector3::new(9, 9, 1), "grass", g);
+        world.set_block(at(4, 2), "rock", g);
+        Arena::capture(&world, g, at(4, 4), 4).unwrap()
+    }
+
+    #[test]
+    fn captures_blocks_and_the_edge_of_the_world() {
+        let g = pvp();
+        let arena = arena(&g);
+        assert_eq!(arena.block(at(4, 2)).unwrap().as_str(), "rock");
+        assert_eq!(arena.block(at(0, 8)).unwrap().as_str(), "grass");
+        assert!(arena.is_solid(at(4, 2)));
+        assert!(!arena.is_solid(at(4, 4)));
+        assert!(arena.is_solid(Vector3::new(4, 4, 1)));
+        let world = World::filled(Vector3::new(9, 9, 1), "grass", &g);
+        let corner = Arena::capture(&world, &g, at(1, 1), 3).unwrap();
+        assert!(corner.block(at(0, 0)).is_some());
+        assert!(corner.block(at(5, 5)).is_none());
+        assert!(!corner.is_solid(at(0, 0)));
+        assert!(corner.is_solid(at(5, 5)));
+    }
+
+    #[test]
+    fn range_and_line_of_sight() {
+        let g = pvp();
+        let arena = arena(&g);
+        assert!(arena.reaches(at(4, 4), at(4, 1), 3, false));
+        assert!(!arena.reaches(at(4, 4), at(4, 1), 3, true));
+        assert!(!arena.reaches(at(4, 4), at(4, 1), 2, false));
+        assert!(arena.reaches(at(4, 4), at(7, 4), 3, true));
+        assert!(arena.reaches(at(4, 4), at(6, 6), 3, true));
+        assert!(!arena.reaches(at(4, 4), at(6, 6), 2, true));
+        // the rock itself can be hit
+        assert!(arena.reaches(at(4, 4), at(4, 2), 2, true));
+        assert!(!arena.reaches(at(4, 4), Vector3::new(4, 4, 1), 3, false));
+    }
+
+    #[test]
+    fn areas() {
+        let g = pvp();
+        let arena = arena(&g);
+        let from = at(4, 4);
+        let radius = Area::Radius(1);
+        assert!(arena.in_area(&radius, from, at(6, 4), at(6, 5)));
+        assert!(arena.in_area(&radius, from, at(6, 4), at(7, 5)));
+        assert!(!arena.in_area(&radius, from, at(6, 4), at(8, 4)));
+
+        let line = Area::Line(4);
+        for x in 5..9 {
+            assert!(arena.in_area(&line, from, at(5, 4), at(x, 4)));
+        }
+        assert!(!arena.in_area(&line, from, at(5, 4), at(4, 5)));
+        // lines stop at the first solid tile
+        assert!(arena.in_area(&line, from, at(4, 3), at(4, 3)));
+        assert!(!arena.in_area(&line, from, at(4, 3), at(4, 1)));
+
+        let cone = Area::Cone {
+            length: 3,
+            angle: 90.0,
+        };
+        assert!(arena.in_area(&cone, from, at(6, 4), at(6, 5)));
+        assert!(arena.in_area(&cone, from, at(6, 4), at(7, 4)));
+        assert!(!arena.in_area(&cone, from, at(6, 4), at(5, 6)));
+        assert!(!arena.in_area(&cone, from, at(6, 4), at(2, 4)));
+        assert!(!arena.in_area(&cone, from, at(6, 4), from));
+    }
+
+    #[test]
+    fn escapes_go_round_solid_tiles() {
+        let g = pvp();
+        let arena = arena(&g);
+        assert_eq!(
+            arena.escape_tiles(at(4, 4), 1.0),
+            vec![at(4, 3), at(3, 4), at(5, 4), at(4, 5)]
+        );
+        assert_eq!(
+            arena.escape_tiles(at(4, 3), 1.0),
+            vec![at(3, 3), at(5, 3), at(4, 4)]
+        );
+        assert!(!arena.escape_tiles(at(4, 4), 3.0).contains(&at(4, 1)));
+        assert!(arena.escape_tiles(at(4, 4), 3.0).contains(&at(6, 2)));
+    }
+}
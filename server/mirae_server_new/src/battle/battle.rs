@@ -0,0 +1,1776 @@
+use super::{
+    arena::Arena,
+    combatant::{ActiveStatus, BattleAbility, Charging, Combatant, CombatantId, Cooldown, Team},
+    damage,
+    effect::EffectContext,
+    event::BattleEvent,
+    initiative::Initiative,
+    replay::{Replay, Step},
+};
+use crate::gamedata::{
+    consumable::Consumable,
+    gamedata::{DmgType, GameData, ItemName, StatusName},
+    item::{Ability, Area, Targets},
+    status::{Stacking, Status, StatusTrigger},
+};
+use crate::{inventory::Wear, vector3::Vector3, world::get_rand};
+use anyhow::{anyhow, Result};
+use rand::{prelude::StdRng, Rng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// the entries of a map in key order. Abilities are stored in hash maps, but
+/// the battle has to resolve them in the same order every time.
+fn sorted<K: Ord, V>(map: &HashMap<K, V>) -> Vec<(&K, &V)> {
+    let mut entries: Vec<(&K, &V)> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Victory(Team),
+    Draw,
+}
+
+/// what a combatant does with their turn.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Choice {
+    /// use an ability. `target` is who to use it on, for abilities that hit
+    /// one enemy or ally. It can be left out if there's only one they could
+    /// pick.
+    Ability {
+        name: String,
+        target: Option<CombatantId>,
+    },
+    /// consume one of an item, e.g. eat an orange.
+    Consume { item: ItemName },
+    /// run away from the battle.
+    Flee,
+}
+
+impl Choice {
+    /// use an ability, on whoever it has to be used on.
+    pub fn ability(name: &str) -> Self {
+        Choice::Ability {
+            name: name.to_string(),
+            target: None,
+        }
+    }
+
+    /// use an ability on `target`.
+    pub fn ability_on(name: &str, target: CombatantId) -> Self {
+        Choice::Ability {
+            name: name.to_string(),
+            target: Some(target),
+        }
+    }
+
+    pub fn consume(item: &str) -> Self {
+        Choice::Consume {
+            item: ItemName::from(item.to_string()),
+        }
+    }
+}
+
+/// a turn based fight between two or more teams. The battle knows nothing
+/// about the world or about players, it just resolves the abilities it's
+/// given. Combatants can join part way through, and leave by fleeing or being
+/// defeated. The battle is over once only one team is left.
+/// All of the battle's randomness comes from its seed, so the same seed and
+/// the same choices always play out the same way.
+pub struct Battle<'a> {
+    g: &'a GameData,
+    seed: u64,
+    rng: StdRng,
+    /// everyone as they were when the battle started, and everything that's
+    /// happened since, so the battle can be replayed.
+    initial: Vec<Vec<Combatant>>,
+    steps: Vec<Step>,
+    arena: Option<Arena>,
+    /// the time in seconds, as far as the battle knows. Only used for
+    /// cooldowns, and only moves when it's told to.
+    clock: f64,
+    num_teams: usize,
+    combatants: Vec<Combatant>,
+    initiative: Initiative,
+    current: CombatantId,
+    turns: u64,
+    log: Vec<BattleEvent>,
+    outcome: Option<Outcome>,
+}
+
+impl<'a> Battle<'a> {
+    /// start a battle between `teams`. Combatants are numbered in order, team
+    /// by team. Whoever is fastest takes the first turn, or whoever comes
+    /// first if they're equally fast. There have to be at least two teams
+    /// with someone on them.
+    pub fn new(g: &'a GameData, seed: u64, teams: Vec<Vec<Combatant>>) -> Result<Self> {
+        if teams.iter().filter(|t| !t.is_empty()).count() < 2 {
+            return Err(anyhow!("a battle needs at least two teams"));
+        }
+        let mut combatants = vec![];
+        for (team, members) in teams.iter().enumerate() {
+            for c in members {
+                let mut c = Combatant {
+                    team,
+                    fled: false,
+                    defeated: false,
+                    ..c.clone()
+                };
+                // a replay's combatants come without their limits
+                c.reset_limits(g);
+                combatants.push(c);
+            }
+        }
+        let mut battle = Battle {
+            g,
+            seed,
+            rng: get_rand(seed),
+            initial: teams.clone(),
+            steps: vec![],
+            arena: None,
+            clock: 0.0,
+            num_teams: teams.len(),
+            initiative: Initiative::new(combatants.len()),
+            combatants,
+            current: 0,
+            turns: 0,
+            log: vec![],
+            outcome: None,
+        };
+        let mut events = vec![];
+        battle.check_defeated(&mut events);
+        battle.log.extend(events);
+        battle.next_turn();
+        Ok(battle)
+    }
+
+    /// start a battle in part of the world. Abilities then need to be in range
+    /// of whoever they're used on, and can hit everyone in an area. Only
+    /// combatants with a position are affected by this. They also take on the
+    /// alignments of the block they're standing on.
+    pub fn in_arena(
+        g: &'a GameData,
+        seed: u64,
+        teams: Vec<Vec<Combatant>>,
+        arena: Arena,
+    ) -> Result<Self> {
+        let mut battle = Battle::new(g, seed, teams)?;
+        battle.arena = Some(arena);
+        for who in 0..battle.combatants.len() {
+            battle.stand(who);
+        }
+        Ok(battle)
+    }
+
+    /// give `who` the alignments of the block they're standing on, if the
+    /// battle is in an arena. This has to be done whenever they move.
+    fn stand(&mut self, who: CombatantId) {
+        let block = match (&self.arena, self.combatants[who].pos) {
+            (Some(arena), Some(pos)) => arena.block(pos).and_then(|b| self.g.blocks.get(b)),
+            _ => return,
+        };
+        match block {
+            Some(block) => self.combatants[who].stand_on(block),
+            None => self.combatants[who].terrain_alignments.clear(),
+        }
+    }
+
+    pub fn arena(&self) -> Option<&Arena> {
+        self.arena.as_ref()
+    }
+
+    fn speeds(&self) -> Vec<Option<f64>> {
+        self.combatants
+            .iter()
+            .map(|c| Some(c.stat("speed")).filter(|_| c.in_fight()))
+            .collect()
+    }
+
+    fn next_turn(&mut self) {
+        if self.outcome.is_none() {
+            if let Some(next) = self.initiative.next(&self.speeds()) {
+                self.current = next;
+            }
+        }
+    }
+
+    /// who will act in the `n` turns after this one, if nobody's speed changes
+    /// and nobody leaves. This includes turns that will be lost to stuns or
+    /// spent charging.
+    pub fn upcoming(&self, n: usize) -> Vec<CombatantId> {
+        self.initiative.preview(&self.speeds(), n)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// everything needed to play this battle back exactly as it happened.
+    pub fn replay(&self) -> Replay {
+        Replay {
+            seed: self.seed,
+            teams: self.initial.clone(),
+            arena: self.arena.clone(),
+            steps: self.steps.clone(),
+        }
+    }
+
+    pub fn combatants(&self) -> &[Combatant] {
+        &self.combatants
+    }
+
+    pub fn combatant(&self, id: CombatantId) -> Option<&Combatant> {
+        self.combatants.get(id)
+    }
+
+    /// everyone who was in the battle from the start.
+    pub fn starters(&self) -> Vec<CombatantId> {
+        (0..self.initial.iter().map(Vec::len).sum()).collect()
+    }
+
+    /// everyone on `team`, including anyone who has left the fight.
+    pub fn team(&self, team: Team) -> Vec<CombatantId> {
+        (0..self.combatants.len())
+            .filter(|id| self.combatants[*id].team == team)
+            .collect()
+    }
+
+    pub fn clock(&self) -> f64 {
+        self.clock
+    }
+
+    /// move the battle's clock forward to `now` (in seconds). The clock starts
+    /// at 0, and whoever is running the battle decides what it means, as long
+    /// as cooldowns carried between battles use the same clock.
+    pub fn set_clock(&mut self, now: f64) -> Result<()> {
+        if now < self.clock {
+            return Err(anyhow!(format!(
+                "the battle's clock can't go backwards from {} to {}",
+                self.clock, now
+            )));
+        }
+        self.clock = now;
+        self.steps.push(Step::Clock(now));
+        Ok(())
+    }
+
+    /// how combatant `who` was when they started out in the battle, either at
+    /// the start or when they joined.
+    pub fn started_as(&self, who: CombatantId) -> Option<&Combatant> {
+        self.initial
+            .iter()
+            .flatten()
+            .chain(self.steps.iter().filter_map(|s| match s {
+                Step::Join { combatant, .. } => Some(&**combatant),
+                _ => None,
+            }))
+            .nth(who)
+    }
+
+    /// whose turn it is.
+    pub fn current(&self) -> CombatantId {
+        self.current
+    }
+
+    pub fn turns(&self) -> u64 {
+        self.turns
+    }
+
+    pub fn log(&self) -> &[BattleEvent] {
+        &self.log
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.outcome
+    }
+
+    /// everyone still in the fight who isn't on `id`'s team.
+    pub fn enemies(&self, id: CombatantId) -> Vec<CombatantId> {
+        let team = self.combatants.get(id).map(|c| c.team);
+        (0..self.combatants.len())
+            .filter(|i| Some(self.combatants[*i].team) != team && self.combatants[*i].in_fight())
+            .collect()
+    }
+
+    /// everyone else still in the fight on `id`'s team.
+    pub fn allies(&self, id: CombatantId) -> Vec<CombatantId> {
+        let team = self.combatants.get(id).map(|c| c.team);
+        (0..self.combatants.len())
+            .filter(|i| {
+                *i != id && Some(self.combatants[*i].team) == team && self.combatants[*i].in_fight()
+            })
+            .collect()
+    }
+
+    /// add someone to the battle part way through, fighting for `team`. `team`
+    /// can be one past the last team, to start a new one. They get their first
+    /// turn once they've built up enough speed, like everyone else.
+    pub fn join(&mut self, team: Team, combatant: Combatant) -> Result<CombatantId> {
+        if self.outcome.is_some() {
+            return Err(anyhow!("the battle is already over!"));
+        }
+        if team > self.num_teams {
+            return Err(anyhow!(format!(
+                "there is no team {}, there are only {}",
+                team, self.num_teams
+            )));
+        }
+        self.num_teams = self.num_teams.max(team + 1);
+        self.steps.push(Step::Join {
+            team,
+            combatant: Box::new(combatant.clone()),
+        });
+        let who = self.combatants.len();
+        let mut combatant = Combatant {
+            team,
+            fled: false,
+            defeated: false,
+            ..combatant
+        };
+        combatant.reset_limits(self.g);
+        self.combatants.push(combatant);
+        self.stand(who);
+        self.log.push(BattleEvent::Joined { who, team });
+        Ok(who)
+    }
+
+    /// whether the current combatant gets to pick what to do this turn, or if
+    /// they're stunned or busy charging.
+    pub fn needs_choice(&self) -> bool {
+        let c = &self.combatants[self.current];
+        c.stunned == 0 && c.charging.is_none()
+    }
+
+    /// whether `who` can reach `target` with `ability`. Outside of an arena,
+    /// or if either of them doesn't have a position, everyone can reach
+    /// everyone.
+    pub fn can_reach(&self, who: CombatantId, target: CombatantId, ability: &Ability) -> bool {
+        let from = self.combatants.get(who).and_then(|c| c.pos);
+        let to = self.combatants.get(target).and_then(|c| c.pos);
+        match (&self.arena, from, to) {
+            (Some(arena), Some(from), Some(to)) if who != target => {
+                arena.reaches(from, to, ability.range, ability.line_of_sight)
+            }
+            _ => true,
+        }
+    }
+
+    /// everyone `who` could pick to use `ability` on. For abilities that don't
+    /// need picking, this is everyone they'd hit.
+    pub fn candidates(&self, who: CombatantId, ability: &Ability) -> Vec<CombatantId> {
+        let candidates = match ability.targets {
+            Targets::Enemy | Targets::Enemies => self.enemies(who),
+            Targets::Ally => self.allies(who),
+            Targets::User => vec![who],
+        };
+        candidates
+            .into_iter()
+            .filter(|c| self.can_reach(who, *c, ability))
+            .collect()
+    }
+
+    /// everyone caught in the area of `ability`, when `who` aims it at
+    /// `target`. Area abilities aimed at an enemy hit every enemy in the area,
+    /// and ones aimed at an ally hit everyone on the user's side. `target` is
+    /// always first.
+    fn area(&self, who: CombatantId, target: CombatantId, ability: &Ability) -> Vec<CombatantId> {
+        let (arena, from, aim) = match (
+            &self.arena,
+            self.combatants[who].pos,
+            self.combatants[target].pos,
+        ) {
+            (Some(arena), Some(from), Some(aim)) if ability.area != Area::Single => {
+                (arena, from, aim)
+            }
+            _ => return vec![target],
+        };
+        let side = match ability.targets {
+            Targets::Ally => {
+                let mut side = self.allies(who);
+                side.push(who);
+                side.sort_unstable();
+                side
+            }
+            _ => self.enemies(who),
+        };
+        let mut hit = vec![target];
+        for id in side {
+            if let Some(pos) = self.combatants[id].pos {
+                if id != target && arena.in_area(&ability.area, from, aim, pos) {
+                    hit.push(id);
+                }
+            }
+        }
+        hit
+    }
+
+    /// who `who` would hit by using `ability` on `target`. `target` is only
+    /// needed for abilities that hit one enemy or ally, when there's more than
+    /// one to pick from.
+    pub fn targets(
+        &self,
+        who: CombatantId,
+        ability: &str,
+        target: Option<CombatantId>,
+    ) -> Result<Vec<CombatantId>> {
+        let c = self
+            .combatants
+            .get(who)
+            .ok_or_else(|| anyhow!(format!("there is no combatant {}", who)))?;
+        let a = c.abilities.get(ability).ok_or_else(|| {
+            anyhow!(format!("{} doesn't have the ability {:?}", c.name, ability))
+        })?;
+        let candidates = self.candidates(who, &a.ability);
+        match (a.ability.targets, target) {
+            (Targets::Enemies, _) | (Targets::User, _) => Ok(candidates),
+            (_, Some(target)) if candidates.contains(&target) => {
+                Ok(self.area(who, target, &a.ability))
+            }
+            (_, Some(target)) => Err(anyhow!(format!(
+                "{} can't use {} on {}",
+                c.name,
+                ability,
+                self.combatants
+                    .get(target)
+                    .map(|t| t.name.as_str())
+                    .unwrap_or("nobody")
+            ))),
+            (_, None) if candidates.len() == 1 => Ok(self.area(who, candidates[0], &a.ability)),
+            (_, None) if candidates.is_empty() => Err(anyhow!(format!(
+                "there is nobody {} can reach with {}",
+                c.name, ability
+            ))),
+            (_, None) => Err(anyhow!(format!(
+                "{} has to choose who to use {} on",
+                c.name, ability
+            ))),
+        }
+    }
+
+    /// check that `who` could use `ability` right now.
+    pub fn check_ability(&self, who: CombatantId, ability: &str) -> Result<()> {
+        let c = self
+            .combatants
+            .get(who)
+            .ok_or_else(|| anyhow!(format!("there is no combatant {}", who)))?;
+        let a = c.abilities.get(ability).ok_or_else(|| {
+            anyhow!(format!("{} doesn't have the ability {:?}", c.name, ability))
+        })?;
+        if !c.in_fight() {
+            return Err(anyhow!(format!("{} is out of the fight", c.name)));
+        }
+        match c.cooldown_left(ability, self.clock) {
+            (0, secs) if secs <= 0.0 => {}
+            (0, secs) => {
+                return Err(anyhow!(format!(
+                    "{} can't use {} for another {:.1} seconds",
+                    c.name, ability, secs
+                )))
+            }
+            (turns, _) => {
+                return Err(anyhow!(format!(
+                    "{} can't use {} for another {} turns",
+                    c.name, ability, turns
+                )))
+            }
+        }
+        if a.ability.energy < 0.0 && c.energy + a.ability.energy < 0.0 {
+            return Err(anyhow!(format!(
+                "{} needs {} energy to use {}, but only has {}",
+                c.name, -a.ability.energy, ability, c.energy
+            )));
+        }
+        c.inventory
+            .check_ability(&a.ability, a.source.as_ref())
+            .map_err(|e| anyhow!(format!("{} can't use {}: {}", c.name, ability, e)))?;
+        if self.candidates(who, &a.ability).is_empty() {
+            return Err(anyhow!(format!(
+                "there is nobody {} can reach with {}",
+                c.name, ability
+            )));
+        }
+        Ok(())
+    }
+
+    /// check that `who` could consume one of `item` right now.
+    pub fn check_consume(&self, who: CombatantId, item: &ItemName) -> Result<&'a Consumable> {
+        let c = self
+            .combatants
+            .get(who)
+            .ok_or_else(|| anyhow!(format!("there is no combatant {}", who)))?;
+        let consumable = self
+            .g
+            .items
+            .get(item)
+            .ok_or_else(|| anyhow!(format!("{:?} is not an item", item)))?
+            .consumable
+            .as_ref()
+            .ok_or_else(|| anyhow!(format!("{} can't be consumed", item.as_str())))?;
+        if !c.in_fight() {
+            return Err(anyhow!(format!("{} is out of the fight", c.name)));
+        }
+        if c.inventory.get(item) == 0 {
+            return Err(anyhow!(format!(
+                "{} doesn't have any {}",
+                c.name,
+                item.as_str()
+            )));
+        }
+        Ok(consumable)
+    }
+
+    /// every ability `who` could use right now.
+    pub fn usable_abilities(&self, who: CombatantId) -> Vec<String> {
+        let mut abilities: Vec<String> = match self.combatants.get(who) {
+            Some(c) => c
+                .abilities
+                .keys()
+                .filter(|a| self.check_ability(who, a).is_ok())
+                .cloned()
+                .collect(),
+            None => vec![],
+        };
+        abilities.sort();
+        abilities
+    }
+
+    /// play out the current combatant's turn. `choice` is what they chose to
+    /// do, and is ignored if they're stunned or charging (see `needs_choice`).
+    /// If they can't use any of their abilities, `None` passes the turn.
+    /// If the choice isn't valid, or the turn can't be played out, nothing
+    /// changes and an error is returned.
+    /// Returns everything that happened this turn.
+    pub fn turn(&mut self, choice: Option<Choice>) -> Result<Vec<BattleEvent>> {
+        if self.outcome.is_some() {
+            return Err(anyhow!("the battle is already over!"));
+        }
+        let actor = self.current;
+        let choice = match choice {
+            _ if !self.needs_choice() => None,
+            Some(Choice::Ability { name, target }) => {
+                self.check_ability(actor, &name)?;
+                // remember exactly who was picked, so the replay doesn't
+                // depend on who else was around
+                let targets = self.targets(actor, &name, target)?;
+                let target = match self.combatants[actor].abilities[&name].ability.targets {
+                    Targets::Enemy | Targets::Ally => targets.first().cloned(),
+                    Targets::Enemies | Targets::User => None,
+                };
+                Some(Choice::Ability { name, target })
+            }
+            Some(Choice::Consume { item }) => {
+                self.check_consume(actor, &item)?;
+                Some(Choice::Consume { item })
+            }
+            Some(Choice::Flee) => Some(Choice::Flee),
+            // with nothing they can do, they have to pass
+            None if self.usable_abilities(actor).is_empty() => None,
+            None => {
+                return Err(anyhow!(format!(
+                    "{} has to choose what to do",
+                    self.combatants[actor].name
+                )))
+            }
+        };
+
+        // the turn can still fail part way through (e.g. an effect that isn't
+        // in the gamemode), so it's played out on the side and only kept if
+        // it all works
+        let (combatants, rng) = (self.combatants.clone(), self.rng.clone());
+        let mut events = vec![BattleEvent::Turn { actor }];
+        if let Err(e) = self.act(actor, choice.clone(), &mut events) {
+            self.combatants = combatants;
+            self.rng = rng;
+            return Err(e);
+        }
+
+        self.steps.push(Step::Turn(choice));
+        self.tick_cooldowns(actor);
+        self.check_defeated(&mut events);
+        self.next_turn();
+        self.turns += 1;
+        self.log.extend(events.iter().cloned());
+        Ok(events)
+    }
+
+    /// everything that happens in `actor`'s turn, given a valid `choice`.
+    /// If this fails, the turn can be left half played.
+    fn act(
+        &mut self,
+        actor: CombatantId,
+        choice: Option<Choice>,
+        events: &mut Vec<BattleEvent>,
+    ) -> Result<()> {
+        // blocks and counters only last until the combatant's next turn
+        self.combatants[actor].blocking.clear();
+        self.combatants[actor].countering.clear();
+        self.tick_statuses(actor, events)?;
+        self.tick_buffs(actor, events);
+        self.regenerate(actor, events);
+
+        if self.combatants[actor].is_dead() {
+            // taken out by their own statuses before they could act
+        } else if let Some(Choice::Flee) = choice {
+            self.flee(actor, events);
+        } else if let Some(Choice::Consume { item }) = choice {
+            self.consume(actor, item, events)?;
+        } else if let Some(Choice::Ability { name, target }) = choice {
+            let ability = self.combatants[actor].abilities[&name].clone();
+            let targets = self.targets(actor, &name, target)?;
+            events.push(BattleEvent::Ability {
+                actor,
+                targets: targets.clone(),
+                ability: name.clone(),
+            });
+            self.pay(actor, &name, events);
+            if ability.ability.charge > 0 {
+                events.push(BattleEvent::ChargeStart {
+                    actor,
+                    ability: name.clone(),
+                    turns: ability.ability.charge,
+                });
+                self.combatants[actor].charging = Some(Charging {
+                    ability: name,
+                    target,
+                    turns_left: ability.ability.charge,
+                });
+            } else {
+                self.use_items(actor, &name, events)?;
+                self.resolve(actor, &targets, &ability.ability, events)?;
+            }
+        } else if self.combatants[actor].stunned > 0 {
+            self.combatants[actor].stunned -= 1;
+            events.push(BattleEvent::Stunned {
+                actor,
+                turns_left: self.combatants[actor].stunned,
+            });
+        } else if let Some(charging) = self.combatants[actor].charging.take() {
+            if charging.turns_left > 1 {
+                events.push(BattleEvent::Charging {
+                    actor,
+                    ability: charging.ability.clone(),
+                    turns_left: charging.turns_left - 1,
+                });
+                self.combatants[actor].charging = Some(Charging {
+                    turns_left: charging.turns_left - 1,
+                    ..charging
+                });
+            } else {
+                let ability = self.combatants[actor].abilities[&charging.ability]
+                    .ability
+                    .clone();
+                // if whoever it was aimed at has left, it goes to whoever's
+                // left, or nobody
+                let targets = self
+                    .targets(actor, &charging.ability, charging.target)
+                    .or_else(|_| self.targets(actor, &charging.ability, None))
+                    .unwrap_or_default();
+                // their carry limits can shrink while they charge, e.g. when a
+                // buff runs out, so what the ability makes might not fit any
+                // more. Then it fizzles, rather than holding up the battle.
+                let mut used = vec![];
+                match self.use_items(actor, &charging.ability, &mut used) {
+                    Ok(()) => {
+                        events.push(BattleEvent::Ability {
+                            actor,
+                            targets: targets.clone(),
+                            ability: charging.ability.clone(),
+                        });
+                        events.extend(used);
+                        self.resolve(actor, &targets, &ability, events)?;
+                    }
+                    Err(e) => events.push(BattleEvent::Fizzled {
+                        actor,
+                        ability: charging.ability.clone(),
+                        reason: e.to_string(),
+                    }),
+                }
+            }
+        } else {
+            events.push(BattleEvent::Pass { actor });
+        }
+        Ok(())
+    }
+
+    /// try to run away. The chance of getting away depends on how `who`'s
+    /// speed compares to the fastest enemy still in the fight. In an arena,
+    /// whoever gets away ends up on a random free tile within the gamemode's
+    /// max_distance, and can't get away at all if there isn't one. Either
+    /// way, the attempt uses up their turn.
+    fn flee(&mut self, who: CombatantId, events: &mut Vec<BattleEvent>) {
+        let fastest = self
+            .enemies(who)
+            .into_iter()
+            .map(|e| self.combatants[e].stat("speed"))
+            .fold(0.0, f64::max);
+        let escapes: Option<Vec<Vector3>> = match (&self.arena, self.combatants[who].pos) {
+            (Some(arena), Some(pos)) => {
+                let taken: Vec<Vector3> = self
+                    .combatants
+                    .iter()
+                    .filter(|c| c.in_fight())
+                    .filter_map(|c| c.pos)
+                    .collect();
+                let tiles = arena.escape_tiles(pos, self.g.flee.max_distance);
+                Some(tiles.into_iter().filter(|t| !taken.contains(t)).collect())
+            }
+            _ => None,
+        };
+        let chance = match &escapes {
+            Some(tiles) if tiles.is_empty() => 0.0,
+            _ => self
+                .g
+                .flee
+                .chance(self.combatants[who].stat("speed"), fastest),
+        };
+        events.push(BattleEvent::FleeAttempt { who, chance });
+        let roll: f64 = self.rng.gen();
+        if roll < chance {
+            let to = escapes.map(|tiles| tiles[self.rng.gen_range(0, tiles.len())]);
+            let c = &mut self.combatants[who];
+            c.fled = true;
+            if to.is_some() {
+                c.pos = to;
+                self.stand(who);
+            }
+            events.push(BattleEvent::Fled { who, to });
+        } else {
+            events.push(BattleEvent::FleeFailed { who });
+        }
+    }
+
+    /// consume one of `item`: apply its health and energy straight away, and
+    /// start (or stack) its buffs.
+    fn consume(&mut self, who: CombatantId, item: ItemName, events: &mut Vec<BattleEvent>) -> Result<()> {
+        let consumable = self.check_consume(who, &item)?;
+        self.combatants[who].inventory.change(item.clone(), -1)?;
+        events.push(BattleEvent::Consumed {
+            who,
+            item: item.clone(),
+        });
+        self.change_health(who, consumable.health, events);
+        self.change_energy(who, consumable.energy, events);
+        if let Some(timed) = &consumable.timed {
+            let now = self.clock;
+            if let Some(active) = self.combatants[who].consumed.add(&item, timed, now) {
+                events.push(BattleEvent::BuffApplied {
+                    who,
+                    item,
+                    stacks: active.stacks,
+                    turns_left: active.turns_left,
+                    secs_left: active.secs_left(now),
+                });
+            }
+            self.combatants[who].reset_limits(self.g);
+        }
+        Ok(())
+    }
+
+    /// pay the up front cost of an ability: its energy. This also starts its
+    /// cooldown. Its items are only used once it goes off (see `use_items`),
+    /// so an ability that's still charging when its user is taken out, or the
+    /// battle ends, doesn't cost any.
+    fn pay(&mut self, who: CombatantId, name: &str, events: &mut Vec<BattleEvent>) {
+        let ability = self.combatants[who].abilities[name].ability.clone();
+        self.change_energy(who, ability.energy, events);
+        if ability.cooldown > 0 || ability.cooldown_secs > 0.0 {
+            self.combatants[who].cooldowns.insert(
+                name.to_string(),
+                Cooldown {
+                    // this turn doesn't count
+                    turns_left: ability.cooldown + 1,
+                    ready_at: self.clock + ability.cooldown_secs,
+                },
+            );
+        }
+    }
+
+    /// take away the items an ability uses up, and add the ones it makes, all
+    /// in one go.
+    fn use_items(&mut self, who: CombatantId, name: &str, events: &mut Vec<BattleEvent>) -> Result<()> {
+        let BattleAbility { source, ability } = self.combatants[who].abilities[name].clone();
+        let (wear, dropped) = self.combatants[who].inventory.use_ability(
+            self.g,
+            &ability,
+            source.as_ref(),
+            &mut self.rng,
+        )?;
+        for (item, amount) in sorted(&ability.remove_items) {
+            events.push(BattleEvent::ItemRemoved {
+                who,
+                item: item.clone(),
+                amount: *amount,
+            });
+        }
+        match (wear, source) {
+            (Wear::Worn(durability), Some(item)) => {
+                events.push(BattleEvent::ItemWorn {
+                    who,
+                    item,
+                    durability,
+                })
+            }
+            (Wear::Destroyed, Some(item)) => {
+                events.push(BattleEvent::ItemDestroyed { who, item })
+            }
+            _ => {}
+        }
+        for (item, amount) in sorted(&ability.make_items) {
+            let lost = *dropped.get(item).unwrap_or(&0);
+            if *amount > lost {
+                events.push(BattleEvent::ItemMade {
+                    who,
+                    item: item.clone(),
+                    amount: amount - lost,
+                });
+            }
+            if lost > 0 {
+                events.push(BattleEvent::ItemDropped {
+                    who,
+                    item: item.clone(),
+                    amount: lost,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// apply everything an ability does once it goes off, apart from its
+    /// items. Whatever the ability does to its user happens once, and
+    /// everything else happens to each of `targets` in turn.
+    fn resolve(
+        &mut self,
+        actor: CombatantId,
+        targets: &[CombatantId],
+        ability: &Ability,
+        events: &mut Vec<BattleEvent>,
+    ) -> Result<()> {
+        self.run_effect("heal", ability.health, actor, actor, (false, 0.0), events)?;
+        for (status, stacks) in sorted(&ability.gain) {
+            self.apply_status(actor, actor, status, *stacks, events)?;
+        }
+        let c = &mut self.combatants[actor];
+        for (dmg, proportion) in sorted(&ability.block) {
+            c.blocking.insert(dmg.clone(), *proportion);
+            events.push(BattleEvent::Block {
+                who: actor,
+                dmg: dmg.clone(),
+                proportion: *proportion,
+            });
+        }
+        for (dmg, proportion) in sorted(&ability.counter) {
+            c.countering.insert(dmg.clone(), *proportion);
+            events.push(BattleEvent::CounterReady {
+                who: actor,
+                dmg: dmg.clone(),
+                proportion: *proportion,
+            });
+        }
+        for target in targets {
+            if self.combatants[actor].is_dead() {
+                break;
+            }
+            self.hit(actor, *target, ability, events)?;
+        }
+        Ok(())
+    }
+
+    /// apply the part of an ability that affects its target.
+    fn hit(
+        &mut self,
+        actor: CombatantId,
+        target: CombatantId,
+        ability: &Ability,
+        events: &mut Vec<BattleEvent>,
+    ) -> Result<()> {
+        // abilities that don't do anything to the target can't miss
+        let attacks = !ability.damage.is_empty()
+            || ability.stun > 0
+            || !ability.inflict.is_empty()
+            || !ability.effects.is_empty();
+        let (mut landed, mut dealt) = (false, 0.0);
+        if attacks {
+            'hits: for _ in 0..=ability.repeat {
+                if self.combatants[target].is_dead() || self.combatants[actor].is_dead() {
+                    break;
+                }
+                let multiplier = match self.roll_hit(actor, target, ability, events) {
+                    Some(multiplier) => multiplier,
+                    None => continue,
+                };
+                landed = true;
+                for (dmg, amount) in sorted(&ability.damage) {
+                    if self.combatants[target].is_dead() || self.combatants[actor].is_dead() {
+                        break 'hits;
+                    }
+                    dealt += self.deal(actor, target, dmg, *amount * multiplier, true, events)?;
+                }
+            }
+        }
+        if landed && !self.combatants[target].is_dead() {
+            for (status, stacks) in sorted(&ability.inflict) {
+                self.apply_status(actor, target, status, *stacks, events)?;
+            }
+        }
+        self.run_effect("stun", ability.stun as f64, actor, target, (landed, dealt), events)?;
+
+        for (effect, magnitude) in sorted(&ability.effects) {
+            events.push(BattleEvent::Effect {
+                user: actor,
+                target,
+                effect: effect.clone(),
+                magnitude: *magnitude,
+            });
+            self.run_effect(effect, *magnitude, actor, target, (landed, dealt), events)?;
+        }
+        Ok(())
+    }
+
+    /// apply one of the gamemode's effects. `(landed, dealt)` is whether the
+    /// ability hit, and how much damage it did to the target. Effects with a
+    /// magnitude of 0 don't do anything.
+    fn run_effect(
+        &mut self,
+        name: &str,
+        magnitude: f64,
+        user: CombatantId,
+        target: CombatantId,
+        (landed, dealt): (bool, f64),
+        events: &mut Vec<BattleEvent>,
+    ) -> Result<()> {
+        if magnitude == 0.0 {
+            return Ok(());
+        }
+        let effect = self.g.effects.get(name)?;
+        effect.apply(
+            magnitude,
+            &mut EffectContext {
+                user,
+                target,
+                landed,
+                dealt,
+                g: self.g,
+                rng: &mut self.rng,
+                combatants: &mut self.combatants,
+                events,
+            },
+        )
+    }
+
+    /// roll whether one hit of an ability lands, and whether it's a critical
+    /// hit. The chance to hit is the attacker's accuracy against the
+    /// defender's evasion. Returns the damage multiplier if it hits.
+    fn roll_hit(
+        &mut self,
+        source: CombatantId,
+        target: CombatantId,
+        ability: &Ability,
+        events: &mut Vec<BattleEvent>,
+    ) -> Option<f64> {
+        let (s, t) = (&self.combatants[source], &self.combatants[target]);
+        let hit_chance = damage::hit_chance(s, t, ability);
+        let (crit_chance, crit_multiplier) = damage::crit(s, ability);
+
+        // always roll both, so that one roll doesn't shift every later one
+        let (hit_roll, crit_roll): (f64, f64) = (self.rng.gen(), self.rng.gen());
+        if hit_roll >= hit_chance {
+            events.push(BattleEvent::Miss { source, target });
+            None
+        } else if crit_roll < crit_chance {
+            events.push(BattleEvent::Critical {
+                source,
+                target,
+                multiplier: crit_multiplier,
+            });
+            Some(crit_multiplier)
+        } else {
+            Some(1.0)
+        }
+    }
+
+    /// deal one hit of damage. The hit may be split into several damage types
+    /// by the gamemode's conversion rules, and each part is dealt separately.
+    /// If `on_hit` is set, the target's on_hit statuses go off for each part
+    /// that gets through. Returns the total damage dealt.
+    fn deal(
+        &mut self,
+        source: CombatantId,
+        target: CombatantId,
+        dmg: &DmgType,
+        amount: f64,
+        on_hit: bool,
+        events: &mut Vec<BattleEvent>,
+    ) -> Result<f64> {
+        let mut total = 0.0;
+        for (dmg, amount) in damage::convert(&self.g.dmg_interactions, dmg, amount) {
+            let dealt = damage::deal_part(
+                &self.g.dmg_interactions,
+                &mut self.combatants,
+                source,
+                target,
+                &dmg,
+                amount,
+                events,
+            );
+            total += dealt;
+            if on_hit && dealt > 0.0 {
+                for status in self.combatants[target].statuses.clone() {
+                    let trigger = &self.status(&status.name)?.on_hit;
+                    if !trigger.is_empty() {
+                        self.trigger(target, &status, trigger, Some(source), events)?;
+                    }
+                }
+            }
+        }
+        Ok(total)
+    }
+
+    fn change_health(&mut self, who: CombatantId, amount: f64, events: &mut Vec<BattleEvent>) {
+        let amount = self.combatants[who].change_health(amount);
+        if amount != 0.0 {
+            events.push(BattleEvent::Health { who, amount });
+        }
+    }
+
+    fn change_energy(&mut self, who: CombatantId, amount: f64, events: &mut Vec<BattleEvent>) {
+        let amount = self.combatants[who].change_energy(amount);
+        if amount != 0.0 {
+            events.push(BattleEvent::Energy { who, amount });
+        }
+    }
+
+    fn status(&self, name: &StatusName) -> Result<&'a Status> {
+        self.g
+            .statuses
+            .get(name)
+            .ok_or_else(|| anyhow!(format!("{:?} is not a status", name)))
+    }
+
+    /// give `who` some stacks of a status, following the status's stacking
+    /// rules if they already have it.
+    fn apply_status(
+        &mut self,
+        source: CombatantId,
+        who: CombatantId,
+        name: &StatusName,
+        stacks: u64,
+        events: &mut Vec<BattleEvent>,
+    ) -> Result<()> {
+        let status = self.status(name)?;
+        let statuses = &mut self.combatants[who].statuses;
+        let active = match statuses.iter_mut().find(|s| &s.name == name) {
+            Some(active) => {
+                match status.stacking {
+                    Stacking::Stack => {
+                        active.stacks = (active.stacks + stacks).min(status.max_stacks);
+                        active.turns_left = status.duration;
+                    }
+                    Stacking::Refresh => active.turns_left = status.duration,
+                    Stacking::Extend => active.turns_left += status.duration,
+                    Stacking::Ignore => return Ok(()),
+                }
+                active.source = source;
+                active.clone()
+            }
+            None => {
+                let active = ActiveStatus {
+                    name: name.clone(),
+                    stacks: stacks.clamp(1, status.max_stacks),
+                    turns_left: status.duration,
+                    source,
+                };
+                statuses.push(active.clone());
+                active
+            }
+        };
+        events.push(BattleEvent::StatusApplied {
+            who,
+            status: active.name,
+            stacks: active.stacks,
+            turns_left: active.turns_left,
+        });
+        Ok(())
+    }
+
+    /// set off one of the triggers of a status `who` has. `attacker` is whoever
+    /// just hit them, for on_hit triggers.
+    fn trigger(
+        &mut self,
+        who: CombatantId,
+        status: &ActiveStatus,
+        trigger: &StatusTrigger,
+        attacker: Option<CombatantId>,
+        events: &mut Vec<BattleEvent>,
+    ) -> Result<()> {
+        let stacks = status.stacks as f64;
+        events.push(BattleEvent::StatusTriggered {
+            who,
+            status: status.name.clone(),
+        });
+        self.change_health(who, trigger.health * stacks, events);
+        self.change_energy(who, trigger.energy * stacks, events);
+        for (dmg, amount) in sorted(&trigger.damage) {
+            self.deal(status.source, who, dmg, amount * stacks, false, events)?;
+        }
+        if let Some(attacker) = attacker {
+            for (dmg, amount) in sorted(&trigger.retaliate) {
+                self.deal(who, attacker, dmg, amount * stacks, false, events)?;
+            }
+        }
+        for name in &trigger.apply {
+            self.apply_status(status.source, who, name, 1, events)?;
+        }
+        Ok(())
+    }
+
+    /// at the start of `who`'s turn, set off their per turn statuses, and
+    /// expire any that have run out.
+    fn tick_statuses(&mut self, who: CombatantId, events: &mut Vec<BattleEvent>) -> Result<()> {
+        for status in self.combatants[who].statuses.clone() {
+            if self.combatants[who].is_dead() {
+                break;
+            }
+            let def = self.status(&status.name)?;
+            if !def.per_turn.is_empty() {
+                self.trigger(who, &status, &def.per_turn, None, events)?;
+            }
+            let statuses = &mut self.combatants[who].statuses;
+            let i = match statuses.iter().position(|s| s.name == status.name) {
+                Some(i) => i,
+                None => continue,
+            };
+            statuses[i].turns_left -= 1;
+            if statuses[i].turns_left == 0 {
+                let expired = statuses.remove(i);
+                events.push(BattleEvent::StatusExpired {
+                    who,
+                    status: expired.name.clone(),
+                });
+                if !def.on_expire.is_empty() {
+                    self.trigger(who, &expired, &def.on_expire, None, events)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// at the start of `who`'s turn, count down their buffs from consumed
+    /// items, and take away any buffs (anyone's) that have run out of time.
+    fn tick_buffs(&mut self, who: CombatantId, events: &mut Vec<BattleEvent>) {
+        let now = self.clock;
+        for id in 0..self.combatants.len() {
+            let c = &mut self.combatants[id];
+            let mut expired = c.consumed.expire(now);
+            if id == who {
+                expired.extend(c.consumed.tick());
+            }
+            if expired.is_empty() {
+                continue;
+            }
+            // losing a max health or energy buff can leave them over the max
+            c.health = c.health.min(c.max_health());
+            c.energy = c.energy.min(c.max_energy());
+            c.reset_limits(self.g);
+            for buff in expired {
+                events.push(BattleEvent::BuffExpired { who: id, item: buff.item });
+            }
+        }
+    }
+
+    /// at the start of `who`'s turn, regain health and energy at the rates
+    /// given by their "health_regen" and "energy_regen" stats.
+    fn regenerate(&mut self, who: CombatantId, events: &mut Vec<BattleEvent>) {
+        if !self.combatants[who].is_dead() {
+            let c = &self.combatants[who];
+            let (health, energy) = (c.stat("health_regen"), c.stat("energy_regen"));
+            self.change_health(who, health, events);
+            self.change_energy(who, energy, events);
+        }
+    }
+
+    /// at the end of `who`'s turn, count down their cooldowns, and forget
+    /// about any that are finished.
+    fn tick_cooldowns(&mut self, who: CombatantId) {
+        let now = self.clock;
+        let cooldowns = &mut self.combatants[who].cooldowns;
+        for cooldown in cooldowns.values_mut() {
+            cooldown.turns_left = cooldown.turns_left.saturating_sub(1);
+        }
+        cooldowns.retain(|_, c| c.turns_left > 0 || c.ready_at > now);
+    }
+
+    /// take anyone who's been defeated out of the fight, and end the battle
+    /// if there's only one team left.
+    fn check_defeated(&mut self, events: &mut Vec<BattleEvent>) {
+        for (id, c) in self.combatants.iter_mut().enumerate() {
+            if c.is_dead() && !c.defeated && !c.fled {
+                c.defeated = true;
+                events.push(BattleEvent::Defeated { who: id });
+            }
+        }
+        let mut teams: Vec<Team> = self
+            .combatants
+            .iter()
+            .filter(|c| c.in_fight())
+            .map(|c| c.team)
+            .collect();
+        teams.sort();
+        teams.dedup();
+        match teams.as_slice() {
+            [] => self.outcome = Some(Outcome::Draw),
+            [winner] => self.outcome = Some(Outcome::Victory(*winner)),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        battle::effect::{Effect, EffectContext, EffectRegistry},
+        gamedata::{
+            carry::Overflow,
+            consumable::TimedBuff,
+            gamedata::{pvp, pvp_with, StatType},
+            item::{AbilityDeser, Buffs},
+        },
+        inventory::Inventory,
+        world::World,
+    };
+    use serde_jacl::de::from_str;
+
+    fn ability(g: &GameData, jacl: &str) -> Ability {
+        let deser: AbilityDeser = from_str(jacl).unwrap();
+        deser
+            .into_ability(
+                &g.dmg,
+                &g.items.keys().cloned().collect(),
+                &g.statuses.keys().cloned().collect(),
+            )
+            .unwrap()
+    }
+
+    /// someone who never misses or crits, with just the given abilities.
+    fn fighter(g: &GameData, name: &str, speed: f64, abilities: &[(&str, &str)]) -> Combatant {
+        let mut stats = HashMap::new();
+        stats.insert(StatType::from("speed".to_string()), speed);
+        stats.insert(StatType::from("evasion".to_string()), 0.0);
+        stats.insert(StatType::from("crit_chance".to_string()), 0.0);
+        let mut combatant =
+            Combatant::new(name.to_string(), g, &stats, Buffs::new(), Inventory::new());
+        for (name, jacl) in abilities {
+            combatant.abilities.insert(
+                name.to_string(),
+                BattleAbility {
+                    source: None,
+                    ability: ability(g, jacl),
+                },
+            );
+        }
+        combatant
+    }
+
+    /// pass everyone else's turns until it's `who`'s.
+    fn skip_to(battle: &mut Battle, who: CombatantId) {
+        while battle.current() != who {
+            battle.turn(None).unwrap();
+        }
+    }
+
+    #[test]
+    fn fights_until_one_team_is_left() {
+        let g = pvp();
+        let attacker = fighter(
+            &g,
+            "attacker",
+            10.0,
+            &[("punch", r#"damage : { "physical" : 5 }"#)],
+        );
+        let target = fighter(&g, "target", 10.0, &[]);
+        let mut battle = Battle::new(&g, 0, vec![vec![attacker], vec![target]]).unwrap();
+        while battle.outcome().is_none() {
+            skip_to(&mut battle, 0);
+            battle.turn(Some(Choice::ability("punch"))).unwrap();
+        }
+        // 20 health, 5 damage a punch
+        assert_eq!(battle.outcome(), Some(Outcome::Victory(0)));
+        let punches = battle
+            .log()
+            .iter()
+            .filter(|e| matches!(e, BattleEvent::Damage { amount, .. } if *amount == 5.0))
+            .count();
+        assert_eq!(punches, 4);
+        assert!(battle.log().contains(&BattleEvent::Defeated { who: 1 }));
+        assert!(battle.combatant(1).unwrap().defeated);
+        assert!(battle.turn(None).is_err());
+    }
+
+    #[test]
+    fn invalid_choices_change_nothing() {
+        let g = pvp();
+        let attacker = fighter(
+            &g,
+            "attacker",
+            10.0,
+            &[("big_punch", r#"energy : -20 damage : { "physical" : 5 }"#)],
+        );
+        let target = fighter(&g, "target", 10.0, &[]);
+        let mut battle = Battle::new(&g, 0, vec![vec![attacker], vec![target]]).unwrap();
+        assert!(battle.turn(Some(Choice::ability("kick"))).is_err());
+        assert!(battle.turn(Some(Choice::ability("big_punch"))).is_err());
+        // they have an ability, it's just not one they can use
+        battle.turn(None).unwrap();
+        assert_eq!(battle.turns(), 1);
+        assert!(battle.log().contains(&BattleEvent::Pass { actor: 0 }));
+        assert_eq!(battle.combatant(1).unwrap().health, 20.0);
+    }
+
+    #[test]
+    fn charged_abilities_go_off_later() {
+        let g = pvp();
+        let attacker = fighter(
+            &g,
+            "attacker",
+            10.0,
+            &[("wind_up", r#"charge : 2 damage : { "physical" : 5 }"#)],
+        );
+        let target = fighter(&g, "target", 10.0, &[]);
+        let mut battle = Battle::new(&g, 0, vec![vec![attacker], vec![target]]).unwrap();
+        battle.turn(Some(Choice::ability("wind_up"))).unwrap();
+        skip_to(&mut battle, 0);
+        assert!(!battle.needs_choice());
+        battle.turn(None).unwrap();
+        assert_eq!(battle.combatant(1).unwrap().health, 20.0);
+        skip_to(&mut battle, 0);
+        let events = battle.turn(None).unwrap();
+        assert!(events.contains(&BattleEvent::Ability {
+            actor: 0,
+            targets: vec![1],
+            ability: "wind_up".to_string(),
+        }));
+        assert_eq!(battle.combatant(1).unwrap().health, 15.0);
+    }
+
+    #[test]
+    fn stuns_take_away_turns() {
+        let g = pvp();
+        let abilities = [("bonk", "stun : 2"), ("wait", r#"targets : "self""#)];
+        let attacker = fighter(&g, "attacker", 10.0, &abilities);
+        let target = fighter(&g, "target", 10.0, &abilities);
+        let mut battle = Battle::new(&g, 0, vec![vec![attacker], vec![target]]).unwrap();
+        battle.turn(Some(Choice::ability("bonk"))).unwrap();
+        for turns_left in &[1, 0] {
+            assert_eq!(battle.current(), 1);
+            assert!(!battle.needs_choice());
+            let events = battle.turn(None).unwrap();
+            assert!(events.contains(&BattleEvent::Stunned {
+                actor: 1,
+                turns_left: *turns_left,
+            }));
+            battle.turn(Some(Choice::ability("wait"))).unwrap();
+        }
+        assert_eq!(battle.current(), 1);
+        assert!(battle.needs_choice());
+    }
+
+    #[test]
+    fn the_block_underfoot_changes_damage() {
+        let g = pvp();
+        let mut world = World::filled(Vector3::new(5, 5, 1), "grass", &g);
+        world.set_block(Vector3::new(2, 2, 0), "snow", &g);
+        let arena = Arena::capture(&world, &g, Vector3::new(2, 2, 0), 2).unwrap();
+        let sear = [("sear", r#"damage : { "heat" : 2 }"#)];
+        let mut attacker = fighter(&g, "attacker", 10.0, &sear);
+        attacker.pos = Some(Vector3::new(1, 2, 0));
+        let mut on_snow = fighter(&g, "on_snow", 10.0, &[]);
+        on_snow.pos = Some(Vector3::new(2, 2, 0));
+        let mut on_grass = fighter(&g, "on_grass", 10.0, &[]);
+        on_grass.pos = Some(Vector3::new(1, 1, 0));
+        let mut battle =
+            Battle::in_arena(&g, 0, vec![vec![attacker], vec![on_snow, on_grass]], arena).unwrap();
+        // snow is frozen, and heat does 1.5 times the damage to frozen things
+        battle.turn(Some(Choice::ability_on("sear", 1))).unwrap();
+        assert_eq!(battle.combatant(1).unwrap().health, 17.0);
+        skip_to(&mut battle, 0);
+        battle.turn(Some(Choice::ability_on("sear", 2))).unwrap();
+        assert_eq!(battle.combatant(2).unwrap().health, 18.0);
+
+        // whoever joins on the snow is frozen too
+        let mut joiner = fighter(&g, "joiner", 10.0, &[]);
+        joiner.pos = Some(Vector3::new(2, 2, 0));
+        let joined = battle.join(1, joiner).unwrap();
+        skip_to(&mut battle, 0);
+        battle
+            .turn(Some(Choice::ability_on("sear", joined)))
+            .unwrap();
+        assert_eq!(battle.combatant(joined).unwrap().health, 17.0);
+    }
+
+    #[test]
+    fn statuses_stack_the_way_they_say() {
+        let g = pvp();
+        let attacker = fighter(
+            &g,
+            "attacker",
+            20.0,
+            &[(
+                "sear",
+                r#"inflict : { "poison" : 3 "burn" : 1 "scorched" : 1 }"#,
+            )],
+        );
+        let target = fighter(&g, "target", 1.0, &[]);
+        let mut battle = Battle::new(&g, 0, vec![vec![attacker], vec![target]]).unwrap();
+        for _ in 0..2 {
+            // the target is too slow to get a turn in between
+            assert_eq!(battle.current(), 0);
+            battle.turn(Some(Choice::ability("sear"))).unwrap();
+        }
+        let statuses = &battle.combatant(1).unwrap().statuses;
+        let status = |name: &str| {
+            statuses
+                .iter()
+                .find(|s| s.name.as_str() == name)
+                .map(|s| (s.stacks, s.turns_left))
+                .unwrap()
+        };
+        // poison stacks up to 5 and starts over, burn lasts longer, and
+        // scorched can't be put on again while it's there
+        assert_eq!(status("poison"), (5, 4));
+        assert_eq!(status("burn"), (1, 4));
+        assert_eq!(status("scorched"), (1, 2));
+        let scorched = battle
+            .log()
+            .iter()
+            .filter(|e| matches!(e, BattleEvent::StatusApplied { status, .. } if status.as_str() == "scorched"))
+            .count();
+        assert_eq!(scorched, 1);
+    }
+
+    /// hurts the user for part of the damage they dealt.
+    struct Recoil;
+
+    impl Effect for Recoil {
+        fn name(&self) -> &str {
+            "recoil"
+        }
+
+        fn validate(&self, magnitude: f64, _: &GameData) -> Result<()> {
+            if magnitude >= 0.0 {
+                Ok(())
+            } else {
+                Err(anyhow!("recoil can't be negative"))
+            }
+        }
+
+        fn apply(&self, magnitude: f64, ctx: &mut EffectContext) -> Result<()> {
+            ctx.change_health(ctx.user, -magnitude * ctx.dealt);
+            Ok(())
+        }
+
+        fn describe(&self, magnitude: f64) -> String {
+            format!("hurts the user for {} of the damage dealt", magnitude)
+        }
+    }
+
+    #[test]
+    fn custom_effects_go_off() {
+        let mut registry = EffectRegistry::new();
+        registry.register(Recoil).unwrap();
+        let g = pvp_with(registry).unwrap();
+        let smash = [(
+            "smash",
+            r#"damage : { "physical" : 4 } effects : { "recoil" : 0.5 }"#,
+        )];
+        let attacker = fighter(&g, "attacker", 10.0, &smash);
+        let target = fighter(&g, "target", 1.0, &[]);
+        let mut battle = Battle::new(&g, 0, vec![vec![attacker], vec![target]]).unwrap();
+        battle.turn(Some(Choice::ability("smash"))).unwrap();
+        assert_eq!(battle.combatant(1).unwrap().health, 16.0);
+        assert_eq!(battle.combatant(0).unwrap().health, 18.0);
+    }
+
+    #[test]
+    fn arenas_limit_range_and_spread_areas() {
+        let g = pvp();
+        let mut world = World::filled(Vector3::new(9, 9, 1), "grass", &g);
+        world.set_block(Vector3::new(4, 2, 0), "rock", &g);
+        let arena = Arena::capture(&world, &g, Vector3::new(4, 4, 0), 4).unwrap();
+        let mut attacker = fighter(
+            &g,
+            "attacker",
+            20.0,
+            &[
+                (
+                    "burst",
+                    r#"damage : { "physical" : 2 } range : 3 line_of_sight : false area : ( shape : "radius" size : 1 )"#,
+                ),
+                ("bolt", r#"damage : { "physical" : 1 } range : 3"#),
+            ],
+        );
+        attacker.pos = Some(Vector3::new(4, 4, 0));
+        let mut enemies = vec![];
+        for (x, y) in [(6, 4), (6, 5), (4, 1), (8, 8)] {
+            let mut enemy = fighter(&g, "enemy", 1.0, &[]);
+            enemy.pos = Some(Vector3::new(x, y, 0));
+            enemies.push(enemy);
+        }
+        let mut battle = Battle::in_arena(&g, 0, vec![vec![attacker], enemies], arena).unwrap();
+        let burst = battle.combatant(0).unwrap().abilities["burst"]
+            .ability
+            .clone();
+        let bolt = battle.combatant(0).unwrap().abilities["bolt"]
+            .ability
+            .clone();
+        // the last enemy is out of range, and the rock is in the way of the
+        // third, which only matters to abilities that need line of sight
+        assert_eq!(battle.candidates(0, &burst), vec![1, 2, 3]);
+        assert_eq!(battle.candidates(0, &bolt), vec![1, 2]);
+        assert_eq!(battle.targets(0, "burst", Some(1)).unwrap(), vec![1, 2]);
+        assert!(battle.turn(Some(Choice::ability_on("bolt", 3))).is_err());
+        assert!(battle.turn(Some(Choice::ability_on("burst", 4))).is_err());
+        battle.turn(Some(Choice::ability_on("burst", 1))).unwrap();
+        let health: Vec<f64> = battle.combatants().iter().map(|c| c.health).collect();
+        assert_eq!(health, vec![20.0, 18.0, 18.0, 20.0, 20.0]);
+    }
+
+    #[test]
+    fn cooldowns_count_the_users_turns() {
+        let g = pvp();
+        let attacker = fighter(
+            &g,
+            "attacker",
+            10.0,
+            &[
+                ("jab", r#"cooldown : 1 damage : { "physical" : 1 }"#),
+                ("wait", r#"targets : "self""#),
+            ],
+        );
+        let target = fighter(&g, "target", 10.0, &[]);
+        let mut battle = Battle::new(&g, 0, vec![vec![attacker], vec![target]]).unwrap();
+        skip_to(&mut battle, 0);
+        battle.turn(Some(Choice::ability("jab"))).unwrap();
+        skip_to(&mut battle, 0);
+        assert!(battle.check_ability(0, "jab").is_err());
+        assert_eq!(battle.usable_abilities(0), vec!["wait".to_string()]);
+        assert!(battle.turn(Some(Choice::ability("jab"))).is_err());
+        battle.turn(Some(Choice::ability("wait"))).unwrap();
+        skip_to(&mut battle, 0);
+        battle.check_ability(0, "jab").unwrap();
+        battle.turn(Some(Choice::ability("jab"))).unwrap();
+    }
+
+    #[test]
+    fn clock_cooldowns_and_energy() {
+        let g = pvp();
+        let attacker = fighter(
+            &g,
+            "attacker",
+            10.0,
+            &[
+                (
+                    "blast",
+                    r#"cooldown_secs : 10 energy : -6 damage : { "physical" : 1 }"#,
+                ),
+                ("wait", r#"targets : "self""#),
+            ],
+        );
+        let target = fighter(&g, "target", 10.0, &[]);
+        let mut battle = Battle::new(&g, 0, vec![vec![attacker], vec![target]]).unwrap();
+        skip_to(&mut battle, 0);
+        battle.turn(Some(Choice::ability("blast"))).unwrap();
+        assert_eq!(battle.combatant(0).unwrap().energy, 4.0);
+        skip_to(&mut battle, 0);
+        // still cooling down, however many turns go by
+        assert!(battle.check_ability(0, "blast").is_err());
+        battle.set_clock(10.0).unwrap();
+        // energy comes back one a turn, so there's still not enough
+        assert!(battle.check_ability(0, "blast").is_err());
+        while battle.combatant(0).unwrap().energy < 6.0 {
+            battle.turn(Some(Choice::ability("wait"))).unwrap();
+            skip_to(&mut battle, 0);
+        }
+        battle.check_ability(0, "blast").unwrap();
+    }
+
+    #[test]
+    fn resting_ends_cooldowns_and_regenerates() {
+        let g = pvp();
+        let mut c = fighter(
+            &g,
+            "c",
+            10.0,
+            &[("jab", r#"cooldown : 3 damage : { "physical" : 1 }"#)],
+        );
+        c.cooldowns.insert(
+            "jab".to_string(),
+            Cooldown {
+                turns_left: 3,
+                ready_at: 0.0,
+            },
+        );
+        c.health = 10.0;
+        c.energy = 0.0;
+        // 0.1 health and 0.5 energy a second
+        assert_eq!(c.rest(10.0), (1.0, 5.0));
+        assert_eq!(c.cooldowns["jab"].turns_left, 0);
+        assert_eq!(c.rest(100.0), (9.0, 5.0));
+        c.health = 0.0;
+        assert_eq!(c.rest(10.0), (0.0, 0.0));
+    }
+
+    #[test]
+    fn fleeing_leaves_the_battle() {
+        let g = pvp();
+        for seed in 0..100 {
+            let runner = fighter(&g, "runner", 10.0, &[]);
+            let chaser = fighter(&g, "chaser", 10.0, &[]);
+            let mut battle = Battle::new(&g, seed, vec![vec![runner], vec![chaser]]).unwrap();
+            let events = battle.turn(Some(Choice::Flee)).unwrap();
+            assert!(events.contains(&BattleEvent::FleeAttempt {
+                who: 0,
+                chance: g.flee.chance(10.0, 10.0),
+            }));
+            if events.contains(&BattleEvent::FleeFailed { who: 0 }) {
+                assert_eq!(battle.outcome(), None);
+                continue;
+            }
+            assert!(events.contains(&BattleEvent::Fled { who: 0, to: None }));
+            assert!(battle.combatant(0).unwrap().fled);
+            assert_eq!(battle.outcome(), Some(Outcome::Victory(1)));
+            return;
+        }
+        panic!("never got away");
+    }
+
+    #[test]
+    fn flee_chances_are_clamped() {
+        let g = pvp();
+        let runner = fighter(&g, "runner", 1.0, &[]);
+        let chaser = fighter(&g, "chaser", 100.0, &[]);
+        let mut battle = Battle::new(&g, 0, vec![vec![runner], vec![chaser]]).unwrap();
+        skip_to(&mut battle, 0);
+        let events = battle.turn(Some(Choice::Flee)).unwrap();
+        assert!(events.contains(&BattleEvent::FleeAttempt {
+            who: 0,
+            chance: g.flee.min_chance,
+        }));
+    }
+
+    #[test]
+    fn escapes_go_somewhere_free() {
+        let g = pvp();
+        // a little room, with the runner in one corner and the chaser in
+        // another
+        let mut world = World::filled(Vector3::new(9, 9, 1), "rock", &g);
+        for (x, y) in [(3, 3), (4, 3), (5, 3), (3, 4), (4, 4)] {
+            world.set_block(Vector3::new(x, y, 0), "grass", &g);
+        }
+        let mut got_away = 0;
+        for seed in 0..50 {
+            let arena = Arena::capture(&world, &g, Vector3::new(4, 4, 0), 4).unwrap();
+            let mut runner = fighter(&g, "runner", 100.0, &[]);
+            runner.pos = Some(Vector3::new(3, 3, 0));
+            let mut chaser = fighter(&g, "chaser", 1.0, &[]);
+            chaser.pos = Some(Vector3::new(4, 4, 0));
+            let mut battle =
+                Battle::in_arena(&g, seed, vec![vec![runner], vec![chaser]], arena).unwrap();
+            battle.turn(Some(Choice::Flee)).unwrap();
+            let runner = battle.combatant(0).unwrap();
+            if runner.fled {
+                got_away += 1;
+                let to = runner.pos.unwrap();
+                assert!(!battle.arena().unwrap().is_solid(to));
+                assert!(to != Vector3::new(3, 3, 0) && to != Vector3::new(4, 4, 0));
+            } else {
+                assert_eq!(runner.pos, Some(Vector3::new(3, 3, 0)));
+            }
+        }
+        assert!(got_away > 0);
+    }
+
+    #[test]
+    fn nowhere_to_run() {
+        let g = pvp();
+        let mut world = World::filled(Vector3::new(5, 5, 1), "rock", &g);
+        world.set_block(Vector3::new(2, 2, 0), "grass", &g);
+        world.set_block(Vector3::new(2, 3, 0), "grass", &g);
+        let arena = Arena::capture(&world, &g, Vector3::new(2, 2, 0), 2).unwrap();
+        let mut runner = fighter(&g, "runner", 100.0, &[]);
+        runner.pos = Some(Vector3::new(2, 2, 0));
+        let mut chaser = fighter(&g, "chaser", 1.0, &[]);
+        chaser.pos = Some(Vector3::new(2, 3, 0));
+        let mut battle = Battle::in_arena(&g, 0, vec![vec![runner], vec![chaser]], arena).unwrap();
+        let events = battle.turn(Some(Choice::Flee)).unwrap();
+        assert!(events.contains(&BattleEvent::FleeAttempt {
+            who: 0,
+            chance: 0.0
+        }));
+        assert!(events.contains(&BattleEvent::FleeFailed { who: 0 }));
+        // failing still costs the turn
+        assert_eq!(battle.turns(), 1);
+        assert!(battle.combatant(0).unwrap().in_fight());
+    }
+
+    #[test]
+    fn charged_abilities_fizzle_if_what_they_make_no_longer_fits() {
+        let mut g = pvp();
+        g.carry.overflow = Overflow::Reject;
+        // workbenches weigh 40, so three only fit while the carry capacity of
+        // 100 is doubled
+        let forge = [(
+            "forge",
+            r#"targets : "self" charge : 2 make_items : { "workbench" : 3 }"#,
+        )];
+        let mut crafter = fighter(&g, "crafter", 10.0, &forge);
+        let mut buffs = Buffs::new();
+        buffs
+            .stat_buffs
+            .insert(StatType::from("carry_capacity".to_string()), 2.0);
+        let strength = TimedBuff {
+            buffs,
+            turns: Some(2),
+            secs: None,
+            stacking: Stacking::Refresh,
+            max_stacks: 1,
+        };
+        crafter
+            .consumed
+            .add(&ItemName::from("orange".to_string()), &strength, 0.0);
+        crafter.reset_limits(&g);
+        let other = fighter(&g, "other", 10.0, &[]);
+        let mut battle = Battle::new(&g, 0, vec![vec![crafter], vec![other]]).unwrap();
+        skip_to(&mut battle, 0);
+        battle.turn(Some(Choice::ability("forge"))).unwrap();
+        skip_to(&mut battle, 0);
+        let events = battle.turn(None).unwrap();
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, BattleEvent::BuffExpired { who: 0, .. })));
+        skip_to(&mut battle, 0);
+        let events = battle.turn(None).unwrap();
+        assert!(events.contains(&BattleEvent::Fizzled {
+            actor: 0,
+            ability: "forge".to_string(),
+            reason: "no room for 1 more workbench".to_string(),
+        }));
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, BattleEvent::Ability { .. })));
+        let crafter = battle.combatant(0).unwrap();
+        assert_eq!(
+            crafter
+                .inventory
+                .get(&ItemName::from("workbench".to_string())),
+            0
+        );
+        assert!(crafter.charging.is_none());
+        // the battle carries on, and the ability can't be started again now
+        // that it doesn't fit
+        skip_to(&mut battle, 0);
+        assert!(battle.check_ability(0, "forge").is_err());
+        assert_eq!(battle.outcome(), None);
+    }
+}
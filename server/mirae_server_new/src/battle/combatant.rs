@@ -0,0 +1,295 @@
+use crate::{
+    active_buffs::ActiveBuffs,
+    gamedata::{
+        block::Block,
+        gamedata::{Alignment, DmgType, GameData, ItemName, MobName, StatType, StatusName},
+        item::{Ability, Buffs, Item},
+        mobtemplate::MobTemplate,
+        set::{ItemSet, SetBonus},
+    },
+    inventory::Inventory,
+    vector3::Vector3,
+};
+use anyhow::Result;
+use rand::prelude::StdRng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// index of a combatant within a battle.
+pub type CombatantId = usize;
+
+/// index of a side within a battle. Combatants on the same team are allies.
+pub type Team = usize;
+
+/// an ability a combatant can use, along with the item that gives it (if any).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BattleAbility {
+    pub source: Option<ItemName>,
+    pub ability: Ability,
+}
+
+/// an ability that is charging up, and will go off in `turns_left` turns.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Charging {
+    pub ability: String,
+    /// who was picked to be hit, for abilities that hit one combatant.
+    pub target: Option<CombatantId>,
+    pub turns_left: u64,
+}
+
+/// how long until a combatant can use one of their abilities again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cooldown {
+    /// how many more of the combatant's turns they have to wait.
+    pub turns_left: u64,
+    /// the battle clock time (in seconds) they have to wait until.
+    pub ready_at: f64,
+}
+
+/// a status a combatant currently has.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveStatus {
+    pub name: StatusName,
+    pub stacks: u64,
+    pub turns_left: u64,
+    /// who applied the status, and is credited with any damage it does.
+    pub source: CombatantId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Combatant {
+    pub name: String,
+    pub mob: Option<MobName>,
+    pub team: Team,
+    /// where the combatant is in the world, for battles fought in an arena.
+    pub pos: Option<Vector3>,
+    /// whether the combatant has run away from the battle.
+    pub fled: bool,
+    /// whether the combatant's defeat has been dealt with by the battle.
+    pub defeated: bool,
+    pub health: f64,
+    pub energy: f64,
+    pub stats: HashMap<StatType, f64>,
+    /// buffs from what the combatant is wearing.
+    pub buffs: Buffs,
+    /// buffs from items the combatant has consumed, which run out.
+    #[serde(default = "ActiveBuffs::new")]
+    pub consumed: ActiveBuffs,
+    pub inventory: Inventory,
+    /// abilities by name. Abilities that come from an item are named
+    /// "item:ability", since different items can have abilities with the same
+    /// name.
+    pub abilities: HashMap<String, BattleAbility>,
+    pub stunned: u64,
+    pub charging: Option<Charging>,
+    pub blocking: HashMap<DmgType, f64>,
+    pub countering: HashMap<DmgType, f64>,
+    /// in the order they were applied.
+    pub statuses: Vec<ActiveStatus>,
+    /// abilities that can't be used again yet, by name.
+    pub cooldowns: HashMap<String, Cooldown>,
+    /// the combatant's own alignments, and the alignments of the block they're
+    /// fighting on.
+    pub own_alignments: Vec<Alignment>,
+    pub terrain_alignments: Vec<Alignment>,
+}
+
+impl Combatant {
+    /// a combatant with full health and energy. Any stat not in `stats` takes
+    /// its default value from the gamemode.
+    pub fn new(
+        name: String,
+        g: &GameData,
+        stats: &HashMap<StatType, f64>,
+        buffs: Buffs,
+        inventory: Inventory,
+    ) -> Self {
+        let mut all_stats = g.stat_defaults.clone();
+        for (k, v) in stats {
+            all_stats.insert(k.clone(), *v);
+        }
+        let mut combatant = Combatant {
+            name,
+            mob: None,
+            team: 0,
+            pos: None,
+            fled: false,
+            defeated: false,
+            health: 0.0,
+            energy: 0.0,
+            stats: all_stats,
+            buffs,
+            consumed: ActiveBuffs::new(),
+            inventory,
+            abilities: HashMap::new(),
+            stunned: 0,
+            charging: None,
+            blocking: HashMap::new(),
+            countering: HashMap::new(),
+            statuses: vec![],
+            cooldowns: HashMap::new(),
+            own_alignments: vec![],
+            terrain_alignments: vec![],
+        };
+        combatant.health = combatant.max_health();
+        combatant.energy = combatant.max_energy();
+        combatant.reset_limits(g);
+        combatant
+    }
+
+    /// limit the combatant's inventory by their carry capacity after buffs.
+    /// Limits aren't saved, so this has to be done again whenever a combatant
+    /// is loaded.
+    pub fn reset_limits(&mut self, g: &GameData) {
+        let stats = self
+            .stats
+            .keys()
+            .map(|s| (s.clone(), self.stat(s.as_str())))
+            .collect();
+        self.inventory.set_limits(Some(g.carry.limits(&stats)));
+    }
+
+    /// a fresh combatant from a mob template, with its tools rolled.
+    pub fn from_mob(template: &MobTemplate, g: &GameData, rng: &mut StdRng) -> Result<Self> {
+        let mut combatant = Combatant::new(
+            template.name.as_str().to_string(),
+            g,
+            &template.stats,
+            Buffs::new(),
+            template.tools.build(g, rng)?,
+        );
+        combatant.mob = Some(template.name.clone());
+        combatant.own_alignments = template.alignments.clone();
+        for (name, ability) in &template.abilities {
+            combatant.abilities.insert(
+                name.clone(),
+                BattleAbility {
+                    source: None,
+                    ability: ability.clone(),
+                },
+            );
+        }
+        Ok(combatant)
+    }
+
+    /// give this combatant every ability of the item.
+    pub fn add_item_abilities(&mut self, item: &Item) {
+        for (name, ability) in &item.abilities {
+            self.abilities.insert(
+                format!("{}:{}", item.name.as_str(), name),
+                BattleAbility {
+                    source: Some(item.name.clone()),
+                    ability: ability.clone(),
+                },
+            );
+        }
+    }
+
+    /// give this combatant every ability of a set bonus.
+    pub fn add_set_abilities(&mut self, set: &ItemSet, bonus: &SetBonus) {
+        for (name, ability) in &bonus.abilities {
+            self.abilities.insert(
+                format!("{}:{}", set.name.as_str(), name),
+                BattleAbility {
+                    source: None,
+                    ability: ability.clone(),
+                },
+            );
+        }
+    }
+
+    /// fight on top of `block`, taking on its alignments.
+    pub fn stand_on(&mut self, block: &Block) {
+        self.terrain_alignments = block.alignments.clone();
+    }
+
+    /// every alignment that damage against this combatant is affected by.
+    pub fn alignments(&self) -> Vec<&Alignment> {
+        let mut alignments: Vec<&Alignment> = self
+            .own_alignments
+            .iter()
+            .chain(self.terrain_alignments.iter())
+            .collect();
+        alignments.sort();
+        alignments.dedup();
+        alignments
+    }
+
+    /// the value of a stat after buffs. Stats the gamemode doesn't have are 0.
+    pub fn stat(&self, stat: &str) -> f64 {
+        let stat = StatType::from(stat.to_string());
+        self.stats.get(&stat).unwrap_or(&0.0)
+            * self.buffs.stat(&stat)
+            * self.consumed.buffs().stat(&stat)
+    }
+
+    /// multiplier on outgoing damage of the given type, from everything the
+    /// combatant is wearing or has consumed.
+    pub fn attack(&self, dmg: &DmgType) -> f64 {
+        self.buffs.attack(dmg) * self.consumed.buffs().attack(dmg)
+    }
+
+    /// multiplier on incoming damage of the given type.
+    pub fn defense(&self, dmg: &DmgType) -> f64 {
+        self.buffs.defense(dmg) * self.consumed.buffs().defense(dmg)
+    }
+
+    pub fn max_health(&self) -> f64 {
+        self.stat("max_health")
+    }
+
+    pub fn max_energy(&self) -> f64 {
+        self.stat("max_energy")
+    }
+
+    /// change health by `amount`, without going over max health. Returns how
+    /// much it actually changed by.
+    pub fn change_health(&mut self, amount: f64) -> f64 {
+        let before = self.health;
+        self.health = (self.health + amount).min(self.max_health().max(before));
+        self.health - before
+    }
+
+    /// change energy by `amount`, without going over max energy. Returns how
+    /// much it actually changed by.
+    pub fn change_energy(&mut self, amount: f64) -> f64 {
+        let before = self.energy;
+        self.energy = (self.energy + amount).min(self.max_energy().max(before));
+        self.energy - before
+    }
+
+    /// how many turns and seconds are left before `ability` can be used again,
+    /// at clock time `now`.
+    pub fn cooldown_left(&self, ability: &str, now: f64) -> (u64, f64) {
+        match self.cooldowns.get(ability) {
+            Some(c) => (c.turns_left, (c.ready_at - now).max(0.0)),
+            None => (0, 0.0),
+        }
+    }
+
+    /// regenerate for `secs` seconds outside of battle, at the rates given by
+    /// the "rest_health_regen" and "rest_energy_regen" stats (per second).
+    /// Cooldowns counted in turns only last for the battle, so they're all
+    /// finished. Returns how much health and energy were regained.
+    pub fn rest(&mut self, secs: f64) -> (f64, f64) {
+        if self.is_dead() {
+            return (0.0, 0.0);
+        }
+        let secs = secs.max(0.0);
+        for cooldown in self.cooldowns.values_mut() {
+            cooldown.turns_left = 0;
+        }
+        let health = self.change_health(self.stat("rest_health_regen") * secs);
+        let energy = self.change_energy(self.stat("rest_energy_regen") * secs);
+        (health, energy)
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.health <= 0.0
+    }
+
+    /// whether the combatant can still act and be targeted.
+    pub fn in_fight(&self) -> bool {
+        !self.is_dead() && !self.defeated && !self.fled
+    }
+}
@@ -0,0 +1,282 @@
+use super::{
+    combatant::{Combatant, CombatantId},
+    event::BattleEvent,
+};
+use crate::gamedata::{dmg::DmgInteraction, gamedata::DmgType, item::Ability};
+use std::collections::HashMap;
+
+/// split a hit of damage up by the gamemode's conversion rules. Whatever part
+/// of the damage isn't converted stays as its original type. Converted damage
+/// isn't converted again.
+pub fn convert(
+    interactions: &HashMap<DmgType, DmgInteraction>,
+    dmg: &DmgType,
+    amount: f64,
+) -> Vec<(DmgType, f64)> {
+    let mut parts = vec![];
+    let mut left = amount;
+    if let Some(interaction) = interactions.get(dmg) {
+        let mut convert: Vec<_> = interaction.convert.iter().collect();
+        convert.sort_by(|a, b| a.0.cmp(b.0));
+        for (to, proportion) in convert {
+            parts.push((to.clone(), amount * proportion));
+            left -= amount * proportion;
+        }
+    }
+    if left > 0.0 {
+        parts.insert(0, (dmg.clone(), left));
+    }
+    parts
+}
+
+/// how much of one part of a hit actually lands on the defender, before
+/// blocking. Applies the attacker's attack buff, the defender's defense buff
+/// (minus however much of it the damage type penetrates), and the damage
+/// type's multiplier against each of the defender's alignments.
+pub fn resolve(
+    interactions: &HashMap<DmgType, DmgInteraction>,
+    attacker: &Combatant,
+    defender: &Combatant,
+    dmg: &DmgType,
+    amount: f64,
+) -> f64 {
+    let interaction = interactions.get(dmg);
+    let mut defense = defender.defense(dmg);
+    // penetration only ever cuts through resistance, it doesn't make
+    // weaknesses any worse.
+    if defense < 1.0 {
+        let penetration = interaction.map(|i| i.penetration).unwrap_or(0.0);
+        defense += (1.0 - defense) * penetration;
+    }
+    let against: f64 = match interaction {
+        Some(interaction) => defender
+            .alignments()
+            .iter()
+            .map(|a| interaction.against.get(a).unwrap_or(&1.0))
+            .product(),
+        None => 1.0,
+    };
+    amount * attacker.attack(dmg) * defense * against
+}
+
+/// deal one part of a hit, taking buffs, the damage type's interactions,
+/// blocks and counters into account. Returns how much got through.
+pub fn deal_part(
+    interactions: &HashMap<DmgType, DmgInteraction>,
+    combatants: &mut [Combatant],
+    source: CombatantId,
+    target: CombatantId,
+    dmg: &DmgType,
+    amount: f64,
+    events: &mut Vec<BattleEvent>,
+) -> f64 {
+    let raw = resolve(
+        interactions,
+        &combatants[source],
+        &combatants[target],
+        dmg,
+        amount,
+    );
+    let t = &mut combatants[target];
+    let blocked = raw * t.blocking.get(dmg).unwrap_or(&0.0).clamp(0.0, 1.0);
+    let dealt = raw - blocked;
+    let countered = dealt * t.countering.get(dmg).unwrap_or(&0.0).clamp(0.0, 1.0);
+    t.health -= dealt;
+    events.push(BattleEvent::Damage {
+        source,
+        target,
+        dmg: dmg.clone(),
+        amount: dealt,
+        blocked,
+    });
+    if countered > 0.0 {
+        combatants[source].health -= countered;
+        events.push(BattleEvent::Counter {
+            source: target,
+            target: source,
+            dmg: dmg.clone(),
+            amount: countered,
+        });
+    }
+    dealt
+}
+
+/// the chance that one hit of `ability` lands: the attacker's accuracy against
+/// the defender's evasion.
+pub fn hit_chance(attacker: &Combatant, defender: &Combatant, ability: &Ability) -> f64 {
+    let accuracy = (attacker.stat("accuracy") * ability.accuracy).max(0.0);
+    let evasion = defender.stat("evasion").max(0.0);
+    if accuracy + evasion > 0.0 {
+        accuracy / (accuracy + evasion)
+    } else {
+        1.0
+    }
+}
+
+/// the chance that a hit of `ability` is critical, and how much more damage it
+/// does if it is.
+pub fn crit(attacker: &Combatant, ability: &Ability) -> (f64, f64) {
+    (
+        (attacker.stat("crit_chance") + ability.crit_chance).clamp(0.0, 1.0),
+        (attacker.stat("crit_multiplier") + ability.crit_multiplier).max(1.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        gamedata::{
+            gamedata::{pvp, Alignment, GameData, StatType},
+            item::{Ability, AbilityDeser, Buffs},
+        },
+        inventory::Inventory,
+    };
+    use serde_jacl::de::from_str;
+
+    fn dmg(name: &str) -> DmgType {
+        DmgType::from(name.to_string())
+    }
+
+    fn fighter(g: &GameData, attack: &[(&str, f64)], defense: &[(&str, f64)]) -> Combatant {
+        let mut buffs = Buffs::new();
+        buffs.attack_buffs = attack.iter().map(|(d, v)| (dmg(d), *v)).collect();
+        buffs.defense_buffs = defense.iter().map(|(d, v)| (dmg(d), *v)).collect();
+        Combatant::new(
+            "test".to_string(),
+            g,
+            &HashMap::new(),
+            buffs,
+            Inventory::new(),
+        )
+    }
+
+    #[test]
+    fn converts_part_of_a_hit() {
+        let g = pvp();
+        assert_eq!(
+            convert(&g.dmg_interactions, &dmg("radioactive"), 4.0),
+            vec![(dmg("radioactive"), 3.0), (dmg("toxic"), 1.0)]
+        );
+        assert_eq!(
+            convert(&g.dmg_interactions, &dmg("physical"), 4.0),
+            vec![(dmg("physical"), 4.0)]
+        );
+    }
+
+    #[test]
+    fn applies_attack_and_defense_buffs() {
+        let g = pvp();
+        let plain = fighter(&g, &[], &[]);
+        let strong = fighter(&g, &[("physical", 1.2)], &[]);
+        let tough = fighter(&g, &[], &[("physical", 0.5)]);
+        let i = &g.dmg_interactions;
+        assert_eq!(resolve(i, &plain, &plain, &dmg("physical"), 5.0), 5.0);
+        assert_eq!(resolve(i, &strong, &plain, &dmg("physical"), 5.0), 6.0);
+        assert_eq!(resolve(i, &plain, &tough, &dmg("physical"), 5.0), 2.5);
+    }
+
+    #[test]
+    fn penetration_only_cuts_through_resistance() {
+        let g = pvp();
+        let plain = fighter(&g, &[], &[]);
+        let resistant = fighter(&g, &[], &[("magic", 0.5)]);
+        let weak = fighter(&g, &[], &[("magic", 2.0)]);
+        let i = &g.dmg_interactions;
+        // magic has a penetration of 0.5
+        assert_eq!(resolve(i, &plain, &resistant, &dmg("magic"), 4.0), 3.0);
+        assert_eq!(resolve(i, &plain, &weak, &dmg("magic"), 4.0), 8.0);
+    }
+
+    #[test]
+    fn multiplies_by_alignments() {
+        let g = pvp();
+        let plain = fighter(&g, &[], &[]);
+        let mut fish = fighter(&g, &[], &[]);
+        fish.own_alignments = vec![Alignment::from("aquatic".to_string())];
+        let i = &g.dmg_interactions;
+        assert_eq!(resolve(i, &plain, &fish, &dmg("electric"), 2.0), 3.0);
+        assert_eq!(resolve(i, &plain, &fish, &dmg("physical"), 2.0), 2.0);
+    }
+
+    #[test]
+    fn blocks_and_counters() {
+        let g = pvp();
+        let mut combatants = vec![fighter(&g, &[], &[]), fighter(&g, &[], &[])];
+        combatants[1].blocking.insert(dmg("physical"), 0.5);
+        combatants[1].countering.insert(dmg("physical"), 0.5);
+        let mut events = vec![];
+        let dealt = deal_part(
+            &g.dmg_interactions,
+            &mut combatants,
+            0,
+            1,
+            &dmg("physical"),
+            8.0,
+            &mut events,
+        );
+        assert_eq!(dealt, 4.0);
+        assert_eq!(combatants[1].health, 16.0);
+        assert_eq!(combatants[0].health, 18.0);
+        assert_eq!(events.len(), 2);
+    }
+
+    fn ability(g: &GameData, jacl: &str) -> Ability {
+        let deser: AbilityDeser = from_str(jacl).unwrap();
+        deser
+            .into_ability(
+                &g.dmg,
+                &g.items.keys().cloned().collect(),
+                &g.statuses.keys().cloned().collect(),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn hit_chance_weighs_accuracy_against_evasion() {
+        let g = pvp();
+        let mut attacker = fighter(&g, &[], &[]);
+        let mut defender = fighter(&g, &[], &[]);
+        let sure = ability(&g, r#"damage : { "physical" : 1 }"#);
+        let wild = ability(&g, r#"damage : { "physical" : 1 } accuracy : 0.5"#);
+        // accuracy 1 against evasion 0.1
+        assert!((hit_chance(&attacker, &defender, &sure) - 1.0 / 1.1).abs() < 1e-9);
+        assert!((hit_chance(&attacker, &defender, &wild) - 0.5 / 0.6).abs() < 1e-9);
+        defender
+            .stats
+            .insert(StatType::from("evasion".to_string()), 0.0);
+        assert_eq!(hit_chance(&attacker, &defender, &wild), 1.0);
+        attacker
+            .stats
+            .insert(StatType::from("accuracy".to_string()), 0.0);
+        assert_eq!(hit_chance(&attacker, &defender, &wild), 1.0);
+        defender
+            .stats
+            .insert(StatType::from("evasion".to_string()), 1.0);
+        assert_eq!(hit_chance(&attacker, &defender, &wild), 0.0);
+    }
+
+    #[test]
+    fn crits_add_to_the_users_stats() {
+        let g = pvp();
+        let mut attacker = fighter(&g, &[], &[]);
+        let plain = ability(&g, r#"damage : { "physical" : 1 }"#);
+        let sharp = ability(
+            &g,
+            r#"damage : { "physical" : 1 } crit_chance : 0.2 crit_multiplier : 0.5"#,
+        );
+        let (chance, multiplier) = crit(&attacker, &plain);
+        assert!((chance - 0.05).abs() < 1e-9);
+        assert_eq!(multiplier, 1.5);
+        let (chance, multiplier) = crit(&attacker, &sharp);
+        assert!((chance - 0.25).abs() < 1e-9);
+        assert_eq!(multiplier, 2.0);
+        attacker
+            .stats
+            .insert(StatType::from("crit_chance".to_string()), 0.9);
+        attacker
+            .stats
+            .insert(StatType::from("crit_multiplier".to_string()), 0.0);
+        assert_eq!(crit(&attacker, &sharp), (1.0, 1.0));
+    }
+}
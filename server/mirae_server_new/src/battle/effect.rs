@@ -0,0 +1,334 @@
+use super::{
+    combatant::{Combatant, CombatantId},
+    damage,
+    event::BattleEvent,
+};
+use crate::gamedata::gamedata::{DmgType, GameData};
+use anyhow::{anyhow, Result};
+use rand::prelude::StdRng;
+use std::collections::HashMap;
+
+/// everything an effect can see and change while it's being applied.
+pub struct EffectContext<'b> {
+    /// whoever used the ability.
+    pub user: CombatantId,
+    /// whoever the ability was used on.
+    pub target: CombatantId,
+    /// whether the ability hit its target at least once.
+    pub landed: bool,
+    /// the total damage the ability dealt to its target.
+    pub dealt: f64,
+    pub g: &'b GameData,
+    /// the battle's rng. Effects that roll anything have to use this, so the
+    /// battle can still be replayed.
+    pub rng: &'b mut StdRng,
+    pub combatants: &'b mut [Combatant],
+    pub events: &'b mut Vec<BattleEvent>,
+}
+
+impl<'b> EffectContext<'b> {
+    /// change someone's health, logging how much it actually changed by.
+    pub fn change_health(&mut self, who: CombatantId, amount: f64) {
+        let amount = self.combatants[who].change_health(amount);
+        if amount != 0.0 {
+            self.events.push(BattleEvent::Health { who, amount });
+        }
+    }
+
+    /// change someone's energy, logging how much it actually changed by.
+    pub fn change_energy(&mut self, who: CombatantId, amount: f64) {
+        let amount = self.combatants[who].change_energy(amount);
+        if amount != 0.0 {
+            self.events.push(BattleEvent::Energy { who, amount });
+        }
+    }
+
+    /// have the user deal damage to `who`, the same way an ability's damage
+    /// is dealt, except that it can't miss or crit, and doesn't set off
+    /// on_hit statuses. Returns the total damage dealt.
+    pub fn deal(&mut self, who: CombatantId, dmg: &DmgType, amount: f64) -> f64 {
+        let mut total = 0.0;
+        for (dmg, amount) in damage::convert(&self.g.dmg_interactions, dmg, amount) {
+            total += damage::deal_part(
+                &self.g.dmg_interactions,
+                self.combatants,
+                self.user,
+                who,
+                &dmg,
+                amount,
+                self.events,
+            );
+        }
+        total
+    }
+}
+
+/// something an ability can do, referenced by name from the gamemode's
+/// abilities along with a magnitude, e.g. `effects : { "lifesteal" : 0.5 }`.
+/// Effects are applied after the ability's damage has been dealt, in name
+/// order.
+pub trait Effect {
+    /// the name the gamemode refers to this effect by.
+    fn name(&self) -> &str;
+
+    /// check that `magnitude` makes sense for this effect. Called once for
+    /// every ability that uses the effect when the gamemode is loaded.
+    fn validate(&self, magnitude: f64, g: &GameData) -> Result<()>;
+
+    /// do whatever the effect does.
+    fn apply(&self, magnitude: f64, ctx: &mut EffectContext) -> Result<()>;
+
+    /// what the effect does, for help text.
+    fn describe(&self, magnitude: f64) -> String;
+}
+
+/// every effect a gamemode can use, by name.
+pub struct EffectRegistry {
+    effects: HashMap<String, Box<dyn Effect>>,
+}
+
+impl Default for EffectRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EffectRegistry {
+    /// a registry with all the built in effects.
+    pub fn new() -> Self {
+        let mut registry = EffectRegistry::empty();
+        registry.add(Box::new(Heal));
+        registry.add(Box::new(Stun));
+        registry.add(Box::new(Drain));
+        registry.add(Box::new(Cleanse));
+        registry
+    }
+
+    /// a registry without any effects, not even the built in ones. Every
+    /// ability's health and stun go through the effects called heal and stun,
+    /// so a gamemode can't be loaded until they've been registered.
+    pub fn empty() -> Self {
+        EffectRegistry {
+            effects: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, effect: Box<dyn Effect>) {
+        self.effects.insert(effect.name().to_string(), effect);
+    }
+
+    /// add a new effect. Fails if there's already an effect with that name.
+    pub fn register<E: Effect + 'static>(&mut self, effect: E) -> Result<()> {
+        if self.effects.contains_key(effect.name()) {
+            return Err(anyhow!(format!(
+                "there is already an effect called {:?}",
+                effect.name()
+            )));
+        }
+        self.add(Box::new(effect));
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<&dyn Effect> {
+        self.effects
+            .get(name)
+            .map(|e| e.as_ref())
+            .ok_or_else(|| anyhow!(format!("there is no effect called {:?}", name)))
+    }
+
+    /// the names of every registered effect, in order.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.effects.keys().map(|n| n.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    pub fn describe(&self, name: &str, magnitude: f64) -> Result<String> {
+        Ok(self.get(name)?.describe(magnitude))
+    }
+}
+
+fn whole_number(name: &str, magnitude: f64) -> Result<()> {
+    if magnitude < 0.0 || magnitude.fract() != 0.0 {
+        Err(anyhow!(format!(
+            "{} needs a whole number that isn't negative, got {}",
+            name, magnitude
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// changes the user's health. Negative magnitudes hurt the user instead.
+pub struct Heal;
+
+impl Effect for Heal {
+    fn name(&self) -> &str {
+        "heal"
+    }
+
+    fn validate(&self, magnitude: f64, _: &GameData) -> Result<()> {
+        if magnitude.is_finite() {
+            Ok(())
+        } else {
+            Err(anyhow!(format!("heal can't be {}", magnitude)))
+        }
+    }
+
+    fn apply(&self, magnitude: f64, ctx: &mut EffectContext) -> Result<()> {
+        ctx.change_health(ctx.user, magnitude);
+        Ok(())
+    }
+
+    fn describe(&self, magnitude: f64) -> String {
+        if magnitude < 0.0 {
+            format!("costs the user {} health", -magnitude)
+        } else {
+            format!("heals the user for {} health", magnitude)
+        }
+    }
+}
+
+/// makes the target lose turns, if the ability hits.
+pub struct Stun;
+
+impl Effect for Stun {
+    fn name(&self) -> &str {
+        "stun"
+    }
+
+    fn validate(&self, magnitude: f64, _: &GameData) -> Result<()> {
+        whole_number(self.name(), magnitude)
+    }
+
+    fn apply(&self, magnitude: f64, ctx: &mut EffectContext) -> Result<()> {
+        let target = ctx.target;
+        if ctx.landed && magnitude > 0.0 && !ctx.combatants[target].is_dead() {
+            ctx.combatants[target].stunned += magnitude as u64;
+            ctx.events.push(BattleEvent::Stun {
+                source: ctx.user,
+                target,
+                turns: magnitude as u64,
+            });
+        }
+        Ok(())
+    }
+
+    fn describe(&self, magnitude: f64) -> String {
+        format!("stuns the target for {} turns", magnitude)
+    }
+}
+
+/// takes energy away from the target, if the ability hits.
+pub struct Drain;
+
+impl Effect for Drain {
+    fn name(&self) -> &str {
+        "drain"
+    }
+
+    fn validate(&self, magnitude: f64, _: &GameData) -> Result<()> {
+        if magnitude >= 0.0 {
+            Ok(())
+        } else {
+            Err(anyhow!(format!("drain can't be negative, got {}", magnitude)))
+        }
+    }
+
+    fn apply(&self, magnitude: f64, ctx: &mut EffectContext) -> Result<()> {
+        if ctx.landed {
+            let target = ctx.target;
+            let amount = ctx.combatants[target].energy.min(magnitude).max(0.0);
+            ctx.change_energy(target, -amount);
+        }
+        Ok(())
+    }
+
+    fn describe(&self, magnitude: f64) -> String {
+        format!("drains {} energy from the target", magnitude)
+    }
+}
+
+/// removes the user's most recent statuses, without setting off their expiry
+/// triggers.
+pub struct Cleanse;
+
+impl Effect for Cleanse {
+    fn name(&self) -> &str {
+        "cleanse"
+    }
+
+    fn validate(&self, magnitude: f64, _: &GameData) -> Result<()> {
+        whole_number(self.name(), magnitude)
+    }
+
+    fn apply(&self, magnitude: f64, ctx: &mut EffectContext) -> Result<()> {
+        let user = ctx.user;
+        for _ in 0..magnitude as u64 {
+            match ctx.combatants[user].statuses.pop() {
+                Some(status) => ctx.events.push(BattleEvent::StatusRemoved {
+                    who: user,
+                    status: status.name,
+                }),
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn describe(&self, magnitude: f64) -> String {
+        format!("removes the user's {} most recent statuses", magnitude)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamedata::gamedata::pvp_with;
+
+    /// does nothing at all.
+    struct Nothing;
+
+    impl Effect for Nothing {
+        fn name(&self) -> &str {
+            "nothing"
+        }
+
+        fn validate(&self, _: f64, _: &GameData) -> Result<()> {
+            Ok(())
+        }
+
+        fn apply(&self, _: f64, _: &mut EffectContext) -> Result<()> {
+            Ok(())
+        }
+
+        fn describe(&self, _: f64) -> String {
+            "does nothing".to_string()
+        }
+    }
+
+    #[test]
+    fn effects_can_be_registered_once() {
+        let mut registry = EffectRegistry::new();
+        assert!(registry.get("nothing").is_err());
+        registry.register(Nothing).unwrap();
+        assert!(registry.register(Nothing).is_err());
+        assert!(registry.register(Heal).is_err());
+        assert_eq!(
+            registry.names(),
+            vec!["cleanse", "drain", "heal", "nothing", "stun"]
+        );
+        assert_eq!(registry.describe("nothing", 1.0).unwrap(), "does nothing");
+    }
+
+    #[test]
+    fn gamemodes_need_heal_and_stun() {
+        assert!(pvp_with(EffectRegistry::empty()).is_err());
+        let mut registry = EffectRegistry::empty();
+        registry.register(Heal).unwrap();
+        registry.register(Stun).unwrap();
+        registry.register(Nothing).unwrap();
+        let g = pvp_with(registry).unwrap();
+        assert_eq!(g.effects.names(), vec!["heal", "nothing", "stun"]);
+    }
+}
@@ -0,0 +1,155 @@
+use super::combatant::{CombatantId, Team};
+use crate::{
+    gamedata::gamedata::{DmgType, ItemName, StatusName},
+    vector3::Vector3,
+};
+
+/// everything that can happen during a battle. A battle's log is the list of
+/// these, in the order they happened.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BattleEvent {
+    /// it's now `actor`'s turn.
+    Turn { actor: CombatantId },
+    /// `actor` had nothing they could do, and passed their turn.
+    Pass { actor: CombatantId },
+    /// `actor` lost their turn to a stun.
+    Stunned { actor: CombatantId, turns_left: u64 },
+    /// `actor` started charging an ability, which will go off in `turns` turns.
+    ChargeStart {
+        actor: CombatantId,
+        ability: String,
+        turns: u64,
+    },
+    /// `actor` spent their turn charging an ability.
+    Charging {
+        actor: CombatantId,
+        ability: String,
+        turns_left: u64,
+    },
+    /// `actor`'s charged ability couldn't go off, because they no longer had
+    /// the items it uses, or room for the ones it makes.
+    Fizzled {
+        actor: CombatantId,
+        ability: String,
+        reason: String,
+    },
+    /// `actor` used an ability on `targets`.
+    Ability {
+        actor: CombatantId,
+        targets: Vec<CombatantId>,
+        ability: String,
+    },
+    /// one hit of `source`'s ability missed `target`.
+    Miss {
+        source: CombatantId,
+        target: CombatantId,
+    },
+    /// one hit of `source`'s ability is a critical hit on `target`, and does
+    /// `multiplier` times the damage.
+    Critical {
+        source: CombatantId,
+        target: CombatantId,
+        multiplier: f64,
+    },
+    Energy { who: CombatantId, amount: f64 },
+    Health { who: CombatantId, amount: f64 },
+    /// `target` took `amount` damage from `source`, after `blocked` was blocked.
+    Damage {
+        source: CombatantId,
+        target: CombatantId,
+        dmg: DmgType,
+        amount: f64,
+        blocked: f64,
+    },
+    /// `source` countered, dealing `amount` damage back to `target`.
+    Counter {
+        source: CombatantId,
+        target: CombatantId,
+        dmg: DmgType,
+        amount: f64,
+    },
+    /// `who` is blocking this proportion of the damage type until their next turn.
+    Block {
+        who: CombatantId,
+        dmg: DmgType,
+        proportion: f64,
+    },
+    /// `who` will counter this proportion of the damage type until their next turn.
+    CounterReady {
+        who: CombatantId,
+        dmg: DmgType,
+        proportion: f64,
+    },
+    Stun {
+        source: CombatantId,
+        target: CombatantId,
+        turns: u64,
+    },
+    /// `who` now has `stacks` of a status, for `turns_left` more turns.
+    StatusApplied {
+        who: CombatantId,
+        status: StatusName,
+        stacks: u64,
+        turns_left: u64,
+    },
+    /// one of `who`'s statuses went off.
+    StatusTriggered { who: CombatantId, status: StatusName },
+    StatusExpired { who: CombatantId, status: StatusName },
+    /// one of `who`'s statuses was taken away before it ran out.
+    StatusRemoved { who: CombatantId, status: StatusName },
+    /// one of the effects of `user`'s ability went off.
+    Effect {
+        user: CombatantId,
+        target: CombatantId,
+        effect: String,
+        magnitude: f64,
+    },
+    ItemRemoved {
+        who: CombatantId,
+        item: ItemName,
+        amount: u64,
+    },
+    ItemMade {
+        who: CombatantId,
+        item: ItemName,
+        amount: u64,
+    },
+    ItemDestroyed { who: CombatantId, item: ItemName },
+    /// the item `who` used wore down, and has `durability` uses left.
+    ItemWorn {
+        who: CombatantId,
+        item: ItemName,
+        durability: u64,
+    },
+    /// `who` made some items they had no room for, and dropped them.
+    ItemDropped {
+        who: CombatantId,
+        item: ItemName,
+        amount: u64,
+    },
+    /// `who` consumed one of an item.
+    Consumed { who: CombatantId, item: ItemName },
+    /// `who` now has `stacks` of the buffs from consuming an item, for however
+    /// many turns and/or seconds are left.
+    BuffApplied {
+        who: CombatantId,
+        item: ItemName,
+        stacks: u64,
+        turns_left: Option<u64>,
+        secs_left: Option<f64>,
+    },
+    BuffExpired { who: CombatantId, item: ItemName },
+    Defeated { who: CombatantId },
+    /// `who` joined the battle part way through, fighting for `team`.
+    Joined { who: CombatantId, team: Team },
+    /// `who` tried to run away, with the given chance of getting away.
+    FleeAttempt { who: CombatantId, chance: f64 },
+    /// `who` ran away, and is out of the battle. `to` is where they ran to,
+    /// in battles fought in an arena.
+    Fled {
+        who: CombatantId,
+        to: Option<Vector3>,
+    },
+    /// `who` tried to run away, but didn't get away.
+    FleeFailed { who: CombatantId },
+}
@@ -0,0 +1,111 @@
+use super::combatant::CombatantId;
+
+/// how much speed a combatant has to build up before they get to act.
+pub const THRESHOLD: f64 = 100.0;
+
+/// the slowest a combatant can be, so that nobody waits forever.
+const MIN_SPEED: f64 = 1.0;
+
+/// decides who acts next. Every combatant fills up a meter by their speed, and
+/// gets a turn whenever it reaches the threshold. Someone twice as fast as
+/// everyone else will act twice for each of their turns.
+/// Stunned or charging combatants still get turns, they just spend them being
+/// stunned or charging.
+#[derive(Debug, Clone)]
+pub struct Initiative {
+    meters: Vec<f64>,
+}
+
+impl Initiative {
+    pub fn new(num_combatants: usize) -> Self {
+        Initiative {
+            meters: vec![0.0; num_combatants],
+        }
+    }
+
+    /// advance time until someone can act, and use up their turn.
+    /// `speeds[i]` is combatant i's current speed, or `None` if they're out of
+    /// the battle and don't get turns any more.
+    /// If several combatants can act at once, whoever has built up the most
+    /// goes first, then whoever is fastest, then whoever joined the battle
+    /// first. Returns `None` if nobody is left to act.
+    pub fn next(&mut self, speeds: &[Option<f64>]) -> Option<CombatantId> {
+        if self.meters.len() < speeds.len() {
+            self.meters.resize(speeds.len(), 0.0);
+        }
+        let active: Vec<(usize, f64)> = speeds
+            .iter()
+            .enumerate()
+            .filter_map(|(i, s)| s.map(|s| (i, s.max(MIN_SPEED))))
+            .collect();
+        if !active.iter().any(|(i, _)| self.meters[*i] >= THRESHOLD) {
+            let wait = active
+                .iter()
+                .map(|(i, speed)| ((THRESHOLD - self.meters[*i]) / speed).ceil())
+                .fold(f64::INFINITY, f64::min);
+            for (i, speed) in &active {
+                self.meters[*i] += speed * wait;
+            }
+        }
+        let (mut best, mut best_speed) = *active.first()?;
+        for (i, speed) in active {
+            let (m, b) = (self.meters[i], self.meters[best]);
+            if m > b || (m == b && speed > best_speed) {
+                best = i;
+                best_speed = speed;
+            }
+        }
+        self.meters[best] -= THRESHOLD;
+        Some(best)
+    }
+
+    /// the next `n` turns, assuming nobody's speed changes.
+    pub fn preview(&self, speeds: &[Option<f64>], n: usize) -> Vec<CombatantId> {
+        let mut future = self.clone();
+        (0..n).filter_map(|_| future.next(speeds)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn faster_combatants_act_more_often() {
+        let initiative = Initiative::new(2);
+        let turns = initiative.preview(&[Some(20.0), Some(10.0)], 9);
+        assert_eq!(turns.iter().filter(|t| **t == 0).count(), 6);
+        assert_eq!(turns.iter().filter(|t| **t == 1).count(), 3);
+    }
+
+    #[test]
+    fn ties_go_to_whoever_came_first() {
+        let mut initiative = Initiative::new(3);
+        let speeds = [Some(10.0), Some(10.0), Some(10.0)];
+        assert_eq!(initiative.preview(&speeds, 6), vec![0, 1, 2, 0, 1, 2]);
+        assert_eq!(initiative.next(&speeds), Some(0));
+    }
+
+    #[test]
+    fn only_combatants_still_in_the_battle_act() {
+        let mut initiative = Initiative::new(3);
+        let speeds = [Some(10.0), None, Some(10.0)];
+        assert_eq!(initiative.preview(&speeds, 4), vec![0, 2, 0, 2]);
+        assert_eq!(initiative.next(&[None, None, None]), None);
+    }
+
+    #[test]
+    fn joiners_get_a_meter() {
+        let mut initiative = Initiative::new(1);
+        assert_eq!(initiative.next(&[Some(10.0)]), Some(0));
+        // someone much faster joins, and soon overtakes
+        assert_eq!(initiative.next(&[Some(10.0), Some(1000.0)]), Some(1));
+    }
+
+    #[test]
+    fn even_the_slowest_get_a_turn() {
+        let initiative = Initiative::new(2);
+        let turns = initiative.preview(&[Some(0.0), Some(50.0)], 200);
+        assert!(turns.contains(&0));
+    }
+}
@@ -0,0 +1,128 @@
+use super::{
+    battle::Battle,
+    combatant::{Combatant, CombatantId},
+    narrate::{duration, num},
+};
+use crate::gamedata::gamedata::ItemName;
+use std::fmt;
+
+/// one of a combatant's abilities, as it stands right now.
+#[derive(Debug, Clone)]
+pub struct AbilityListing {
+    pub name: String,
+    pub energy: f64,
+    pub health: f64,
+    pub cooldown: u64,
+    pub cooldown_secs: f64,
+    /// how long until it can be used again.
+    pub turns_left: u64,
+    pub secs_left: f64,
+    /// why it can't be used right now, if it can't.
+    pub unusable: Option<String>,
+}
+
+impl fmt::Display for AbilityListing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = vec![];
+        for (amount, what) in &[(self.energy, "energy"), (self.health, "health")] {
+            if *amount < 0.0 {
+                parts.push(format!("costs {} {}", num(-amount), what));
+            } else if *amount > 0.0 {
+                parts.push(format!("gives {} {}", num(*amount), what));
+            }
+        }
+        match (self.cooldown, self.cooldown_secs) {
+            (0, secs) if secs <= 0.0 => {}
+            (0, secs) => parts.push(format!("cooldown {}s", num(secs))),
+            (turns, secs) if secs <= 0.0 => parts.push(format!("cooldown {} turns", turns)),
+            (turns, secs) => parts.push(format!("cooldown {} turns and {}s", turns, num(secs))),
+        }
+        write!(f, "{}", self.name)?;
+        if !parts.is_empty() {
+            write!(f, " ({})", parts.join(", "))?;
+        }
+        match &self.unusable {
+            None => write!(f, ": ready"),
+            Some(reason) => write!(f, ": {}", reason),
+        }
+    }
+}
+
+/// everything `who` can do, in name order, and whether they can do it right
+/// now.
+pub fn list_abilities(battle: &Battle, who: CombatantId) -> Vec<AbilityListing> {
+    let c = match battle.combatant(who) {
+        Some(c) => c,
+        None => return vec![],
+    };
+    let mut names: Vec<&String> = c.abilities.keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .map(|name| {
+            let ability = &c.abilities[name].ability;
+            let (turns_left, secs_left) = c.cooldown_left(name, battle.clock());
+            AbilityListing {
+                name: name.clone(),
+                energy: ability.energy,
+                health: ability.health,
+                cooldown: ability.cooldown,
+                cooldown_secs: ability.cooldown_secs,
+                turns_left,
+                secs_left,
+                unusable: battle.check_ability(who, name).err().map(|e| e.to_string()),
+            }
+        })
+        .collect()
+}
+
+/// the buffs from one item a combatant has consumed, as they stand right now.
+#[derive(Debug, Clone)]
+pub struct BuffListing {
+    pub item: ItemName,
+    pub stacks: u64,
+    pub turns_left: Option<u64>,
+    pub secs_left: Option<f64>,
+}
+
+impl fmt::Display for BuffListing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} x{} ({} left)",
+            self.item.as_str(),
+            self.stacks,
+            duration(self.turns_left, self.secs_left)
+        )
+    }
+}
+
+/// every buff `who` has from consuming items, in the order they got them.
+pub fn list_buffs(battle: &Battle, who: CombatantId) -> Vec<BuffListing> {
+    match battle.combatant(who) {
+        Some(c) => c
+            .consumed
+            .active()
+            .iter()
+            .map(|a| BuffListing {
+                item: a.item.clone(),
+                stacks: a.stacks,
+                turns_left: a.turns_left,
+                secs_left: a.secs_left(battle.clock()),
+            })
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// a combatant's current and max health and energy.
+pub fn summary(c: &Combatant) -> String {
+    format!(
+        "{}: {}/{} health, {}/{} energy",
+        c.name,
+        num(c.health.max(0.0)),
+        num(c.max_health()),
+        num(c.energy),
+        num(c.max_energy())
+    )
+}
@@ -0,0 +1,12 @@
+pub mod arena;
+#[allow(clippy::module_inception)]
+pub mod battle;
+pub mod combatant;
+pub mod damage;
+pub mod effect;
+pub mod event;
+pub mod initiative;
+pub mod listing;
+pub mod narrate;
+pub mod replay;
+pub mod strategy;
@@ -0,0 +1,387 @@
+use super::{
+    battle::{Battle, Outcome},
+    combatant::CombatantId,
+    event::BattleEvent,
+};
+use crate::gamedata::gamedata::{GameData, MobAction};
+
+/// a number, without a pointless ".0" on the end.
+pub(super) fn num(x: f64) -> String {
+    if x.fract() == 0.0 {
+        format!("{}", x)
+    } else {
+        format!("{:.1}", x)
+    }
+}
+
+/// how long something lasts, in turns, seconds, or whichever runs out first.
+pub(super) fn duration(turns: Option<u64>, secs: Option<f64>) -> String {
+    match (turns, secs) {
+        (Some(turns), Some(secs)) => format!("{} turns or {}s", turns, num(secs)),
+        (Some(turns), None) => format!("{} turns", turns),
+        (None, Some(secs)) => format!("{}s", num(secs)),
+        (None, None) => "until the end of the fight".to_string(),
+    }
+}
+
+/// turns a battle's log into something a person can read.
+struct Narrator<'a, 'b> {
+    battle: &'a Battle<'b>,
+    g: &'a GameData,
+    lines: Vec<String>,
+    turn: usize,
+}
+
+impl<'a, 'b> Narrator<'a, 'b> {
+    fn name(&self, id: CombatantId) -> &str {
+        self.battle
+            .combatant(id)
+            .map(|c| c.name.as_str())
+            .unwrap_or("someone")
+    }
+
+    fn names(&self, ids: &[CombatantId]) -> String {
+        let names: Vec<&str> = ids.iter().map(|id| self.name(*id)).collect();
+        match names.split_last() {
+            Some((last, rest)) if !rest.is_empty() => format!("{} and {}", rest.join(", "), last),
+            _ => names.join(""),
+        }
+    }
+
+    /// if `who` is a mob with something to say about `action`, say it. Which
+    /// quote gets used only depends on the turn, so narrating the same battle
+    /// twice gives the same story.
+    fn quote(&mut self, who: CombatantId, action: &str) {
+        let quote = self
+            .battle
+            .combatant(who)
+            .and_then(|c| c.mob.as_ref())
+            .and_then(|mob| self.g.mob_templates.get(mob))
+            .and_then(|t| t.quotes.get(&MobAction::from(action.to_string())))
+            .filter(|quotes| !quotes.is_empty())
+            .map(|quotes| quotes[self.turn % quotes.len()].clone());
+        if let Some(quote) = quote {
+            let line = format!("{}: \"{}\"", self.name(who), quote);
+            self.lines.push(line);
+        }
+    }
+
+    /// have the first mob fighting against `who` say something about `action`.
+    fn enemy_quote(&mut self, who: CombatantId, action: &str) {
+        let team = self.battle.combatant(who).map(|c| c.team);
+        let enemy = self
+            .battle
+            .combatants()
+            .iter()
+            .position(|c| Some(c.team) != team && c.mob.is_some());
+        if let Some(enemy) = enemy {
+            self.quote(enemy, action);
+        }
+    }
+
+    fn say(&mut self, line: String) {
+        self.lines.push(line);
+    }
+
+    fn event(&mut self, event: &BattleEvent) {
+        let line = match event {
+            BattleEvent::Turn { actor } => {
+                self.turn += 1;
+                format!("-- turn {}: {} --", self.turn, self.name(*actor))
+            }
+            BattleEvent::Pass { actor } => format!("{} can't do anything.", self.name(*actor)),
+            BattleEvent::Stunned { actor, turns_left } => format!(
+                "{} is stunned ({} more turns).",
+                self.name(*actor),
+                turns_left
+            ),
+            BattleEvent::ChargeStart {
+                actor,
+                ability,
+                turns,
+            } => format!(
+                "{} starts charging up {} ({} turns).",
+                self.name(*actor),
+                ability,
+                turns
+            ),
+            BattleEvent::Charging {
+                actor,
+                ability,
+                turns_left,
+            } => format!(
+                "{} keeps charging {} ({} more turns).",
+                self.name(*actor),
+                ability,
+                turns_left
+            ),
+            BattleEvent::Fizzled {
+                actor,
+                ability,
+                reason,
+            } => format!(
+                "{}'s {} fizzles: {}.",
+                self.name(*actor),
+                ability,
+                reason
+            ),
+            BattleEvent::Ability {
+                actor,
+                targets,
+                ability,
+            } => {
+                let line = match targets.as_slice() {
+                    [] => format!("{} uses {}.", self.name(*actor), ability),
+                    [target] if target == actor => {
+                        format!("{} uses {}.", self.name(*actor), ability)
+                    }
+                    _ => format!(
+                        "{} uses {} on {}.",
+                        self.name(*actor),
+                        ability,
+                        self.names(targets)
+                    ),
+                };
+                self.say(line);
+                self.quote(*actor, "mob_attack");
+                return;
+            }
+            BattleEvent::Miss { source, target } => {
+                format!("{} misses {}.", self.name(*source), self.name(*target))
+            }
+            BattleEvent::Critical { multiplier, .. } => {
+                format!("a critical hit! (x{})", num(*multiplier))
+            }
+            BattleEvent::Energy { who, amount } if *amount >= 0.0 => {
+                format!("{} gains {} energy.", self.name(*who), num(*amount))
+            }
+            BattleEvent::Energy { who, amount } => {
+                format!("{} loses {} energy.", self.name(*who), num(-amount))
+            }
+            BattleEvent::Health { who, amount } if *amount >= 0.0 => {
+                format!("{} heals {} health.", self.name(*who), num(*amount))
+            }
+            BattleEvent::Health { who, amount } => {
+                format!("{} loses {} health.", self.name(*who), num(-amount))
+            }
+            BattleEvent::Damage {
+                target,
+                dmg,
+                amount,
+                blocked,
+                ..
+            } => {
+                let mut line = format!(
+                    "{} takes {} {} damage",
+                    self.name(*target),
+                    num(*amount),
+                    dmg.as_str()
+                );
+                if *blocked > 0.0 {
+                    line += &format!(" ({} blocked)", num(*blocked));
+                }
+                line + "."
+            }
+            BattleEvent::Counter {
+                source,
+                target,
+                dmg,
+                amount,
+            } => format!(
+                "{} counters, dealing {} {} damage to {}.",
+                self.name(*source),
+                num(*amount),
+                dmg.as_str(),
+                self.name(*target)
+            ),
+            BattleEvent::Block {
+                who,
+                dmg,
+                proportion,
+            } => format!(
+                "{} braces against {} damage ({}%).",
+                self.name(*who),
+                dmg.as_str(),
+                num(proportion * 100.0)
+            ),
+            BattleEvent::CounterReady {
+                who,
+                dmg,
+                proportion,
+            } => format!(
+                "{} gets ready to counter {} damage ({}%).",
+                self.name(*who),
+                dmg.as_str(),
+                num(proportion * 100.0)
+            ),
+            BattleEvent::Stun { target, turns, .. } => format!(
+                "{} is stunned for {} turns.",
+                self.name(*target),
+                turns
+            ),
+            BattleEvent::StatusApplied {
+                who,
+                status,
+                stacks,
+                turns_left,
+            } => format!(
+                "{} has {} x{} ({} turns).",
+                self.name(*who),
+                status.as_str(),
+                stacks,
+                turns_left
+            ),
+            BattleEvent::StatusTriggered { who, status } => {
+                format!("{}'s {} goes off.", self.name(*who), status.as_str())
+            }
+            BattleEvent::StatusExpired { who, status } => {
+                format!("{}'s {} wears off.", self.name(*who), status.as_str())
+            }
+            BattleEvent::StatusRemoved { who, status } => {
+                format!("{}'s {} is removed.", self.name(*who), status.as_str())
+            }
+            BattleEvent::Effect {
+                effect, magnitude, ..
+            } => match self.g.effects.describe(effect, *magnitude) {
+                Ok(description) => format!("({}: {})", effect, description),
+                Err(_) => format!("({} {})", effect, num(*magnitude)),
+            },
+            BattleEvent::ItemRemoved { who, item, amount } => format!(
+                "{} uses up {} {}.",
+                self.name(*who),
+                amount,
+                item.as_str()
+            ),
+            BattleEvent::ItemMade { who, item, amount } => format!(
+                "{} makes {} {}.",
+                self.name(*who),
+                amount,
+                item.as_str()
+            ),
+            BattleEvent::ItemDropped { who, item, amount } => format!(
+                "{} has no room for {} {}, and drops it.",
+                self.name(*who),
+                amount,
+                item.as_str()
+            ),
+            BattleEvent::ItemWorn {
+                who,
+                item,
+                durability,
+            } => format!(
+                "{}'s {} wears down ({} uses left).",
+                self.name(*who),
+                item.as_str(),
+                durability
+            ),
+            BattleEvent::ItemDestroyed { who, item } => {
+                format!("{}'s {} is destroyed.", self.name(*who), item.as_str())
+            }
+            BattleEvent::Consumed { who, item } => {
+                format!("{} consumes {}.", self.name(*who), item.as_str())
+            }
+            BattleEvent::BuffApplied {
+                who,
+                item,
+                stacks,
+                turns_left,
+                secs_left,
+            } => format!(
+                "{} is buffed by {} x{} ({}).",
+                self.name(*who),
+                item.as_str(),
+                stacks,
+                duration(*turns_left, *secs_left)
+            ),
+            BattleEvent::BuffExpired { who, item } => {
+                format!("{}'s {} buff wears off.", self.name(*who), item.as_str())
+            }
+            BattleEvent::Defeated { who } => format!("{} is defeated!", self.name(*who)),
+            BattleEvent::Joined { who, .. } => {
+                let line = format!("{} joins the fight!", self.name(*who));
+                self.say(line);
+                self.quote(*who, "entrance");
+                return;
+            }
+            BattleEvent::FleeAttempt { who, chance } => {
+                let line = format!(
+                    "{} tries to run away ({}% chance).",
+                    self.name(*who),
+                    num(chance * 100.0)
+                );
+                self.say(line);
+                self.enemy_quote(*who, "player_run");
+                return;
+            }
+            BattleEvent::Fled { who, .. } => {
+                let line = format!("{} runs away!", self.name(*who));
+                self.say(line);
+                self.enemy_quote(*who, "player_run_success");
+                return;
+            }
+            BattleEvent::FleeFailed { who } => {
+                let line = format!("{} doesn't get away!", self.name(*who));
+                self.say(line);
+                self.enemy_quote(*who, "player_run_failure");
+                return;
+            }
+        };
+        self.say(line);
+    }
+}
+
+/// a turn by turn account of everything that's happened in the battle so far,
+/// with the mobs' quotes mixed in where they fit.
+pub fn narrate(battle: &Battle, g: &GameData) -> Vec<String> {
+    let mut n = Narrator {
+        battle,
+        g,
+        lines: vec![],
+        turn: 0,
+    };
+    // anyone who joins later gets introduced when they do
+    let starting = battle.starters();
+    let mut teams = vec![];
+    let num_teams = starting.iter().map(|id| battle.combatants()[*id].team + 1).max();
+    for team in 0..num_teams.unwrap_or(0) {
+        let members: Vec<CombatantId> = starting
+            .iter()
+            .cloned()
+            .filter(|id| battle.combatants()[*id].team == team)
+            .collect();
+        if !members.is_empty() {
+            teams.push(n.names(&members));
+        }
+    }
+    n.say(format!("{} (seed {})", teams.join(" vs "), battle.seed()));
+    for id in starting {
+        n.quote(id, "entrance");
+    }
+    for event in battle.log() {
+        n.event(event);
+    }
+    match battle.outcome() {
+        Some(Outcome::Victory(team)) => {
+            let winners = battle.team(team);
+            let line = match winners.as_slice() {
+                [winner] => format!("{} wins!", n.name(*winner)),
+                _ => format!("{} win!", n.names(&winners)),
+            };
+            n.say(line);
+            let mobs_won = winners
+                .iter()
+                .all(|id| battle.combatants()[*id].mob.is_some());
+            for id in 0..battle.combatants().len() {
+                let c = &battle.combatants()[id];
+                if c.team == team && c.mob.is_some() {
+                    n.quote(id, "mob_victory");
+                } else if c.team != team && !mobs_won {
+                    n.quote(id, "player_victory");
+                }
+            }
+        }
+        Some(Outcome::Draw) => n.say("it's a draw.".to_string()),
+        None => n.say("the battle isn't over yet.".to_string()),
+    }
+    n.lines
+}
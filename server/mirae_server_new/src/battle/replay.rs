@@ -0,0 +1,134 @@
+use super::{
+    arena::Arena,
+    battle::{Battle, Choice},
+    combatant::{Combatant, Team},
+};
+use crate::gamedata::gamedata::GameData;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// something that happened in a battle that the replay needs to know about.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Step {
+    /// someone joined the battle part way through.
+    Join {
+        team: Team,
+        combatant: Box<Combatant>,
+    },
+    /// the battle's clock was moved forward to this time.
+    Clock(f64),
+    /// whatever was chosen on a turn. `None` for turns where there was nothing
+    /// to choose.
+    Turn(Option<Choice>),
+}
+
+/// a recording of a battle. The battle's seed and everyone's starting state
+/// decide everything random, so replaying the same choices always ends up in
+/// the same place.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    /// everyone who was in the battle from the start, by team.
+    pub teams: Vec<Vec<Combatant>>,
+    /// where the battle was fought, if it was fought in the world.
+    pub arena: Option<Arena>,
+    pub steps: Vec<Step>,
+}
+
+impl Replay {
+    fn save_path(dir: &str, name: &str) -> Result<String> {
+        if name.is_empty()
+            || name.contains("..")
+            || name.contains(['/', '\\', '\0'])
+        {
+            return Err(anyhow!(format!("{:?} can't be used as a replay's name", name)));
+        }
+        Ok(format!("{}/replays/{}.json", dir, name))
+    }
+
+    pub fn save(&self, dir: &str, name: &str) -> Result<()> {
+        let path = Self::save_path(dir, name)?;
+        if let Some(parent) = Path::new(&path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(dir: &str, name: &str) -> Result<Replay> {
+        Self::load_file(&Self::save_path(dir, name)?)
+    }
+
+    pub fn load_file(path: &str) -> Result<Replay> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// play the battle back. Fails if one of the choices can't be made any
+    /// more, which usually means the gamemode has changed since the battle
+    /// was recorded.
+    pub fn play<'a>(&self, g: &'a GameData) -> Result<Battle<'a>> {
+        let mut battle = match &self.arena {
+            Some(arena) => Battle::in_arena(g, self.seed, self.teams.clone(), arena.clone())?,
+            None => Battle::new(g, self.seed, self.teams.clone())?,
+        };
+        for (i, step) in self.steps.iter().enumerate() {
+            let result = match step {
+                Step::Join { team, combatant } => {
+                    battle.join(*team, (**combatant).clone()).map(|_| ())
+                }
+                Step::Clock(now) => battle.set_clock(*now),
+                Step::Turn(choice) => battle.turn(choice.clone()).map(|_| ()),
+            };
+            result.map_err(|e| anyhow!(format!("the replay diverged on step {}: {}", i, e)))?;
+        }
+        Ok(battle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        gamedata::gamedata::pvp,
+        sim::{run_battle, Side, SimConfig},
+    };
+
+    #[test]
+    fn replays_play_back_the_same_battle() {
+        let g = pvp();
+        let sides = [
+            Side::parse("loadout:pvp/loadouts/knight.jacl", &g).unwrap(),
+            Side::parse("mob:lowly_ben", &g).unwrap(),
+        ];
+        let config = SimConfig {
+            battles: 1,
+            seed: 7,
+            max_turns: 200,
+            turn_secs: 1.0,
+            replay_dir: None,
+        };
+        let battle = run_battle(&g, &sides, 7, &config).unwrap();
+        assert!(battle.turns() > 0);
+        let dir = std::env::temp_dir().join(format!("mirae_replay_{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        battle.replay().save(dir, "7").unwrap();
+        let replay = Replay::load(dir, "7").unwrap();
+        let _ = fs::remove_dir_all(dir);
+        let replayed = replay.play(&g).unwrap();
+        assert_eq!(replayed.log(), battle.log());
+        assert_eq!(replayed.outcome(), battle.outcome());
+        assert_eq!(replayed.turns(), battle.turns());
+    }
+
+    #[test]
+    fn names_cant_point_outside_the_save_dir() {
+        for name in &["", "..", "../7", "a/b", "a\\b", "a\0b"] {
+            assert!(Replay::save_path("saves", name).is_err(), "{:?}", name);
+        }
+        assert_eq!(
+            Replay::save_path("saves", "7").unwrap(),
+            "saves/replays/7.json"
+        );
+    }
+}
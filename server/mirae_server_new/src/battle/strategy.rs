@@ -0,0 +1,345 @@
+use super::{
+    battle::{Battle, Choice},
+    combatant::{Combatant, CombatantId},
+    damage,
+};
+use crate::gamedata::{
+    gamedata::{DmgType, GameData, StatusName},
+    item::{Ability, Targets},
+    mobtemplate::Strategy,
+};
+use rand::{prelude::StdRng, Rng};
+use std::collections::HashMap;
+
+/// how much damage `user` can expect to deal to `target` with `ability`,
+/// taking hit chance, crits, the target's defense buffs and alignments, and
+/// anything the target is currently blocking into account.
+fn expected_damage(g: &GameData, user: &Combatant, target: &Combatant, ability: &Ability) -> f64 {
+    let mut per_hit = 0.0;
+    for (dmg, amount) in &ability.damage {
+        for (dmg, amount) in damage::convert(&g.dmg_interactions, dmg, *amount) {
+            let blocked = target.blocking.get(&dmg).unwrap_or(&0.0).clamp(0.0, 1.0);
+            per_hit += damage::resolve(&g.dmg_interactions, user, target, &dmg, amount)
+                * (1.0 - blocked);
+        }
+    }
+    let (crit_chance, crit_multiplier) = damage::crit(user, ability);
+    per_hit
+        * damage::hit_chance(user, target, ability)
+        * (1.0 + crit_chance * (crit_multiplier - 1.0))
+        * (ability.repeat + 1) as f64
+}
+
+/// roughly how much damage and healing some statuses will do over their whole
+/// duration.
+fn status_value(g: &GameData, statuses: &HashMap<StatusName, u64>) -> (f64, f64) {
+    let mut damage = 0.0;
+    let mut healing = 0.0;
+    for (name, stacks) in statuses {
+        if let Some(status) = g.statuses.get(name) {
+            let turns = (status.duration * (*stacks).min(status.max_stacks)) as f64;
+            damage += status.per_turn.damage.values().sum::<f64>() * turns;
+            healing += status.per_turn.health.max(0.0) * turns;
+        }
+    }
+    (damage, healing)
+}
+
+/// the most damaging thing `attacker` could do to `defender` right now, and
+/// how much damage it would do.
+fn biggest_threat<'a>(
+    g: &GameData,
+    attacker: &'a Combatant,
+    defender: &Combatant,
+) -> Option<(&'a Ability, f64)> {
+    attacker
+        .abilities
+        .values()
+        .map(|a| (&a.ability, expected_damage(g, attacker, defender, &a.ability)))
+        // a gamemode with odd enough numbers could make nonsense of this
+        .filter(|a| !a.1.is_nan())
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// how much of the damage from `threat` would be stopped (or sent back) by
+/// the given blocking or countering proportions.
+fn protection(
+    g: &GameData,
+    attacker: &Combatant,
+    defender: &Combatant,
+    threat: &Ability,
+    proportions: &HashMap<DmgType, f64>,
+) -> f64 {
+    let mut total = 0.0;
+    for (dmg, amount) in &threat.damage {
+        for (dmg, amount) in damage::convert(&g.dmg_interactions, dmg, *amount) {
+            let proportion = proportions.get(&dmg).unwrap_or(&0.0).clamp(0.0, 1.0);
+            total += damage::resolve(&g.dmg_interactions, attacker, defender, &dmg, amount)
+                * proportion;
+        }
+    }
+    total * (threat.repeat + 1) as f64
+}
+
+/// how worried `c` should be about their health, from 0 (not at all) to 1.
+fn health_danger(c: &Combatant, strategy: &Strategy) -> f64 {
+    let health = c.health / c.max_health().max(1.0);
+    if health < strategy.low_health {
+        1.0 - health / strategy.low_health
+    } else {
+        0.0
+    }
+}
+
+/// how good an idea using `ability` on `targets` looks for `who`.
+fn score(
+    battle: &Battle,
+    g: &GameData,
+    who: CombatantId,
+    ability: &Ability,
+    targets: &[CombatantId],
+    strategy: &Strategy,
+) -> f64 {
+    let user = &battle.combatants()[who];
+    // never kill yourself on purpose
+    if user.health + ability.health <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    let max_health = user.max_health().max(1.0);
+    let max_energy = user.max_energy().max(1.0);
+    let danger = health_danger(user, strategy);
+    // the scariest thing any enemy could do to the user
+    let threat = battle
+        .enemies(who)
+        .into_iter()
+        .filter_map(|e| {
+            let enemy = &battle.combatants()[e];
+            biggest_threat(g, enemy, user).map(|(a, amount)| (enemy, a, amount))
+        })
+        .max_by(|a, b| a.2.total_cmp(&b.2));
+
+    let (mut offense, mut support) = (0.0, 0.0);
+    for t in targets {
+        let target = &battle.combatants()[*t];
+        let dealt = expected_damage(g, user, target, ability);
+        let (status_damage, status_healing) = status_value(g, &ability.inflict);
+        if target.team != user.team {
+            offense += dealt + status_damage;
+            if dealt >= target.health {
+                // taking someone out is worth a lot more than just the damage
+                offense += target.health.max(0.0);
+            }
+            if let Some((_, amount)) = biggest_threat(g, target, user) {
+                offense += ability.stun as f64 * amount;
+            }
+        } else {
+            // helping an ally (or yourself) is worth more the worse off they are
+            let missing = target.max_health() - target.health;
+            support += status_healing.min(missing) * (1.0 + health_danger(target, strategy))
+                - dealt
+                - status_damage;
+        }
+    }
+    if let Some((enemy, threat, _)) = threat {
+        offense += protection(g, enemy, user, threat, &ability.counter);
+    }
+
+    let mut defense = (ability.health.max(0.0) + status_value(g, &ability.gain).1)
+        .min(max_health - user.health);
+    if let Some((enemy, threat, _)) = threat {
+        defense += protection(g, enemy, user, threat, &ability.block);
+    }
+    // hurting yourself is always a cost, and a bigger one when you're hurt
+    let self_harm = (-ability.health).max(0.0) * (1.0 + danger);
+
+    let energy_after = user.energy + ability.energy;
+    let energy_gain = ability.energy.max(0.0).min(max_energy - user.energy);
+    let mut score = strategy.aggression * offense
+        + strategy.caution * (support + danger * defense)
+        - self_harm
+        + 0.5 * energy_gain * (1.0 - user.energy / max_energy);
+    if ability.energy < 0.0 && energy_after < strategy.energy_reserve * max_energy {
+        score *= 0.25;
+    }
+    // charged abilities take several turns to do anything
+    score / (ability.charge + 1) as f64
+}
+
+/// pick what `who` should do, following `strategy`. Only abilities they can
+/// actually use are considered, so anything needing items they don't have (or
+/// more energy than they have) is never picked. Abilities that hit one enemy
+/// or ally are weighed up against each of them. Returns `None` if there's
+/// nothing to choose.
+pub fn choose(
+    battle: &Battle,
+    g: &GameData,
+    who: CombatantId,
+    strategy: &Strategy,
+    rng: &mut StdRng,
+) -> Option<Choice> {
+    if battle.current() != who || !battle.needs_choice() {
+        return None;
+    }
+    let user = battle.combatant(who)?;
+    let mut best: Option<(f64, Choice)> = None;
+    // usable_abilities and candidates are sorted, so ties always go the same
+    // way
+    for name in battle.usable_abilities(who) {
+        let ability = &user.abilities[&name].ability;
+        let options: Vec<Option<CombatantId>> = match ability.targets {
+            Targets::Enemy | Targets::Ally => {
+                battle.candidates(who, ability).into_iter().map(Some).collect()
+            }
+            Targets::Enemies | Targets::User => vec![None],
+        };
+        for target in options {
+            let targets = battle.targets(who, &name, target).unwrap_or_default();
+            let mut s = score(battle, g, who, ability, &targets, strategy);
+            if s.is_finite() {
+                s += s.abs().max(1.0) * strategy.randomness * rng.gen_range(-1.0, 1.0);
+            }
+            if best.as_ref().map(|(b, _)| s > *b).unwrap_or(true) {
+                best = Some((
+                    s,
+                    Choice::Ability {
+                        name: name.clone(),
+                        target,
+                    },
+                ));
+            }
+        }
+    }
+    best.map(|(_, choice)| choice)
+}
+
+/// pick what `who` should do using their mob template's strategy. Returns
+/// `None` if they aren't a mob, or have nothing to choose.
+pub fn choose_for_mob(
+    battle: &Battle,
+    g: &GameData,
+    who: CombatantId,
+    rng: &mut StdRng,
+) -> Option<Choice> {
+    let mob = battle.combatant(who)?.mob.as_ref()?;
+    let template = g.mob_templates.get(mob)?;
+    choose(battle, g, who, &template.strategy, rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        battle::combatant::BattleAbility,
+        gamedata::{
+            gamedata::{pvp, StatType},
+            item::{AbilityDeser, Buffs},
+        },
+        inventory::Inventory,
+        world::get_rand,
+    };
+    use serde_jacl::de::from_str;
+
+    fn strategy() -> Strategy {
+        Strategy {
+            aggression: 1.0,
+            caution: 1.0,
+            low_health: 0.5,
+            energy_reserve: 0.0,
+            randomness: 0.0,
+        }
+    }
+
+    fn fighter(g: &GameData, speed: f64, abilities: &[(&str, &str)]) -> Combatant {
+        let mut stats = HashMap::new();
+        stats.insert(StatType::from("speed".to_string()), speed);
+        stats.insert(StatType::from("evasion".to_string()), 0.0);
+        stats.insert(StatType::from("crit_chance".to_string()), 0.0);
+        let mut combatant = Combatant::new(
+            "test".to_string(),
+            g,
+            &stats,
+            Buffs::new(),
+            Inventory::new(),
+        );
+        for (name, jacl) in abilities {
+            let deser: AbilityDeser = from_str(jacl).unwrap();
+            let ability = deser
+                .into_ability(
+                    &g.dmg,
+                    &g.items.keys().cloned().collect(),
+                    &g.statuses.keys().cloned().collect(),
+                )
+                .unwrap();
+            combatant.abilities.insert(
+                name.to_string(),
+                BattleAbility {
+                    source: None,
+                    ability,
+                },
+            );
+        }
+        combatant
+    }
+
+    fn pick(battle: &Battle, g: &GameData) -> Choice {
+        choose(battle, g, 0, &strategy(), &mut get_rand(0)).unwrap()
+    }
+
+    #[test]
+    fn picks_the_most_damage() {
+        let g = pvp();
+        let user = fighter(
+            &g,
+            20.0,
+            &[
+                ("poke", r#"damage : { "physical" : 1 }"#),
+                ("smash", r#"damage : { "physical" : 5 }"#),
+                ("sear", r#"damage : { "heat" : 4 }"#),
+            ],
+        );
+        let mut frozen = fighter(&g, 1.0, &[]);
+        frozen.own_alignments = vec!["frozen".to_string().into()];
+        let battle = Battle::new(&g, 0, vec![vec![user.clone()], vec![fighter(&g, 1.0, &[])]]);
+        assert_eq!(pick(&battle.unwrap(), &g), Choice::ability_on("smash", 1));
+        // heat does 1.5 times the damage to frozen things
+        let battle = Battle::new(&g, 0, vec![vec![user], vec![frozen]]).unwrap();
+        assert_eq!(pick(&battle, &g), Choice::ability_on("sear", 1));
+    }
+
+    #[test]
+    fn finishes_off_whoever_it_can() {
+        let g = pvp();
+        let user = fighter(&g, 20.0, &[("poke", r#"damage : { "physical" : 3 }"#)]);
+        let healthy = fighter(&g, 1.0, &[]);
+        let mut hurt = fighter(&g, 1.0, &[]);
+        hurt.health = 2.0;
+        let battle = Battle::new(&g, 0, vec![vec![user], vec![healthy, hurt]]).unwrap();
+        assert_eq!(pick(&battle, &g), Choice::ability_on("poke", 2));
+    }
+
+    #[test]
+    fn heals_once_its_hurt_but_never_kills_itself() {
+        let g = pvp();
+        let abilities = [
+            ("poke", r#"damage : { "physical" : 2 }"#),
+            ("patch", r#"targets : "self" health : 10"#),
+            ("blast", r#"damage : { "physical" : 50 } health : -15"#),
+        ];
+        let mut user = fighter(&g, 20.0, &abilities);
+        let battle = Battle::new(&g, 0, vec![vec![user.clone()], vec![fighter(&g, 1.0, &[])]]);
+        assert_eq!(pick(&battle.unwrap(), &g), Choice::ability_on("blast", 1));
+        user.health = 5.0;
+        let battle = Battle::new(&g, 0, vec![vec![user], vec![fighter(&g, 1.0, &[])]]).unwrap();
+        assert_eq!(pick(&battle, &g), Choice::ability("patch"));
+    }
+
+    #[test]
+    fn only_chooses_on_its_own_turn() {
+        let g = pvp();
+        let user = fighter(&g, 1.0, &[("poke", r#"damage : { "physical" : 1 }"#)]);
+        let enemy = fighter(&g, 20.0, &[]);
+        let battle = Battle::new(&g, 0, vec![vec![user], vec![enemy]]).unwrap();
+        assert_eq!(choose(&battle, &g, 0, &strategy(), &mut get_rand(0)), None);
+        assert!(choose_for_mob(&battle, &g, 1, &mut get_rand(0)).is_none());
+    }
+}
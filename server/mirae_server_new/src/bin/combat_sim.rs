@@ -0,0 +1,48 @@
+use anyhow::{anyhow, Result};
+use mirae_server_new::{
+    gamedata::gamedata::GameMode,
+    sim::{simulate, Side, SimConfig},
+};
+use serde_jacl::de::from_str;
+use std::{env, fs};
+
+const USAGE: &str = "usage: combat_sim [--gamemode <path>] [--battles <n>] [--seed <n>] [--max-turns <n>] [--turn-secs <n>] [--save-replays <dir>] <side> <side>
+where a side is one of mob:<name>, item:<name> or loadout:<path>";
+
+fn main() -> Result<()> {
+    let mut gamemode = "pvp/gamemode.jacl".to_string();
+    let mut config = SimConfig {
+        battles: 1000,
+        seed: 0,
+        max_turns: 500,
+        turn_secs: 5.0,
+        replay_dir: None,
+    };
+    let mut sides = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!(format!("{} needs a value\n{}", arg, USAGE)));
+        match arg.as_str() {
+            "--gamemode" => gamemode = value()?,
+            "--battles" => config.battles = value()?.parse()?,
+            "--seed" => config.seed = value()?.parse()?,
+            "--max-turns" => config.max_turns = value()?.parse()?,
+            "--turn-secs" => config.turn_secs = value()?.parse()?,
+            "--save-replays" => config.replay_dir = Some(value()?),
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => sides.push(arg),
+        }
+    }
+    if sides.len() != 2 {
+        return Err(anyhow!(USAGE));
+    }
+
+    let m: GameMode = from_str(&fs::read_to_string(&gamemode)?)?;
+    let g = m.into_gamedata()?;
+    let sides = [Side::parse(&sides[0], &g)?, Side::parse(&sides[1], &g)?];
+    print!("{}", simulate(&g, &sides, &config)?);
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use anyhow::{anyhow, Result};
+use mirae_server_new::{
+    battle::{
+        listing::{list_abilities, list_buffs, summary},
+        narrate::narrate,
+        replay::Replay,
+    },
+    gamedata::gamedata::GameMode,
+};
+use serde_jacl::de::from_str;
+use std::{env, fs};
+
+const USAGE: &str = "usage: replay [--gamemode <path>] <replay.json>";
+
+fn main() -> Result<()> {
+    let mut gamemode = "pvp/gamemode.jacl".to_string();
+    let mut path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--gamemode" => {
+                gamemode = args
+                    .next()
+                    .ok_or_else(|| anyhow!(format!("--gamemode needs a value\n{}", USAGE)))?
+            }
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.ok_or_else(|| anyhow!(USAGE))?;
+
+    let m: GameMode = from_str(&fs::read_to_string(&gamemode)?)?;
+    let g = m.into_gamedata()?;
+    let battle = Replay::load_file(&path)?.play(&g)?;
+    for line in narrate(&battle, &g) {
+        println!("{}", line);
+    }
+    println!();
+    for (id, c) in battle.combatants().iter().enumerate() {
+        println!("{}", summary(c));
+        for ability in list_abilities(&battle, id) {
+            println!("  {}", ability);
+        }
+        for buff in list_buffs(&battle, id) {
+            println!("  buff: {}", buff);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,364 @@
+use crate::{
+    gamedata::{
+        gamedata::{GameData, ItemName, SlotName},
+        item::Buffs,
+        set::{ItemSet, SetBonus},
+    },
+    inventory::{Inventory, ItemInstance},
+};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// one equipped item. Unique items keep their instance, so their durability
+/// and affixes come along with them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Equipped {
+    pub item: ItemName,
+    pub instance: Option<ItemInstance>,
+}
+
+impl Equipped {
+    /// the item's buffs, along with its affixes' if it's an instance.
+    pub fn buffs(&self, g: &GameData) -> Buffs {
+        match &self.instance {
+            Some(instance) => instance.buffs(g),
+            None => g
+                .items
+                .get(&self.item)
+                .map(|i| i.buffs.clone())
+                .unwrap_or_else(Buffs::new),
+        }
+    }
+}
+
+/// everything someone has equipped, by slot. Equipped items are taken out of
+/// their inventory, and put back when they're unequipped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Equipment {
+    slots: HashMap<SlotName, Vec<Equipped>>,
+}
+
+impl Default for Equipment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Equipment {
+    pub fn new() -> Self {
+        Equipment {
+            slots: HashMap::new(),
+        }
+    }
+
+    /// what's in `slot`, in the order it was equipped.
+    pub fn slot(&self, slot: &SlotName) -> &[Equipped] {
+        self.slots.get(slot).map(|s| s.as_slice()).unwrap_or(&[])
+    }
+
+    /// everything equipped, in slot order.
+    pub fn equipped(&self) -> Vec<(&SlotName, &Equipped)> {
+        let mut slots: Vec<&SlotName> = self.slots.keys().collect();
+        slots.sort();
+        slots
+            .into_iter()
+            .flat_map(|slot| self.slots[slot].iter().map(move |e| (slot, e)))
+            .collect()
+    }
+
+    /// every set with a piece equipped, and how many different pieces of it
+    /// are equipped, in name order.
+    pub fn sets<'g>(&self, g: &'g GameData) -> Vec<(&'g ItemSet, u64)> {
+        let worn: Vec<&ItemName> = self.equipped().into_iter().map(|(_, e)| &e.item).collect();
+        let mut sets: Vec<(&ItemSet, u64)> = g
+            .sets
+            .values()
+            .map(|s| (s, s.items.iter().filter(|i| worn.contains(i)).count() as u64))
+            .filter(|(_, n)| *n > 0)
+            .collect();
+        sets.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+        sets
+    }
+
+    /// every set bonus that enough pieces are equipped for.
+    pub fn set_bonuses<'g>(&self, g: &'g GameData) -> Vec<(&'g ItemSet, &'g SetBonus)> {
+        self.sets(g)
+            .into_iter()
+            .flat_map(|(set, worn)| set.bonuses(worn).map(move |b| (set, b)))
+            .collect()
+    }
+
+    /// the buffs from everything equipped, and from any set bonuses,
+    /// multiplied together.
+    pub fn buffs(&self, g: &GameData) -> Buffs {
+        let mut buffs = Buffs::new();
+        for (_, equipped) in self.equipped() {
+            buffs.stack(&equipped.buffs(g));
+        }
+        for (_, bonus) in self.set_bonuses(g) {
+            buffs.stack(&bonus.buffs);
+        }
+        buffs
+    }
+
+    /// equip `item` from `inventory`. For unique items, `instance` picks which
+    /// one to equip, and a plain copy is used if it's left out.
+    pub fn equip(
+        &mut self,
+        g: &GameData,
+        inventory: &mut Inventory,
+        item: &ItemName,
+        instance: Option<u64>,
+    ) -> Result<()> {
+        let def = g
+            .items
+            .get(item)
+            .ok_or_else(|| anyhow!(format!("{:?} is not an item", item)))?;
+        let slot = match &def.slot {
+            Some(slot) if def.wearable || def.equipable => slot.clone(),
+            _ => return Err(anyhow!(format!("{} can't be equipped", item.as_str()))),
+        };
+        let room = *g
+            .slots
+            .get(&slot)
+            .ok_or_else(|| anyhow!(format!("{:?} is not an equipment slot", slot)))?;
+        let used = self.slot(&slot).len() as u64;
+        if used >= room {
+            return Err(anyhow!(format!(
+                "there's no room in {} ({} of {} used), unequip something first",
+                slot.as_str(),
+                used,
+                room
+            )));
+        }
+        let instance = match instance {
+            Some(id) => {
+                match inventory.instance(id) {
+                    Some(i) if &i.item == item => {}
+                    Some(i) => {
+                        return Err(anyhow!(format!(
+                            "item {} is {}, not {}",
+                            id,
+                            i.item.as_str(),
+                            item.as_str()
+                        )))
+                    }
+                    None => return Err(anyhow!(format!("you don't have an item with id {}", id))),
+                }
+                Some(inventory.take_instance(id)?)
+            }
+            None => {
+                if inventory.items().get(item).cloned().unwrap_or(0) == 0 {
+                    return Err(anyhow!(format!(
+                        "you don't have a plain {} to equip",
+                        item.as_str()
+                    )));
+                }
+                inventory.change(item.clone(), -1)?;
+                None
+            }
+        };
+        self.slots.entry(slot).or_default().push(Equipped {
+            item: item.clone(),
+            instance,
+        });
+        Ok(())
+    }
+
+    /// put `item` back in `inventory`. For unique items, `instance` picks which
+    /// one to unequip, and a plain copy is unequipped if it's left out.
+    /// Nothing changes if there's no room for it.
+    pub fn unequip(
+        &mut self,
+        inventory: &mut Inventory,
+        item: &ItemName,
+        instance: Option<u64>,
+    ) -> Result<()> {
+        let (slot, i) = self
+            .slots
+            .iter()
+            .find_map(|(slot, equipped)| {
+                equipped
+                    .iter()
+                    .position(|e| {
+                        &e.item == item && e.instance.as_ref().map(|i| i.id) == instance
+                    })
+                    .map(|i| (slot.clone(), i))
+            })
+            .ok_or_else(|| anyhow!(format!("{} isn't equipped", item.as_str())))?;
+        let mut delta = HashMap::new();
+        delta.insert(item.clone(), 1);
+        inventory.check(&delta)?;
+        let equipped = self.slots.get_mut(&slot).unwrap().remove(i);
+        match equipped.instance {
+            Some(instance) => {
+                inventory.add_instance(instance)?;
+            }
+            None => {
+                inventory.change(item.clone(), 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        gamedata::gamedata::{pvp, StatType},
+        inventory::Limits,
+        world::get_rand,
+    };
+
+    fn n(name: &str) -> ItemName {
+        ItemName::from(name.to_string())
+    }
+
+    fn slot(name: &str) -> SlotName {
+        SlotName::from(name.to_string())
+    }
+
+    fn inventory(g: &GameData, items: &[(&str, u64)]) -> Inventory {
+        let items = items.iter().map(|(k, v)| (n(k), *v)).collect();
+        Inventory::rolled(g, &items, &mut get_rand(0)).unwrap()
+    }
+
+    #[test]
+    fn slots_only_hold_so_much() {
+        let g = pvp();
+        let mut inv = inventory(
+            &g,
+            &[
+                ("orange", 2),
+                ("trump_orange", 1),
+                ("sentient_trump_orange", 1),
+            ],
+        );
+        let mut equipment = Equipment::new();
+        equipment.equip(&g, &mut inv, &n("orange"), None).unwrap();
+        equipment
+            .equip(&g, &mut inv, &n("trump_orange"), None)
+            .unwrap();
+        let err = equipment
+            .equip(&g, &mut inv, &n("sentient_trump_orange"), None)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "there's no room in trinket (2 of 2 used), unequip something first"
+        );
+        assert_eq!(inv.get(&n("orange")), 1);
+        assert_eq!(inv.get(&n("trump_orange")), 0);
+        assert_eq!(inv.get(&n("sentient_trump_orange")), 1);
+        let worn: Vec<&ItemName> = equipment
+            .slot(&slot("trinket"))
+            .iter()
+            .map(|e| &e.item)
+            .collect();
+        assert_eq!(worn, vec![&n("orange"), &n("trump_orange")]);
+    }
+
+    #[test]
+    fn only_equips_what_can_be_equipped_and_is_there() {
+        let g = pvp();
+        let mut inv = inventory(&g, &[("wood", 1), ("sonic_chops", 1)]);
+        let mut equipment = Equipment::new();
+        assert!(equipment.equip(&g, &mut inv, &n("wood"), None).is_err());
+        assert!(equipment
+            .equip(&g, &mut inv, &n("sonic_chops"), None)
+            .is_err());
+        assert!(equipment.equip(&g, &mut inv, &n("heghead"), None).is_err());
+        assert!(equipment.equip(&g, &mut inv, &n("nothing"), None).is_err());
+        assert!(equipment.equipped().is_empty());
+        assert_eq!(inv.get(&n("wood")), 1);
+    }
+
+    #[test]
+    fn unique_items_keep_their_instance() {
+        let g = pvp();
+        let mut inv = inventory(&g, &[("iron_sword", 2), ("orange", 1)]);
+        let mut equipment = Equipment::new();
+        let picked = inv.instances()[1].clone();
+        // there are only instances, not plain copies
+        assert!(equipment
+            .equip(&g, &mut inv, &n("iron_sword"), None)
+            .is_err());
+        assert!(equipment
+            .equip(&g, &mut inv, &n("orange"), Some(picked.id))
+            .is_err());
+        assert!(equipment
+            .equip(&g, &mut inv, &n("iron_sword"), Some(1000))
+            .is_err());
+        equipment
+            .equip(&g, &mut inv, &n("iron_sword"), Some(picked.id))
+            .unwrap();
+        assert!(inv.instance(picked.id).is_none());
+        assert_eq!(
+            equipment.slot(&slot("hand"))[0].instance.as_ref(),
+            Some(&picked)
+        );
+        assert!(equipment.unequip(&mut inv, &n("iron_sword"), None).is_err());
+        equipment
+            .unequip(&mut inv, &n("iron_sword"), Some(picked.id))
+            .unwrap();
+        assert_eq!(inv.instance(picked.id), Some(&picked));
+        assert!(equipment.equipped().is_empty());
+    }
+
+    #[test]
+    fn unequipping_needs_room() {
+        let g = pvp();
+        let mut inv = inventory(&g, &[("orange", 1), ("iron", 5)]);
+        let mut equipment = Equipment::new();
+        equipment.equip(&g, &mut inv, &n("orange"), None).unwrap();
+        // five iron at 2 each is all it can hold
+        inv.set_limits(Some(Limits {
+            carry: g.carry.clone(),
+            capacity: Some(10.0),
+        }));
+        assert!(equipment.unequip(&mut inv, &n("orange"), None).is_err());
+        assert_eq!(equipment.slot(&slot("trinket")).len(), 1);
+        inv.change(n("iron"), -1).unwrap();
+        equipment.unequip(&mut inv, &n("orange"), None).unwrap();
+        assert_eq!(inv.get(&n("orange")), 1);
+        assert!(equipment.unequip(&mut inv, &n("orange"), None).is_err());
+    }
+
+    #[test]
+    fn sets_count_different_pieces() {
+        let g = pvp();
+        let mut inv = inventory(&g, &[("heghead", 1), ("sonic's_shoes", 1), ("orange", 2)]);
+        let mut equipment = Equipment::new();
+        equipment.equip(&g, &mut inv, &n("heghead"), None).unwrap();
+        let sets: Vec<(&str, u64)> = equipment
+            .sets(&g)
+            .iter()
+            .map(|(s, worn)| (s.name.as_str(), *worn))
+            .collect();
+        assert_eq!(sets, vec![("sonic", 1)]);
+        assert!(equipment.set_bonuses(&g).is_empty());
+        let speed = StatType::from("speed".to_string());
+        assert_eq!(equipment.buffs(&g).stat(&speed), 1.5);
+
+        equipment
+            .equip(&g, &mut inv, &n("sonic's_shoes"), None)
+            .unwrap();
+        let bonuses = equipment.set_bonuses(&g);
+        assert_eq!(bonuses.len(), 1);
+        assert_eq!(bonuses[0].0.name.as_str(), "sonic");
+        assert!(bonuses[0].1.abilities.contains_key("spin_dash"));
+        // 1.5 from the head, 2 from the shoes and 1.25 from the set
+        assert_eq!(equipment.buffs(&g).stat(&speed), 3.75);
+
+        // two of the same piece only count once
+        equipment.equip(&g, &mut inv, &n("orange"), None).unwrap();
+        equipment.equip(&g, &mut inv, &n("orange"), None).unwrap();
+        assert_eq!(equipment.sets(&g).len(), 2);
+        assert_eq!(equipment.set_bonuses(&g).len(), 1);
+        equipment
+            .unequip(&mut inv, &n("sonic's_shoes"), None)
+            .unwrap();
+        assert!(equipment.set_bonuses(&g).is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use super::{gamedata::BlockName, serde_defaults::*};
+use super::{gamedata::{Alignment, BlockName}, serde_defaults::*};
 use crate::rgb::RGB;
 use serde::Deserialize;
 
@@ -43,15 +43,23 @@ pub struct BlockDeser {
     z_passable: bool,
     #[serde(default = "LightingDeser::invalid")]
     light: LightingDeser,
+    #[serde(default = "default_vec")]
+    alignments: Vec<String>,
 }
 
 impl BlockDeser {
+    pub fn alignments(&self) -> &[String] {
+        &self.alignments
+    }
+
     pub fn into_block(self, name: BlockName) -> Block {
         Block {
             name,
             color: self.color,
             mob_spawn_chance: self.mob_spawn_chance,
             solid: self.solid,
+            z_passable: self.z_passable,
+            alignments: self.alignments.into_iter().map(Alignment::from).collect(),
             light: if self.light.intensity < 0.0
                 || self.light.intensity > 1.0
                 || self.light.falloff < 0.0
@@ -79,5 +87,7 @@ pub struct Block {
     pub color: RGB,
     pub mob_spawn_chance: f64,
     pub solid: bool,
+    pub z_passable: bool,
+    pub alignments: Vec<Alignment>,
     pub light: Option<Lighting>,
 }
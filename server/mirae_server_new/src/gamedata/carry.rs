@@ -0,0 +1,123 @@
+use super::{
+    gamedata::{ItemName, StatType},
+    item::Item,
+    serde_defaults::*,
+};
+use crate::inventory::Limits;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+fn default_overflow() -> String {
+    "reject".to_string()
+}
+
+/// the gamemode's rules for how much an inventory can hold. Everything is
+/// unlimited by default.
+#[derive(Deserialize, Debug)]
+pub struct CarryDeser {
+    /// how many different items an inventory can hold, or 0 for no limit.
+    #[serde(default = "default_u64")]
+    slots: u64,
+    /// the stat that limits how much weight someone can carry, if any.
+    #[serde(default = "default_string")]
+    capacity_stat: String,
+    /// what happens to items that don't fit: "reject" or "drop".
+    #[serde(default = "default_overflow")]
+    overflow: String,
+}
+
+impl CarryDeser {
+    pub fn into_carry(
+        self,
+        stat_types: &HashSet<StatType>,
+        items: &HashMap<ItemName, Item>,
+    ) -> Result<Carry> {
+        let capacity_stat = if self.capacity_stat.is_empty() {
+            None
+        } else {
+            let stat = StatType::from(self.capacity_stat);
+            if !stat_types.contains(&stat) {
+                return Err(anyhow!(format!(
+                    "capacity_stat {:?} is not a stat",
+                    stat
+                )));
+            }
+            Some(stat)
+        };
+        let overflow = match self.overflow.as_str() {
+            "reject" => Overflow::Reject,
+            "drop" => Overflow::Drop,
+            other => {
+                return Err(anyhow!(format!(
+                    "overflow must be \"reject\" or \"drop\", got {:?}",
+                    other
+                )))
+            }
+        };
+        Ok(Carry {
+            slots: if self.slots == 0 { None } else { Some(self.slots) },
+            capacity_stat,
+            overflow,
+            stacks: Arc::new(
+                items
+                    .values()
+                    .filter_map(|i| i.stack.map(|s| (i.name.clone(), s)))
+                    .collect(),
+            ),
+            weights: Arc::new(
+                items
+                    .values()
+                    .filter(|i| i.weight > 0.0)
+                    .map(|i| (i.name.clone(), i.weight))
+                    .collect(),
+            ),
+        })
+    }
+}
+
+/// what happens to items that won't fit in an inventory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Overflow {
+    /// the whole change fails.
+    Reject,
+    /// whatever fits goes in, and the rest is dropped on the ground.
+    Drop,
+}
+
+/// how much an inventory can hold, as set by the gamemode. Cheap to clone, so
+/// every limited inventory keeps its own copy.
+#[derive(Debug, Clone)]
+pub struct Carry {
+    pub slots: Option<u64>,
+    pub capacity_stat: Option<StatType>,
+    pub overflow: Overflow,
+    stacks: Arc<HashMap<ItemName, u64>>,
+    weights: Arc<HashMap<ItemName, f64>>,
+}
+
+impl Carry {
+    /// the most of `item` that fits in one inventory, if there's a limit.
+    pub fn stack(&self, item: &ItemName) -> Option<u64> {
+        self.stacks.get(item).cloned()
+    }
+
+    pub fn weight(&self, item: &ItemName) -> f64 {
+        *self.weights.get(item).unwrap_or(&0.0)
+    }
+
+    /// the limits on the inventory of someone with the given stats. Their
+    /// carry capacity is their capacity stat, if the gamemode has one.
+    pub fn limits(&self, stats: &HashMap<StatType, f64>) -> Limits {
+        Limits {
+            carry: self.clone(),
+            capacity: self
+                .capacity_stat
+                .as_ref()
+                .map(|s| *stats.get(s).unwrap_or(&0.0)),
+        }
+    }
+}
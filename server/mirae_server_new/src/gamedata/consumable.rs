@@ -0,0 +1,136 @@
+use super::{
+    gamedata::{DmgType, ItemName, StatType},
+    item::{Buffs, BuffsDeser},
+    serde_defaults::*,
+    status::Stacking,
+};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::HashSet;
+
+fn default_stacking() -> String {
+    "refresh".to_string()
+}
+
+fn default_max_stacks() -> u64 {
+    1
+}
+
+/// what happens when an item is consumed. Anything left out does nothing.
+#[derive(Deserialize, Debug)]
+pub struct ConsumableDeser {
+    #[serde(default = "default_f64")]
+    health: f64,
+    #[serde(default = "default_f64")]
+    energy: f64,
+    /// buffs that last for a while after the item is consumed.
+    #[serde(default = "BuffsDeser::new")]
+    buffs: BuffsDeser,
+    /// how many of the consumer's turns the buffs last for in battle, or 0
+    /// for no limit on turns.
+    #[serde(default = "default_u64")]
+    turns: u64,
+    /// how many seconds the buffs last for, or 0 for no limit on time.
+    #[serde(default = "default_f64")]
+    secs: f64,
+    /// what consuming another one does while the buffs are still going.
+    #[serde(default = "default_stacking")]
+    stacking: String,
+    #[serde(default = "default_max_stacks")]
+    max_stacks: u64,
+}
+
+impl Default for ConsumableDeser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConsumableDeser {
+    pub fn new() -> Self {
+        ConsumableDeser {
+            health: 0.0,
+            energy: 0.0,
+            buffs: BuffsDeser::new(),
+            turns: 0,
+            secs: 0.0,
+            stacking: default_stacking(),
+            max_stacks: default_max_stacks(),
+        }
+    }
+
+    /// the consumable, or `None` if consuming the item wouldn't do anything.
+    pub fn into_consumable(
+        self,
+        name: &ItemName,
+        dmg_types: &HashSet<DmgType>,
+        stat_types: &HashSet<StatType>,
+    ) -> Result<Option<Consumable>> {
+        let buffs = self.buffs.into_buffs(dmg_types, stat_types)?;
+        if !self.secs.is_finite() || self.secs < 0.0 {
+            return Err(anyhow!(format!(
+                "the buffs from consuming {:?} can't last {} seconds",
+                name, self.secs
+            )));
+        }
+        let timed = if buffs.is_empty() {
+            if self.turns > 0 || self.secs > 0.0 {
+                return Err(anyhow!(format!(
+                    "consuming {:?} has a duration, but no buffs",
+                    name
+                )));
+            }
+            None
+        } else {
+            if self.turns == 0 && self.secs == 0.0 {
+                return Err(anyhow!(format!(
+                    "the buffs from consuming {:?} need to last for some turns or seconds",
+                    name
+                )));
+            }
+            if self.max_stacks == 0 {
+                return Err(anyhow!(format!(
+                    "the buffs from consuming {:?} must allow at least 1 stack",
+                    name
+                )));
+            }
+            Some(TimedBuff {
+                buffs,
+                turns: if self.turns == 0 { None } else { Some(self.turns) },
+                secs: if self.secs == 0.0 { None } else { Some(self.secs) },
+                stacking: Stacking::parse(&self.stacking).map_err(|e| {
+                    anyhow!(format!("consuming {:?} has {}", name, e))
+                })?,
+                max_stacks: self.max_stacks,
+            })
+        };
+        if self.health == 0.0 && self.energy == 0.0 && timed.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Consumable {
+            health: self.health,
+            energy: self.energy,
+            timed,
+        }))
+    }
+}
+
+/// buffs from consuming an item, which run out after a number of the
+/// consumer's turns, a number of seconds, or whichever comes first.
+#[derive(Debug, Clone)]
+pub struct TimedBuff {
+    /// the buffs for each stack.
+    pub buffs: Buffs,
+    pub turns: Option<u64>,
+    pub secs: Option<f64>,
+    pub stacking: Stacking,
+    pub max_stacks: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Consumable {
+    /// health and energy gained (or lost) straight away.
+    pub health: f64,
+    pub energy: f64,
+    pub timed: Option<TimedBuff>,
+}
@@ -0,0 +1,69 @@
+use super::{
+    gamedata::{Alignment, DmgType},
+    serde_defaults::*,
+};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Deserialize, Debug)]
+pub struct DmgTypeDeser {
+    #[serde(default = "default_hmap")]
+    against: HashMap<String, f64>,
+    #[serde(default = "default_f64")]
+    penetration: f64,
+    #[serde(default = "default_hmap")]
+    convert: HashMap<String, f64>,
+}
+
+impl DmgTypeDeser {
+    pub fn into_dmg_interaction(
+        self,
+        name: &DmgType,
+        dmg_types: &HashSet<DmgType>,
+        alignments: &HashSet<Alignment>,
+    ) -> Result<DmgInteraction> {
+        if self.penetration < 0.0 || self.penetration > 1.0 {
+            return Err(anyhow!(format!(
+                "penetration of {:?} must be between 0 and 1, got {}",
+                name, self.penetration
+            )));
+        }
+        let convert = map(self.convert, dmg_types)?;
+        if convert.contains_key(name) {
+            return Err(anyhow!(format!("{:?} cannot convert into itself", name)));
+        }
+        let total: f64 = convert.values().sum();
+        if convert.values().any(|x| *x < 0.0) || total > 1.0 {
+            return Err(anyhow!(format!(
+                "{:?} must convert between 0 and all of its damage, got {}",
+                name, total
+            )));
+        }
+        for v in self.against.values() {
+            if *v < 0.0 {
+                return Err(anyhow!(format!(
+                    "{:?} has a negative multiplier {}",
+                    name, v
+                )));
+            }
+        }
+        Ok(DmgInteraction {
+            against: map(self.against, alignments)?,
+            penetration: self.penetration,
+            convert,
+        })
+    }
+}
+
+/// how a damage type interacts with everything else.
+#[derive(Debug, Clone)]
+pub struct DmgInteraction {
+    /// damage multiplier against defenders with each alignment.
+    pub against: HashMap<Alignment, f64>,
+    /// proportion of the defender's resistance to this type that is ignored.
+    pub penetration: f64,
+    /// proportion of this damage that turns into other damage types before it
+    /// hits.
+    pub convert: HashMap<DmgType, f64>,
+}
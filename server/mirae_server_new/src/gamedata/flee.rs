@@ -0,0 +1,135 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+fn default_base_chance() -> f64 {
+    0.5
+}
+
+fn default_speed_factor() -> f64 {
+    0.5
+}
+
+fn default_min_chance() -> f64 {
+    0.05
+}
+
+fn default_max_chance() -> f64 {
+    0.95
+}
+
+fn default_max_distance() -> f64 {
+    5.0
+}
+
+/// the gamemode's rules for running away from battles.
+#[derive(Deserialize, Debug)]
+pub struct FleeDeser {
+    #[serde(default = "default_base_chance")]
+    base_chance: f64,
+    #[serde(default = "default_speed_factor")]
+    speed_factor: f64,
+    #[serde(default = "default_min_chance")]
+    min_chance: f64,
+    #[serde(default = "default_max_chance")]
+    max_chance: f64,
+    #[serde(default = "default_max_distance")]
+    max_distance: f64,
+}
+
+impl FleeDeser {
+    pub fn into_flee(self) -> Result<Flee> {
+        for (name, chance) in &[
+            ("base_chance", self.base_chance),
+            ("min_chance", self.min_chance),
+            ("max_chance", self.max_chance),
+        ] {
+            if *chance < 0.0 || *chance > 1.0 {
+                return Err(anyhow!(format!(
+                    "{} must be between 0 and 1, got {}",
+                    name, chance
+                )));
+            }
+        }
+        if self.min_chance > self.max_chance {
+            return Err(anyhow!(format!(
+                "min_chance ({}) can't be more than max_chance ({})",
+                self.min_chance, self.max_chance
+            )));
+        }
+        if !self.speed_factor.is_finite() {
+            return Err(anyhow!(format!(
+                "speed_factor can't be {}",
+                self.speed_factor
+            )));
+        }
+        if self.max_distance < 1.0 {
+            return Err(anyhow!(format!(
+                "max_distance must be at least 1, got {}",
+                self.max_distance
+            )));
+        }
+        Ok(Flee {
+            base_chance: self.base_chance,
+            speed_factor: self.speed_factor,
+            min_chance: self.min_chance,
+            max_chance: self.max_chance,
+            max_distance: self.max_distance,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Flee {
+    /// the chance of getting away from someone just as fast.
+    pub base_chance: f64,
+    /// how much being faster (or slower) than the fastest enemy changes the
+    /// chance. The speed difference is scaled to between -1 and 1 first.
+    pub speed_factor: f64,
+    pub min_chance: f64,
+    pub max_chance: f64,
+    /// how far away someone who gets away can end up, in tiles.
+    pub max_distance: f64,
+}
+
+impl Flee {
+    /// the chance of someone with speed `speed` getting away from enemies
+    /// whose fastest has speed `fastest`.
+    pub fn chance(&self, speed: f64, fastest: f64) -> f64 {
+        let (speed, fastest) = (speed.max(0.0), fastest.max(0.0));
+        let difference = if speed + fastest > 0.0 {
+            (speed - fastest) / (speed + fastest)
+        } else {
+            0.0
+        };
+        (self.base_chance + self.speed_factor * difference).clamp(self.min_chance, self.max_chance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_jacl::de::from_str;
+
+    fn flee(jacl: &str) -> Result<Flee> {
+        from_str::<FleeDeser>(jacl).unwrap().into_flee()
+    }
+
+    #[test]
+    fn faster_runners_get_away_more() {
+        let f = flee("speed_factor : 0.6 min_chance : 0.1 max_chance : 0.9").unwrap();
+        assert_eq!(f.chance(10.0, 10.0), 0.5);
+        assert!((f.chance(30.0, 10.0) - 0.8).abs() < 1e-9);
+        assert!((f.chance(10.0, 30.0) - 0.2).abs() < 1e-9);
+        assert_eq!(f.chance(100.0, 1.0), 0.9);
+        assert_eq!(f.chance(1.0, 100.0), 0.1);
+        assert_eq!(f.chance(0.0, 0.0), 0.5);
+    }
+
+    #[test]
+    fn rejects_nonsense() {
+        assert!(flee("base_chance : 1.5").is_err());
+        assert!(flee("min_chance : 0.6 max_chance : 0.4").is_err());
+        assert!(flee("max_distance : 0.5").is_err());
+        assert!(flee("max_distance : 1").is_ok());
+    }
+}
@@ -1,13 +1,14 @@
 use anyhow::{anyhow, Result};
 use bimap::BiMap;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_jacl::de::from_str;
 use std::{
     collections::{HashMap, HashSet},
     fs,
 };
-use super::{item::{Item, ItemDeser}, terrain::{BiomeDeser, TerrainDeser, Terrain, Biome}, mobtemplate::{MobTemplate, MobTemplateDeser}, block::{Block, BlockDeser}};
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+use crate::battle::effect::EffectRegistry;
+use super::{carry::{Carry, CarryDeser}, dmg::{DmgInteraction, DmgTypeDeser}, flee::{Flee, FleeDeser}, status::{Status, StatusDeser}, item::{Item, ItemDeser}, set::{ItemSet, ItemSetDeser}, terrain::{BiomeDeser, TerrainDeser, Terrain, Biome}, mobtemplate::{MobTemplate, MobTemplateDeser}, block::{Block, BlockDeser}};
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct DmgType(String);
 
 impl From<String> for DmgType {
@@ -17,6 +18,10 @@ impl From<String> for DmgType {
 }
 
 impl DmgType {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
     fn checked_from(s: String, g: &GameData) -> Result<Self> {
         let val = Self(s);
         if g.dmg.contains(&val) {
@@ -27,7 +32,32 @@ impl DmgType {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+/// a tag on mobs and blocks that damage types can be strong or weak against.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct Alignment(String);
+
+impl From<String> for Alignment {
+    fn from(s: String) -> Self {
+        Self(s)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct StatusName(String);
+
+impl From<String> for StatusName {
+    fn from(s: String) -> Self {
+        Self(s)
+    }
+}
+
+impl StatusName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct StatType(String);
 
 impl From<String> for StatType {
@@ -37,6 +67,10 @@ impl From<String> for StatType {
 }
 
 impl StatType {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
     fn checked_from(s: String, g: &GameData) -> Result<Self> {
         let val = Self(s);
         if g.stat.contains(&val) {
@@ -47,6 +81,38 @@ impl StatType {
     }
 }
 
+/// a place on the body that equipment goes, e.g. "head" or "hand".
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct SlotName(String);
+
+impl From<String> for SlotName {
+    fn from(s: String) -> Self {
+        Self(s)
+    }
+}
+
+impl SlotName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// a group of items that give bonuses when worn together.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct SetName(String);
+
+impl From<String> for SetName {
+    fn from(s: String) -> Self {
+        Self(s)
+    }
+}
+
+impl SetName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct StructureName(String);
 
@@ -87,7 +153,7 @@ impl BiomeName {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct ItemName(String);
 
 impl From<String> for ItemName {
@@ -97,6 +163,10 @@ impl From<String> for ItemName {
 }
 
 impl ItemName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
     fn checked_from(s: String, g: &GameData) -> Result<Self> {
         let val = Self(s);
         if g.items.contains_key(&val) {
@@ -107,7 +177,7 @@ impl ItemName {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct MobAction(String);
 
 impl From<String> for MobAction {
@@ -117,6 +187,10 @@ impl From<String> for MobAction {
 }
 
 impl MobAction {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
     fn checked_from(s: String, g: &GameData) -> Result<Self> {
         let val = Self(s);
         if g.mob_actions.contains(&val) {
@@ -127,7 +201,7 @@ impl MobAction {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct MobName(String);
 
 impl From<String> for MobName {
@@ -137,6 +211,10 @@ impl From<String> for MobName {
 }
 
 impl MobName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
     fn checked_from(s: String, g: &GameData) -> Result<Self> {
         let val = Self(s);
         if g.mob_templates.contains_key(&val) {
@@ -151,7 +229,7 @@ impl MobName {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct BlockName(String);
 
 impl From<String> for BlockName {
@@ -161,6 +239,10 @@ impl From<String> for BlockName {
 }
 
 impl BlockName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
     fn checked_from(s: String, g: &GameData) -> Result<Self> {
         let val = Self(s);
         if g.blocks.contains_key(&val) {
@@ -179,44 +261,115 @@ pub struct GameMode {
     structures: String,
     dmg: String,
     stat: String,
+    statuses: String,
     mob_actions: String,
     mobs: String,
     blocks: String,
+    flee: String,
+    carry: String,
+    slots: String,
+    sets: String,
 }
 
 impl GameMode {
+    /// load the gamemode with just the built in effects.
     pub fn into_gamedata(&self) -> Result<GameData> {
+        self.into_gamedata_with(EffectRegistry::new())
+    }
+
+    /// load the gamemode, with extra effects its abilities can use.
+    pub fn into_gamedata_with(&self, effects: EffectRegistry) -> Result<GameData> {
         let deser = GameDataDeser {
             terrain: from_str(&fs::read_to_string(&self.terrain)?)?,
             dmg: from_str(&fs::read_to_string(&self.dmg)?)?,
             stat: from_str(&fs::read_to_string(&self.stat)?)?,
+            statuses: from_str(&fs::read_to_string(&self.statuses)?)?,
             items: from_str(&fs::read_to_string(&self.items)?)?,
             mob_actions: from_str(&fs::read_to_string(&self.mob_actions)?)?,
             mob_templates: from_str(&fs::read_to_string(&self.mobs)?)?,
             blocks: from_str(&fs::read_to_string(&self.blocks)?)?,
             biomes: from_str(&fs::read_to_string(&self.biomes)?)?,
             structures: from_str(&fs::read_to_string(&self.structures)?)?,
+            flee: from_str(&fs::read_to_string(&self.flee)?)?,
+            carry: from_str(&fs::read_to_string(&self.carry)?)?,
+            slots: from_str(&fs::read_to_string(&self.slots)?)?,
+            sets: from_str(&fs::read_to_string(&self.sets)?)?,
         };
         let structures = deser
             .structures
             .into_iter()
             .map(|x| StructureName(x))
             .collect();
-        let dmg_types = deser.dmg.into_iter().map(|x| DmgType(x)).collect();
-        let stat_types = deser.stat.into_iter().map(|x| StatType(x)).collect();
+        let dmg_types: HashSet<DmgType> = deser.dmg.keys().map(|x| DmgType(x.clone())).collect();
+        let mut alignments = HashSet::new();
+        for mob in deser.mob_templates.values() {
+            alignments.extend(mob.alignments().iter().map(|x| Alignment(x.clone())));
+        }
+        for block in deser.blocks.values() {
+            alignments.extend(block.alignments().iter().map(|x| Alignment(x.clone())));
+        }
+        let mut dmg_interactions = HashMap::new();
+        for (name, v) in deser.dmg {
+            let name = DmgType(name);
+            let interaction = v.into_dmg_interaction(&name, &dmg_types, &alignments)?;
+            dmg_interactions.insert(name, interaction);
+        }
+        let status_names: HashSet<StatusName> =
+            deser.statuses.keys().map(|x| StatusName(x.clone())).collect();
+        let mut statuses = HashMap::new();
+        for (name, v) in deser.statuses {
+            let name = StatusName(name);
+            statuses.insert(
+                name.clone(),
+                v.into_status(name, &dmg_types, &status_names)?,
+            );
+        }
+        let stat_defaults: HashMap<StatType, f64> = deser
+            .stat
+            .into_iter()
+            .map(|(k, v)| (StatType(k), v))
+            .collect();
+        let stat_types = stat_defaults.keys().cloned().collect();
         let mob_actions = deser
             .mob_actions
             .into_iter()
             .map(|x| MobAction(x))
             .collect();
         let item_names = deser.items.keys().map(|x| ItemName(x.clone())).collect();
+        let mut slots = HashMap::new();
+        for (name, count) in deser.slots {
+            if count == 0 {
+                return Err(anyhow!(format!(
+                    "slot {:?} has to fit at least one item",
+                    name
+                )));
+            }
+            slots.insert(SlotName(name), count);
+        }
+        let slot_names = slots.keys().cloned().collect();
 
         let mut items = HashMap::new();
         for (name, v) in deser.items {
             let name = ItemName::from(name);
             items.insert(
                 name.clone(),
-                v.into_item(&dmg_types, &stat_types, &item_names, name)?,
+                v.into_item(
+                    &dmg_types,
+                    &stat_types,
+                    &item_names,
+                    &status_names,
+                    &slot_names,
+                    name,
+                )?,
+            );
+        }
+
+        let mut sets = HashMap::new();
+        for (name, v) in deser.sets {
+            let name = SetName(name);
+            sets.insert(
+                name.clone(),
+                v.into_item_set(name, &items, &slots, &dmg_types, &stat_types, &status_names)?,
             );
         }
 
@@ -225,7 +378,14 @@ impl GameMode {
             let name = MobName::from(name);
             mob_templates.insert(
                 name.clone(),
-                v.into_mobtemplate(&dmg_types, &item_names, &mob_actions, name)?,
+                v.into_mobtemplate(
+                    &dmg_types,
+                    &stat_types,
+                    &item_names,
+                    &status_names,
+                    &mob_actions,
+                    name,
+                )?,
             );
         }
 
@@ -256,18 +416,27 @@ impl GameMode {
         }
 
         let terrain = deser.terrain.into_terrain(&biome_names)?;
+        let flee = deser.flee.into_flee()?;
+        let carry = deser.carry.into_carry(&stat_types, &items)?;
 
-        GameData::new(
+        GameData::new(GameDataParts {
             terrain,
-            dmg_types,
-            stat_types,
+            dmg: dmg_types,
+            dmg_interactions,
+            stat_defaults,
+            statuses,
+            effects,
             items,
             mob_templates,
             mob_actions,
             blocks,
             structures,
             biomes,
-        )
+            flee,
+            carry,
+            slots,
+            sets,
+        })
     }
 }
 
@@ -275,19 +444,49 @@ impl GameMode {
 pub struct GameDataDeser {
     terrain: TerrainDeser,
     structures: Vec<String>,
-    dmg: Vec<String>,
-    stat: Vec<String>,
+    dmg: HashMap<String, DmgTypeDeser>,
+    stat: HashMap<String, f64>,
+    statuses: HashMap<String, StatusDeser>,
     items: HashMap<String, ItemDeser>,
     mob_actions: Vec<String>,
     mob_templates: HashMap<String, MobTemplateDeser>,
     blocks: HashMap<String, BlockDeser>,
     biomes: HashMap<String, BiomeDeser>,
+    flee: FleeDeser,
+    carry: CarryDeser,
+    slots: HashMap<String, u64>,
+    sets: HashMap<String, ItemSetDeser>,
+}
+
+/// everything a `GameData` is made from, once it's all been checked. The
+/// rest is worked out from these.
+pub struct GameDataParts {
+    pub terrain: Terrain,
+    pub dmg: HashSet<DmgType>,
+    pub dmg_interactions: HashMap<DmgType, DmgInteraction>,
+    pub stat_defaults: HashMap<StatType, f64>,
+    pub statuses: HashMap<StatusName, Status>,
+    pub effects: EffectRegistry,
+    pub items: HashMap<ItemName, Item>,
+    pub mob_templates: HashMap<MobName, MobTemplate>,
+    pub mob_actions: HashSet<MobAction>,
+    pub blocks: HashMap<BlockName, Block>,
+    pub structures: HashSet<StructureName>,
+    pub biomes: HashMap<BiomeName, Biome>,
+    pub flee: Flee,
+    pub carry: Carry,
+    pub slots: HashMap<SlotName, u64>,
+    pub sets: HashMap<SetName, ItemSet>,
 }
 
 pub struct GameData {
     pub terrain: Terrain,
     pub dmg: HashSet<DmgType>,
+    pub dmg_interactions: HashMap<DmgType, DmgInteraction>,
     pub stat: HashSet<StatType>,
+    pub stat_defaults: HashMap<StatType, f64>,
+    pub statuses: HashMap<StatusName, Status>,
+    pub effects: EffectRegistry,
     pub structures: HashSet<StructureName>,
     pub biomes: HashMap<BiomeName, Biome>,
     pub items: HashMap<ItemName, Item>,
@@ -298,20 +497,34 @@ pub struct GameData {
     pub blocks: HashMap<BlockName, Block>,
     pub block_id_map: BiMap<u8, BlockName>,
     pub max_block_id: u8,
+    pub flee: Flee,
+    pub carry: Carry,
+    /// every equipment slot, and how many items fit in it.
+    pub slots: HashMap<SlotName, u64>,
+    /// groups of items that give bonuses when they're worn together.
+    pub sets: HashMap<SetName, ItemSet>,
 }
 
 impl GameData {
-    pub fn new(
-        terrain: Terrain,
-        dmg: HashSet<DmgType>,
-        stat: HashSet<StatType>,
-        items: HashMap<ItemName, Item>,
-        mob_templates: HashMap<MobName, MobTemplate>,
-        mob_actions: HashSet<MobAction>,
-        blocks: HashMap<BlockName, Block>,
-        structures: HashSet<StructureName>,
-        biomes: HashMap<BiomeName, Biome>,
-    ) -> Result<Self> {
+    pub fn new(parts: GameDataParts) -> Result<Self> {
+        let GameDataParts {
+            terrain,
+            dmg,
+            dmg_interactions,
+            stat_defaults,
+            statuses,
+            effects,
+            items,
+            mob_templates,
+            mob_actions,
+            blocks,
+            structures,
+            biomes,
+            flee,
+            carry,
+            slots,
+            sets,
+        } = parts;
         let mut mob_id_map: BiMap<u16, MobName> = BiMap::new();
         let mut max_mob_id = 0;
         for name in mob_templates.keys() {
@@ -338,10 +551,14 @@ impl GameData {
             max_block_id += 1
         }
 
-        Ok(GameData {
+        let g = GameData {
             terrain,
             dmg,
-            stat,
+            dmg_interactions,
+            stat: stat_defaults.keys().cloned().collect(),
+            stat_defaults,
+            statuses,
+            effects,
             items,
             mob_templates,
             mob_actions,
@@ -352,7 +569,47 @@ impl GameData {
             biomes,
             max_block_id,
             max_mob_id,
-        })
+            flee,
+            carry,
+            slots,
+            sets,
+        };
+        g.validate_effects()?;
+        Ok(g)
+    }
+
+    /// make sure every effect used by an ability exists, and is given a
+    /// magnitude it can work with.
+    fn validate_effects(&self) -> Result<()> {
+        // every ability's health and stun are applied through these
+        for effect in &["heal", "stun"] {
+            self.effects
+                .get(effect)
+                .map_err(|e| anyhow!(format!("{}, which every gamemode needs", e)))?;
+        }
+        let abilities = self
+            .items
+            .values()
+            .flat_map(|i| i.abilities.iter().map(move |(n, a)| (i.name.as_str(), n, a)))
+            .chain(
+                self.mob_templates
+                    .values()
+                    .flat_map(|m| m.abilities.iter().map(move |(n, a)| (m.name.as_str(), n, a))),
+            )
+            .chain(self.sets.values().flat_map(|s| {
+                s.bonuses
+                    .iter()
+                    .flat_map(move |b| b.abilities.iter().map(move |(n, a)| (s.name.as_str(), n, a)))
+            }));
+        for (owner, name, ability) in abilities {
+            for (effect, magnitude) in &ability.effects {
+                self.effects
+                    .get(effect)
+                    .and_then(|e| e.validate(*magnitude, self))
+                    .map_err(|e| anyhow!(format!("{} of {}: {}", name, owner, e)))?;
+            }
+        }
+        Ok(())
     }
 
     pub fn get_mob_name_by_id(&self, id: u16) -> Result<MobName> {
@@ -371,6 +628,13 @@ impl GameData {
             .clone())
     }
 
+    pub fn get_block_by_id(&self, id: u8) -> Result<&Block> {
+        let name = self.get_block_name_by_id(id)?;
+        self.blocks
+            .get(&name)
+            .ok_or_else(|| anyhow!(format!("block {:?} doesn't exist!", name)))
+    }
+
     pub fn get_block_id_by_name<S: Into<String>>(&self, name: S) -> Result<u8> {
         let name = BlockName::checked_from(name.into(), self)?;
         self.get_block_id_by_blockname(&name)
@@ -384,3 +648,16 @@ impl GameData {
             .clone())
     }
 }
+
+/// the pvp gamemode, which the tests are written against.
+#[cfg(test)]
+pub(crate) fn pvp() -> GameData {
+    pvp_with(EffectRegistry::new()).unwrap()
+}
+
+/// the pvp gamemode, with extra effects.
+#[cfg(test)]
+pub(crate) fn pvp_with(effects: EffectRegistry) -> Result<GameData> {
+    let mode: GameMode = from_str(&fs::read_to_string("pvp/gamemode.jacl").unwrap()).unwrap();
+    mode.into_gamedata_with(effects)
+}
@@ -1,7 +1,8 @@
-use super::{gamedata::{DmgType, ItemName, StatType},
+use super::{consumable::{Consumable, ConsumableDeser},
+    gamedata::{DmgType, ItemName, SlotName, StatType, StatusName},
     serde_defaults::*};
 use anyhow::{anyhow, Result};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
 #[derive(Deserialize, Debug)]
@@ -18,6 +19,24 @@ pub struct AbilityDeser {
     health: f64,
     #[serde(default = "default_f64")]
     energy: f64,
+    #[serde(default = "default_f64_one")]
+    accuracy: f64,
+    #[serde(default = "default_f64")]
+    crit_chance: f64,
+    #[serde(default = "default_f64")]
+    crit_multiplier: f64,
+    #[serde(default = "default_targets")]
+    targets: String,
+    #[serde(default = "default_range")]
+    range: u64,
+    #[serde(default = "AreaDeser::new")]
+    area: AreaDeser,
+    #[serde(default = "default_true")]
+    line_of_sight: bool,
+    #[serde(default = "default_u64")]
+    cooldown: u64,
+    #[serde(default = "default_f64")]
+    cooldown_secs: f64,
 
     #[serde(default = "default_hmap")]
     damage: HashMap<String, f64>,
@@ -25,6 +44,12 @@ pub struct AbilityDeser {
     block: HashMap<String, f64>,
     #[serde(default = "default_hmap")]
     counter: HashMap<String, f64>,
+    #[serde(default = "default_hmap")]
+    inflict: HashMap<String, u64>,
+    #[serde(default = "default_hmap")]
+    gain: HashMap<String, u64>,
+    #[serde(default = "default_hmap")]
+    effects: HashMap<String, f64>,
 
     #[serde(default = "default_hmap")]
     require_items: HashMap<String, u64>,
@@ -34,12 +59,105 @@ pub struct AbilityDeser {
     make_items: HashMap<String, u64>,
 }
 
+fn default_targets() -> String {
+    "enemy".to_string()
+}
+
+fn default_range() -> u64 {
+    1
+}
+
+fn default_shape() -> String {
+    "single".to_string()
+}
+
+fn default_cone_angle() -> f64 {
+    90.0
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AreaDeser {
+    #[serde(default = "default_shape")]
+    shape: String,
+    #[serde(default = "default_u64")]
+    size: u64,
+    #[serde(default = "default_cone_angle")]
+    angle: f64,
+}
+
+impl AreaDeser {
+    fn new() -> Self {
+        AreaDeser {
+            shape: default_shape(),
+            size: 0,
+            angle: default_cone_angle(),
+        }
+    }
+
+    fn into_area(self) -> Result<Area> {
+        if self.shape != "single" && self.size == 0 {
+            return Err(anyhow!(format!(
+                "a {} area needs a size of at least 1",
+                self.shape
+            )));
+        }
+        match self.shape.as_str() {
+            "single" => Ok(Area::Single),
+            "radius" => Ok(Area::Radius(self.size)),
+            "line" => Ok(Area::Line(self.size)),
+            "cone" if self.angle > 0.0 && self.angle <= 360.0 => Ok(Area::Cone {
+                length: self.size,
+                angle: self.angle,
+            }),
+            "cone" => Err(anyhow!(format!(
+                "a cone's angle must be more than 0 and at most 360, got {}",
+                self.angle
+            ))),
+            other => Err(anyhow!(format!(
+                "invalid area shape {:?}, expected one of single, radius, line or cone",
+                other
+            ))),
+        }
+    }
+}
+
 impl AbilityDeser {
     pub fn into_ability(
         self,
         dmg_types: &HashSet<DmgType>,
         item_names: &HashSet<ItemName>,
+        statuses: &HashSet<StatusName>,
     ) -> Result<Ability> {
+        if self.accuracy < 0.0 {
+            return Err(anyhow!(format!(
+                "accuracy can't be negative, got {}",
+                self.accuracy
+            )));
+        }
+        let targets = match self.targets.as_str() {
+            "enemy" => Targets::Enemy,
+            "enemies" => Targets::Enemies,
+            "ally" => Targets::Ally,
+            "self" => Targets::User,
+            other => {
+                return Err(anyhow!(format!(
+                    "invalid targets {:?}, expected one of enemy, enemies, ally or self",
+                    other
+                )))
+            }
+        };
+        if self.cooldown_secs < 0.0 {
+            return Err(anyhow!(format!(
+                "cooldown_secs can't be negative, got {}",
+                self.cooldown_secs
+            )));
+        }
+        let area = self.area.into_area()?;
+        if area != Area::Single && targets != Targets::Enemy && targets != Targets::Ally {
+            return Err(anyhow!(
+                "only abilities that target an enemy or an ally can hit an area"
+            ));
+        }
         let remove_items = map(self.remove_items, item_names)?;
         let require_items = if self.require_items.is_empty() {
             remove_items.clone()
@@ -73,9 +191,21 @@ impl AbilityDeser {
             repeat: self.repeat,
             health: self.health,
             energy: self.energy,
+            accuracy: self.accuracy,
+            crit_chance: self.crit_chance,
+            crit_multiplier: self.crit_multiplier,
+            targets,
+            range: self.range,
+            area,
+            line_of_sight: self.line_of_sight,
+            cooldown: self.cooldown,
+            cooldown_secs: self.cooldown_secs,
             damage: map(self.damage, dmg_types)?,
             block: map(self.block, dmg_types)?,
             counter: map(self.counter, dmg_types)?,
+            inflict: map(self.inflict, statuses)?,
+            gain: map(self.gain, statuses)?,
+            effects: self.effects,
             require_items,
             remove_items,
             make_items: map(self.make_items, item_names)?,
@@ -93,6 +223,12 @@ pub struct BuffsDeser {
     stat_buffs: HashMap<String, f64>,
 }
 
+impl Default for BuffsDeser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl BuffsDeser {
     pub fn new() -> Self {
         BuffsDeser {
@@ -115,11 +251,48 @@ impl BuffsDeser {
     }
 }
 
+/// something extra an item instance might turn out to have, e.g. being
+/// unusually sharp.
+#[derive(Deserialize, Debug)]
+pub struct AffixDeser {
+    /// the chance of each new instance of the item having this affix.
+    chance: f64,
+    #[serde(default = "BuffsDeser::new")]
+    buffs: BuffsDeser,
+}
+
+impl AffixDeser {
+    fn into_affix(
+        self,
+        dmg_types: &HashSet<DmgType>,
+        stat_types: &HashSet<StatType>,
+    ) -> Result<Affix> {
+        if self.chance < 0.0 || self.chance > 1.0 {
+            return Err(anyhow!(format!(
+                "affix chance must be between 0 and 1, got {}",
+                self.chance
+            )));
+        }
+        Ok(Affix {
+            chance: self.chance,
+            buffs: self.buffs.into_buffs(dmg_types, stat_types)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Affix {
+    pub chance: f64,
+    /// buffs on top of the item's own buffs.
+    pub buffs: Buffs,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct ItemDeser {
     #[serde(default = "default_false")]
     wearable: bool,
-    #[serde(default = "default_false")]
+    /// the gamemode files spell it "equippable".
+    #[serde(default = "default_false", alias = "equippable")]
     equipable: bool,
     #[serde(default = "default_i64")]
     xp: i64,
@@ -129,6 +302,26 @@ pub struct ItemDeser {
     abilities: HashMap<String, AbilityDeser>,
     #[serde(default = "default_string")]
     description: String,
+    /// the most of this item one inventory can hold, or 0 for no limit.
+    #[serde(default = "default_u64")]
+    stack: u64,
+    #[serde(default = "default_f64")]
+    weight: f64,
+    /// whether each one of this item is its own instance, with its own
+    /// durability and affixes.
+    #[serde(default = "default_false")]
+    unique: bool,
+    /// how many uses an instance lasts, or 0 if it never wears out.
+    #[serde(default = "default_u64")]
+    durability: u64,
+    #[serde(default = "default_hmap")]
+    affixes: HashMap<String, AffixDeser>,
+    /// the equipment slot the item goes in, if it can be equipped.
+    #[serde(default = "default_string")]
+    slot: String,
+    /// what happens when the item is consumed, if it can be.
+    #[serde(default = "ConsumableDeser::new")]
+    consumable: ConsumableDeser,
 }
 
 impl ItemDeser {
@@ -137,11 +330,59 @@ impl ItemDeser {
         dmg_types: &HashSet<DmgType>,
         stat_types: &HashSet<StatType>,
         item_names: &HashSet<ItemName>,
+        statuses: &HashSet<StatusName>,
+        slots: &HashSet<SlotName>,
         name: ItemName,
     ) -> Result<Item> {
         let mut abilities = HashMap::new();
         for (k, v) in self.abilities {
-            abilities.insert(k, v.into_ability(dmg_types, item_names)?);
+            abilities.insert(k, v.into_ability(dmg_types, item_names, statuses)?);
+        }
+        if !self.unique && (self.durability > 0 || !self.affixes.is_empty()) {
+            return Err(anyhow!(format!(
+                "{:?} needs to be unique to have durability or affixes",
+                name
+            )));
+        }
+        let mut affixes = HashMap::new();
+        for (k, v) in self.affixes {
+            let affix = v
+                .into_affix(dmg_types, stat_types)
+                .map_err(|e| anyhow!(format!("affix {} of {:?}: {}", k, name, e)))?;
+            affixes.insert(k, affix);
+        }
+        let slot = if self.slot.is_empty() {
+            None
+        } else {
+            let slot = SlotName::from(self.slot);
+            if !slots.contains(&slot) {
+                return Err(anyhow!(format!(
+                    "{:?} goes in {:?}, which is not an equipment slot",
+                    name, slot
+                )));
+            }
+            if !self.wearable && !self.equipable {
+                return Err(anyhow!(format!(
+                    "{:?} goes in {:?}, but is neither wearable nor equippable",
+                    name, slot
+                )));
+            }
+            Some(slot)
+        };
+        let consumable = self
+            .consumable
+            .into_consumable(&name, dmg_types, stat_types)?;
+        if consumable.is_some() && self.unique {
+            return Err(anyhow!(format!(
+                "{:?} can't be both unique and consumable",
+                name
+            )));
+        }
+        if self.weight < 0.0 || !self.weight.is_finite() {
+            return Err(anyhow!(format!(
+                "{:?} can't weigh {}",
+                name, self.weight
+            )));
         }
 
         Ok(Item {
@@ -151,6 +392,17 @@ impl ItemDeser {
             xp: self.xp,
             buffs: self.buffs.into_buffs(dmg_types, stat_types)?,
             abilities,
+            stack: if self.stack == 0 { None } else { Some(self.stack) },
+            weight: self.weight,
+            unique: self.unique,
+            durability: if self.durability == 0 {
+                None
+            } else {
+                Some(self.durability)
+            },
+            affixes,
+            slot,
+            consumable,
             description: if self.description == "" {
                 None
             } else {
@@ -160,36 +412,151 @@ impl ItemDeser {
     }
 }
 
-#[derive(Debug, Clone)]
+/// who an ability is used on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Targets {
+    /// one enemy, chosen by the user.
+    Enemy,
+    /// every enemy still in the fight.
+    Enemies,
+    /// one of the user's allies (not the user).
+    Ally,
+    /// the user.
+    User,
+}
+
+/// the shape of the area an ability hits, around whoever it's aimed at.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Area {
+    /// just whoever it's aimed at.
+    Single,
+    /// everyone within this many tiles of whoever it's aimed at. Solid blocks
+    /// shield anyone behind them.
+    Radius(u64),
+    /// everyone on a line this many tiles long, from the user towards whoever
+    /// it's aimed at. The line stops at the first solid block.
+    Line(u64),
+    /// everyone within `length` tiles of the user, and within `angle` degrees
+    /// of the direction to whoever it's aimed at.
+    Cone { length: u64, angle: f64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Ability {
-    destroy_item: bool,
-    stun: u64,
-    charge: u64,
-    repeat: u64,
-    health: f64,
-    energy: f64,
-    damage: HashMap<DmgType, f64>,
-    block: HashMap<DmgType, f64>,
-    counter: HashMap<DmgType, f64>,
-    require_items: HashMap<ItemName, u64>,
-    remove_items: HashMap<ItemName, u64>,
-    make_items: HashMap<ItemName, u64>,
+    pub destroy_item: bool,
+    pub stun: u64,
+    pub charge: u64,
+    pub repeat: u64,
+    pub health: f64,
+    pub energy: f64,
+    /// multiplier on the user's accuracy stat.
+    pub accuracy: f64,
+    /// added to the user's crit_chance stat.
+    pub crit_chance: f64,
+    /// added to the user's crit_multiplier stat.
+    pub crit_multiplier: f64,
+    /// who the ability's damage, stun, inflicted statuses and effects go to.
+    pub targets: Targets,
+    /// how many tiles away the ability can reach. Only matters for battles
+    /// fought in an arena.
+    pub range: u64,
+    pub area: Area,
+    /// whether solid blocks between the user and the target stop the ability.
+    pub line_of_sight: bool,
+    /// how many of the user's turns they have to wait after using the ability
+    /// before they can use it again.
+    pub cooldown: u64,
+    /// how many seconds the user has to wait after using the ability before
+    /// they can use it again, in or out of battle.
+    pub cooldown_secs: f64,
+    pub damage: HashMap<DmgType, f64>,
+    pub block: HashMap<DmgType, f64>,
+    pub counter: HashMap<DmgType, f64>,
+    /// stacks of statuses applied to the target if the ability hits.
+    pub inflict: HashMap<StatusName, u64>,
+    /// stacks of statuses the user gives themself.
+    pub gain: HashMap<StatusName, u64>,
+    /// magnitudes of effects by name. Effects are checked against the
+    /// gamemode's effect registry once all the game data is loaded.
+    pub effects: HashMap<String, f64>,
+    pub require_items: HashMap<ItemName, u64>,
+    pub remove_items: HashMap<ItemName, u64>,
+    pub make_items: HashMap<ItemName, u64>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Buffs {
-    defense_buffs: HashMap<DmgType, f64>,
-    attack_buffs: HashMap<DmgType, f64>,
-    stat_buffs: HashMap<StatType, f64>,
+    pub defense_buffs: HashMap<DmgType, f64>,
+    pub attack_buffs: HashMap<DmgType, f64>,
+    pub stat_buffs: HashMap<StatType, f64>,
+}
+
+impl Default for Buffs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Buffs {
+    pub fn new() -> Self {
+        Buffs {
+            defense_buffs: HashMap::new(),
+            attack_buffs: HashMap::new(),
+            stat_buffs: HashMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.defense_buffs.is_empty() && self.attack_buffs.is_empty() && self.stat_buffs.is_empty()
+    }
+
+    /// multiplier on outgoing damage of the given type.
+    pub fn attack(&self, dmg: &DmgType) -> f64 {
+        *self.attack_buffs.get(dmg).unwrap_or(&1.0)
+    }
+
+    /// multiplier on incoming damage of the given type.
+    pub fn defense(&self, dmg: &DmgType) -> f64 {
+        *self.defense_buffs.get(dmg).unwrap_or(&1.0)
+    }
+
+    /// multiplier on the given stat.
+    pub fn stat(&self, stat: &StatType) -> f64 {
+        *self.stat_buffs.get(stat).unwrap_or(&1.0)
+    }
+
+    /// add another set of buffs on top of these, e.g. from wearing several
+    /// items. Buffs are multipliers, so they multiply together.
+    pub fn stack(&mut self, other: &Buffs) {
+        for (k, v) in &other.defense_buffs {
+            *self.defense_buffs.entry(k.clone()).or_insert(1.0) *= v;
+        }
+        for (k, v) in &other.attack_buffs {
+            *self.attack_buffs.entry(k.clone()).or_insert(1.0) *= v;
+        }
+        for (k, v) in &other.stat_buffs {
+            *self.stat_buffs.entry(k.clone()).or_insert(1.0) *= v;
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Item {
-    name: ItemName,
-    wearable: bool,
-    equipable: bool,
-    xp: i64,
-    buffs: Buffs,
-    abilities: HashMap<String, Ability>,
-    description: Option<String>,
+    pub name: ItemName,
+    pub wearable: bool,
+    pub equipable: bool,
+    pub xp: i64,
+    pub buffs: Buffs,
+    pub abilities: HashMap<String, Ability>,
+    pub description: Option<String>,
+    /// the most of this item one inventory can hold, if there's a limit.
+    pub stack: Option<u64>,
+    pub weight: f64,
+    pub unique: bool,
+    /// how many uses an instance of the item lasts, if it can wear out.
+    pub durability: Option<u64>,
+    pub affixes: HashMap<String, Affix>,
+    /// the equipment slot the item goes in, if it can be equipped.
+    pub slot: Option<SlotName>,
+    pub consumable: Option<Consumable>,
 }
@@ -1,9 +1,13 @@
 use super::{
-    gamedata::{DmgType, ItemName, MobAction, MobName},
+    gamedata::{
+        Alignment, DmgType, GameData, ItemName, MobAction, MobName, StatType, StatusName,
+    },
     item::{Ability, AbilityDeser},
     serde_defaults::*,
 };
+use crate::inventory::Inventory;
 use anyhow::{anyhow, Result};
+use rand::{prelude::StdRng, Rng};
 use serde::Deserialize;
 use std::collections::{HashMap, HashSet};
 
@@ -44,12 +48,36 @@ struct InventoryBuilderDeser {
 }
 
 #[derive(Debug, Clone)]
-struct InventoryBuilder {
+pub struct InventoryBuilder {
     min: u64,
     max: u64,
     items: Vec<ItemGen>,
 }
 
+impl InventoryBuilder {
+    /// roll between min and max items, each one picked with a chance
+    /// proportional to its prob. Unique items get new instances.
+    pub fn build(&self, g: &GameData, rng: &mut StdRng) -> Result<Inventory> {
+        let mut items = HashMap::new();
+        let total: f64 = self.items.iter().map(|x| x.prob).sum();
+        if self.items.is_empty() || total <= 0.0 || self.max < self.min {
+            return Ok(Inventory::new());
+        }
+        let count = rng.gen_range(self.min, self.max + 1);
+        for _ in 0..count {
+            let mut roll = rng.gen::<f64>() * total;
+            for gen in &self.items {
+                if roll < gen.prob {
+                    *items.entry(gen.name.clone()).or_insert(0) += gen.per;
+                    break;
+                }
+                roll -= gen.prob;
+            }
+        }
+        Inventory::rolled(g, &items, rng)
+    }
+}
+
 impl InventoryBuilderDeser {
     fn new() -> Self {
         InventoryBuilderDeser {
@@ -73,6 +101,155 @@ impl InventoryBuilderDeser {
     }
 }
 
+fn default_home_radius() -> u64 {
+    5
+}
+
+fn default_wander_chance() -> f64 {
+    0.25
+}
+
+#[derive(Deserialize, Debug)]
+struct BehaviorDeser {
+    #[serde(default = "default_home_radius")]
+    home_radius: u64,
+    #[serde(default = "default_u64")]
+    aggro_radius: u64,
+    #[serde(default = "default_u64")]
+    leash_radius: u64,
+    #[serde(default = "default_wander_chance")]
+    wander_chance: f64,
+}
+
+impl BehaviorDeser {
+    fn new() -> Self {
+        BehaviorDeser {
+            home_radius: default_home_radius(),
+            aggro_radius: 0,
+            leash_radius: 0,
+            wander_chance: default_wander_chance(),
+        }
+    }
+
+    fn into_behavior(self) -> Result<Behavior> {
+        if self.wander_chance < 0.0 || self.wander_chance > 1.0 {
+            return Err(anyhow!(format!(
+                "wander_chance must be between 0 and 1, got {}",
+                self.wander_chance
+            )));
+        }
+        // an unset leash lets the mob chase twice as far as it can see
+        let leash_radius = if self.leash_radius == 0 {
+            self.home_radius.max(self.aggro_radius * 2)
+        } else {
+            self.leash_radius
+        };
+        if leash_radius < self.home_radius {
+            return Err(anyhow!(format!(
+                "leash_radius {} cannot be smaller than home_radius {}",
+                leash_radius, self.home_radius
+            )));
+        }
+        Ok(Behavior {
+            home_radius: self.home_radius,
+            aggro_radius: self.aggro_radius,
+            leash_radius,
+            wander_chance: self.wander_chance,
+        })
+    }
+}
+
+/// how a mob moves around the world when it isn't fighting.
+#[derive(Debug, Clone)]
+pub struct Behavior {
+    /// how far the mob will wander from where it spawned.
+    pub home_radius: u64,
+    /// how close a player has to be before the mob starts chasing them.
+    /// 0 means the mob never chases anyone.
+    pub aggro_radius: u64,
+    /// how far from home the mob will chase a player before giving up.
+    pub leash_radius: u64,
+    /// chance per tick that an idle mob takes a step.
+    pub wander_chance: f64,
+}
+
+fn default_low_health() -> f64 {
+    0.3
+}
+
+fn default_randomness() -> f64 {
+    0.1
+}
+
+#[derive(Deserialize, Debug)]
+struct StrategyDeser {
+    #[serde(default = "default_f64_one")]
+    aggression: f64,
+    #[serde(default = "default_f64_one")]
+    caution: f64,
+    #[serde(default = "default_low_health")]
+    low_health: f64,
+    #[serde(default = "default_f64")]
+    energy_reserve: f64,
+    #[serde(default = "default_randomness")]
+    randomness: f64,
+}
+
+impl StrategyDeser {
+    fn new() -> Self {
+        StrategyDeser {
+            aggression: 1.0,
+            caution: 1.0,
+            low_health: default_low_health(),
+            energy_reserve: 0.0,
+            randomness: default_randomness(),
+        }
+    }
+
+    fn into_strategy(self) -> Result<Strategy> {
+        if self.aggression < 0.0 || self.caution < 0.0 || self.randomness < 0.0 {
+            return Err(anyhow!(
+                "aggression, caution and randomness can't be negative"
+            ));
+        }
+        for (name, v) in &[
+            ("low_health", self.low_health),
+            ("energy_reserve", self.energy_reserve),
+        ] {
+            if *v < 0.0 || *v > 1.0 {
+                return Err(anyhow!(format!(
+                    "{} must be between 0 and 1, got {}",
+                    name, v
+                )));
+            }
+        }
+        Ok(Strategy {
+            aggression: self.aggression,
+            caution: self.caution,
+            low_health: self.low_health,
+            energy_reserve: self.energy_reserve,
+            randomness: self.randomness,
+        })
+    }
+}
+
+/// how a mob picks its abilities in battle.
+#[derive(Debug, Clone)]
+pub struct Strategy {
+    /// how much the mob values dealing damage.
+    pub aggression: f64,
+    /// how much the mob values blocking, countering and healing once its
+    /// health is low.
+    pub caution: f64,
+    /// the proportion of max health below which the mob starts to worry.
+    pub low_health: f64,
+    /// the proportion of max energy the mob tries not to spend.
+    pub energy_reserve: f64,
+    /// how much the mob's choices are shaken up. 0 always picks what looks
+    /// best.
+    pub randomness: f64,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct MobTemplateDeser {
     #[serde(default = "default_i64")]
@@ -81,41 +258,63 @@ pub struct MobTemplateDeser {
     abilities: HashMap<String, AbilityDeser>,
     #[serde(default = "default_hmap")]
     quotes: HashMap<String, Vec<String>>,
+    #[serde(default = "default_hmap")]
+    stats: HashMap<String, f64>,
     #[serde(default = "InventoryBuilderDeser::new")]
     tools: InventoryBuilderDeser,
     #[serde(default = "InventoryBuilderDeser::new")]
     drops: InventoryBuilderDeser,
+    #[serde(default = "BehaviorDeser::new")]
+    behavior: BehaviorDeser,
+    #[serde(default = "StrategyDeser::new")]
+    strategy: StrategyDeser,
+    #[serde(default = "default_vec")]
+    alignments: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct MobTemplate {
-    name: MobName,
-    xp: i64,
-    abilities: HashMap<String, Ability>,
-    quotes: HashMap<MobAction, Vec<String>>,
-    tools: InventoryBuilder,
-    drops: InventoryBuilder,
+    pub name: MobName,
+    pub xp: i64,
+    pub abilities: HashMap<String, Ability>,
+    pub quotes: HashMap<MobAction, Vec<String>>,
+    pub stats: HashMap<StatType, f64>,
+    pub tools: InventoryBuilder,
+    pub drops: InventoryBuilder,
+    pub behavior: Behavior,
+    pub strategy: Strategy,
+    pub alignments: Vec<Alignment>,
 }
 
 impl MobTemplateDeser {
+    pub fn alignments(&self) -> &[String] {
+        &self.alignments
+    }
+
     pub fn into_mobtemplate(
         self,
         dmg_types: &HashSet<DmgType>,
+        stat_types: &HashSet<StatType>,
         item_names: &HashSet<ItemName>,
+        statuses: &HashSet<StatusName>,
         mob_actions: &HashSet<MobAction>,
         name: MobName,
     ) -> Result<MobTemplate> {
         let mut abilities = HashMap::new();
         for (k, v) in self.abilities {
-            abilities.insert(k, v.into_ability(dmg_types, item_names)?);
+            abilities.insert(k, v.into_ability(dmg_types, item_names, statuses)?);
         }
         Ok(MobTemplate {
             name,
             xp: self.xp,
             abilities,
             quotes: map(self.quotes, mob_actions)?,
+            stats: map(self.stats, stat_types)?,
             tools: self.tools.into_inventorybuilder(item_names)?,
             drops: self.drops.into_inventorybuilder(item_names)?,
+            behavior: self.behavior.into_behavior()?,
+            strategy: self.strategy.into_strategy()?,
+            alignments: self.alignments.into_iter().map(Alignment::from).collect(),
         })
     }
 }
\ No newline at end of file
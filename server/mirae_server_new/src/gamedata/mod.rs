@@ -1,6 +1,12 @@
 pub mod gamedata;
 pub mod block;
+pub mod carry;
+pub mod consumable;
+pub mod dmg;
+pub mod flee;
 pub mod item;
 pub mod mobtemplate;
+pub mod set;
+pub mod status;
 pub mod terrain;
 mod serde_defaults;
\ No newline at end of file
@@ -25,10 +25,18 @@ pub fn default_f64() -> f64 {
     0.0
 }
 
+pub fn default_f64_one() -> f64 {
+    1.0
+}
+
 pub fn default_hmap<K, V>() -> HashMap<K, V> {
     HashMap::new()
 }
 
+pub fn default_vec<T>() -> Vec<T> {
+    Vec::new()
+}
+
 pub fn default_string() -> String {
     "".to_string()
 }
@@ -0,0 +1,219 @@
+use super::{
+    gamedata::{DmgType, ItemName, SetName, SlotName, StatType, StatusName},
+    item::{Ability, AbilityDeser, Buffs, BuffsDeser, Item},
+    serde_defaults::*,
+};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+/// what wearing enough pieces of a set gives you.
+#[derive(Deserialize, Debug)]
+pub struct SetBonusDeser {
+    #[serde(default = "BuffsDeser::new")]
+    buffs: BuffsDeser,
+    #[serde(default = "default_hmap")]
+    abilities: HashMap<String, AbilityDeser>,
+}
+
+/// a group of items that give bonuses when they're worn together.
+#[derive(Deserialize, Debug)]
+pub struct ItemSetDeser {
+    items: Vec<String>,
+    /// bonuses by how many different pieces have to be worn, e.g. "2".
+    bonuses: HashMap<String, SetBonusDeser>,
+}
+
+impl ItemSetDeser {
+    pub fn into_item_set(
+        self,
+        name: SetName,
+        items: &HashMap<ItemName, Item>,
+        slots: &HashMap<SlotName, u64>,
+        dmg_types: &HashSet<DmgType>,
+        stat_types: &HashSet<StatType>,
+        statuses: &HashSet<StatusName>,
+    ) -> Result<ItemSet> {
+        let item_names = items.keys().cloned().collect();
+        let mut pieces: Vec<ItemName> = vec![];
+        // how many pieces go in each slot, to work out how many can be worn
+        // at once
+        let mut per_slot: HashMap<&SlotName, u64> = HashMap::new();
+        for item in self.items {
+            let item = ItemName::from(item);
+            let slot = items
+                .get(&item)
+                .ok_or_else(|| anyhow!(format!("set {:?} has {:?}, which is not an item", name, item)))?
+                .slot
+                .as_ref()
+                .ok_or_else(|| {
+                    anyhow!(format!(
+                        "set {:?} has {:?}, which can't be equipped",
+                        name, item
+                    ))
+                })?;
+            if pieces.contains(&item) {
+                return Err(anyhow!(format!("set {:?} has {:?} twice", name, item)));
+            }
+            *per_slot.entry(slot).or_insert(0) += 1;
+            pieces.push(item);
+        }
+        if pieces.len() < 2 {
+            return Err(anyhow!(format!(
+                "set {:?} needs at least 2 items, got {}",
+                name,
+                pieces.len()
+            )));
+        }
+        let wearable: u64 = per_slot.iter().map(|(s, n)| slots[*s].min(*n)).sum();
+        if self.bonuses.is_empty() {
+            return Err(anyhow!(format!("set {:?} has no bonuses", name)));
+        }
+        let mut bonuses = vec![];
+        for (k, v) in self.bonuses {
+            let needs: u64 = k.parse().map_err(|_| {
+                anyhow!(format!(
+                    "bonuses of set {:?} are keyed by how many pieces they need, got {:?}",
+                    name, k
+                ))
+            })?;
+            if needs < 2 {
+                return Err(anyhow!(format!(
+                    "bonuses of set {:?} need at least 2 pieces, got {}",
+                    name, needs
+                )));
+            }
+            if needs > wearable {
+                return Err(anyhow!(format!(
+                    "the {} piece bonus of set {:?} can never be had, only {} of its pieces can be worn at once",
+                    needs, name, wearable
+                )));
+            }
+            let mut abilities = HashMap::new();
+            for (ability, a) in v.abilities {
+                let a = a
+                    .into_ability(dmg_types, &item_names, statuses)
+                    .map_err(|e| anyhow!(format!("{} of set {:?}: {}", ability, name, e)))?;
+                if a.destroy_item {
+                    return Err(anyhow!(format!(
+                        "{} of set {:?} can't destroy an item, set abilities don't come from one",
+                        ability, name
+                    )));
+                }
+                abilities.insert(ability, a);
+            }
+            bonuses.push(SetBonus {
+                needs,
+                buffs: v
+                    .buffs
+                    .into_buffs(dmg_types, stat_types)
+                    .map_err(|e| anyhow!(format!("bonus {} of set {:?}: {}", needs, name, e)))?,
+                abilities,
+            });
+        }
+        bonuses.sort_by_key(|b| b.needs);
+        Ok(ItemSet {
+            name,
+            items: pieces,
+            bonuses,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SetBonus {
+    /// how many different pieces of the set have to be worn.
+    pub needs: u64,
+    pub buffs: Buffs,
+    pub abilities: HashMap<String, Ability>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ItemSet {
+    pub name: SetName,
+    pub items: Vec<ItemName>,
+    /// fewest pieces first.
+    pub bonuses: Vec<SetBonus>,
+}
+
+impl ItemSet {
+    /// the bonuses someone wearing `worn` different pieces gets.
+    pub fn bonuses(&self, worn: u64) -> impl Iterator<Item = &SetBonus> {
+        self.bonuses.iter().filter(move |b| b.needs <= worn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamedata::gamedata::{pvp, GameData};
+    use serde_jacl::de::from_str;
+
+    fn set(g: &GameData, jacl: &str) -> Result<ItemSet> {
+        let deser: ItemSetDeser = from_str(jacl).unwrap();
+        deser.into_item_set(
+            SetName::from("test".to_string()),
+            &g.items,
+            &g.slots,
+            &g.dmg,
+            &g.stat_defaults.keys().cloned().collect(),
+            &g.statuses.keys().cloned().collect(),
+        )
+    }
+
+    #[test]
+    fn bonuses_build_up() {
+        let g = pvp();
+        let s = set(
+            &g,
+            r#"items : ["orange" "trump_orange" "heghead"]
+            bonuses : {
+                "3" : ( buffs : ( stat_buffs : { "speed" : 2 } ) )
+                "2" : ( abilities : { "pulp" : ( damage : { "physical" : 1 } ) } )
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            s.bonuses.iter().map(|b| b.needs).collect::<Vec<u64>>(),
+            vec![2, 3]
+        );
+        assert_eq!(s.bonuses(1).count(), 0);
+        assert_eq!(s.bonuses(2).count(), 1);
+        assert_eq!(s.bonuses(3).count(), 2);
+    }
+
+    #[test]
+    fn rejects_sets_that_make_no_sense() {
+        let g = pvp();
+        let bonus = r#"bonuses : { "2" : ( buffs : ( stat_buffs : { "speed" : 2 } ) ) }"#;
+        for items in [
+            r#"["orange"]"#,
+            r#"["orange" "orange"]"#,
+            r#"["orange" "nothing"]"#,
+            r#"["orange" "wood"]"#,
+        ] {
+            assert!(
+                set(&g, &format!("items : {} {}", items, bonus)).is_err(),
+                "{}",
+                items
+            );
+        }
+        let items = r#"items : ["orange" "heghead"]"#;
+        assert!(set(&g, &format!("{} {}", items, bonus)).is_ok());
+        assert!(set(&g, &format!("{} bonuses : {{}}", items)).is_err());
+        for needs in ["1", "3", "two"] {
+            let bonuses = format!(r#"bonuses : {{ "{}" : ( ) }}"#, needs);
+            assert!(
+                set(&g, &format!("{} {}", items, bonuses)).is_err(),
+                "{}",
+                needs
+            );
+        }
+        // there are only two trinket slots
+        let oranges = r#"items : ["orange" "trump_orange" "sentient_trump_orange"]"#;
+        assert!(set(&g, &format!(r#"{} bonuses : {{ "3" : ( ) }}"#, oranges)).is_err());
+        assert!(set(&g, &format!(r#"{} bonuses : {{ "2" : ( ) }}"#, oranges)).is_ok());
+        let destroys = r#"bonuses : { "2" : ( abilities : { "eat" : ( destroy_item : true ) } ) }"#;
+        assert!(set(&g, &format!("{} {}", items, destroys)).is_err());
+    }
+}
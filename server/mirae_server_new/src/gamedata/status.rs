@@ -0,0 +1,194 @@
+use super::{
+    gamedata::{DmgType, StatusName},
+    serde_defaults::*,
+};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Deserialize, Debug)]
+pub struct StatusTriggerDeser {
+    #[serde(default = "default_f64")]
+    health: f64,
+    #[serde(default = "default_f64")]
+    energy: f64,
+    #[serde(default = "default_hmap")]
+    damage: HashMap<String, f64>,
+    #[serde(default = "default_hmap")]
+    retaliate: HashMap<String, f64>,
+    #[serde(default = "default_vec")]
+    apply: Vec<String>,
+}
+
+impl Default for StatusTriggerDeser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatusTriggerDeser {
+    pub fn new() -> Self {
+        StatusTriggerDeser {
+            health: 0.0,
+            energy: 0.0,
+            damage: HashMap::new(),
+            retaliate: HashMap::new(),
+            apply: vec![],
+        }
+    }
+
+    pub fn into_status_trigger(
+        self,
+        dmg_types: &HashSet<DmgType>,
+        statuses: &HashSet<StatusName>,
+        on_hit: bool,
+    ) -> Result<StatusTrigger> {
+        if !on_hit && !self.retaliate.is_empty() {
+            return Err(anyhow!(
+                "only on_hit triggers can retaliate, since nobody else hit the holder"
+            ));
+        }
+        let mut apply = vec![];
+        for name in self.apply {
+            let name = StatusName::from(name);
+            if !statuses.contains(&name) {
+                return Err(anyhow!(format!("{:?} is not a status", name)));
+            }
+            apply.push(name);
+        }
+        Ok(StatusTrigger {
+            health: self.health,
+            energy: self.energy,
+            damage: map(self.damage, dmg_types)?,
+            retaliate: map(self.retaliate, dmg_types)?,
+            apply,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StatusDeser {
+    duration: u64,
+    #[serde(default = "default_stacking")]
+    stacking: String,
+    #[serde(default = "default_max_stacks")]
+    max_stacks: u64,
+    #[serde(default = "StatusTriggerDeser::new")]
+    per_turn: StatusTriggerDeser,
+    #[serde(default = "StatusTriggerDeser::new")]
+    on_hit: StatusTriggerDeser,
+    #[serde(default = "StatusTriggerDeser::new")]
+    on_expire: StatusTriggerDeser,
+    #[serde(default = "default_string")]
+    description: String,
+}
+
+fn default_stacking() -> String {
+    "refresh".to_string()
+}
+
+fn default_max_stacks() -> u64 {
+    1
+}
+
+impl StatusDeser {
+    pub fn into_status(
+        self,
+        name: StatusName,
+        dmg_types: &HashSet<DmgType>,
+        statuses: &HashSet<StatusName>,
+    ) -> Result<Status> {
+        if self.duration == 0 {
+            return Err(anyhow!(format!("{:?} must last at least 1 turn", name)));
+        }
+        if self.max_stacks == 0 {
+            return Err(anyhow!(format!("{:?} must allow at least 1 stack", name)));
+        }
+        let stacking = Stacking::parse(&self.stacking)
+            .map_err(|e| anyhow!(format!("{:?} has {}", name, e)))?;
+        Ok(Status {
+            duration: self.duration,
+            stacking,
+            max_stacks: self.max_stacks,
+            per_turn: self.per_turn.into_status_trigger(dmg_types, statuses, false)?,
+            on_hit: self.on_hit.into_status_trigger(dmg_types, statuses, true)?,
+            on_expire: self.on_expire.into_status_trigger(dmg_types, statuses, false)?,
+            description: if self.description.is_empty() {
+                None
+            } else {
+                Some(self.description)
+            },
+            name,
+        })
+    }
+}
+
+/// what happens when a status (or the buffs from a consumable) is applied to
+/// someone who already has it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stacking {
+    /// add the new stacks (up to max_stacks), and restart the duration.
+    Stack,
+    /// restart the duration.
+    Refresh,
+    /// add the duration on to however long is left.
+    Extend,
+    /// nothing, the status that's already there stays as it is.
+    Ignore,
+}
+
+impl Stacking {
+    pub fn parse(s: &str) -> Result<Stacking> {
+        match s {
+            "stack" => Ok(Stacking::Stack),
+            "refresh" => Ok(Stacking::Refresh),
+            "extend" => Ok(Stacking::Extend),
+            "ignore" => Ok(Stacking::Ignore),
+            other => Err(anyhow!(format!(
+                "invalid stacking {:?}, expected one of stack, refresh, extend or ignore",
+                other
+            ))),
+        }
+    }
+}
+
+/// something that happens to the holder of a status. Amounts are per stack.
+#[derive(Debug, Clone)]
+pub struct StatusTrigger {
+    pub health: f64,
+    pub energy: f64,
+    /// damage dealt to the holder, by whoever applied the status.
+    pub damage: HashMap<DmgType, f64>,
+    /// damage dealt back to whoever hit the holder. Only on_hit triggers can
+    /// retaliate.
+    pub retaliate: HashMap<DmgType, f64>,
+    /// other statuses applied to the holder.
+    pub apply: Vec<StatusName>,
+}
+
+impl StatusTrigger {
+    pub fn is_empty(&self) -> bool {
+        self.health == 0.0
+            && self.energy == 0.0
+            && self.damage.is_empty()
+            && self.retaliate.is_empty()
+            && self.apply.is_empty()
+    }
+}
+
+/// a timed effect on a combatant, like poison or regeneration.
+#[derive(Debug, Clone)]
+pub struct Status {
+    pub name: StatusName,
+    /// how many of the holder's turns the status lasts for.
+    pub duration: u64,
+    pub stacking: Stacking,
+    pub max_stacks: u64,
+    /// goes off at the start of each of the holder's turns.
+    pub per_turn: StatusTrigger,
+    /// goes off whenever the holder takes damage.
+    pub on_hit: StatusTrigger,
+    /// goes off once the status runs out.
+    pub on_expire: StatusTrigger,
+    pub description: Option<String>,
+}
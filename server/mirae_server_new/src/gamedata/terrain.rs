@@ -2,7 +2,7 @@ use crate::vector3::Vector3;
 use anyhow::{anyhow, Result};
 use serde::Deserialize;
 use std::collections::HashSet;
-use super::gamedata::{StructureName, BiomeName, BlockName};
+use super::{gamedata::{StructureName, BiomeName, BlockName}, serde_defaults::*};
 
 #[derive(Debug, Deserialize)]
 pub struct TerrainDeser {
@@ -156,8 +156,11 @@ impl BlockCutoffDeser {
 
 #[derive(Debug, Deserialize)]
 pub struct BiomeDeser {
+    #[serde(default = "default_vec")]
     pub terrain_pass: Vec<BlockCutoffDeser>,
+    #[serde(default = "default_vec")]
     pub biome_pass: Vec<BlockCutoffDeser>,
+    #[serde(default = "default_vec")]
     pub spawn: Vec<StructureSpawnDeser>,
 }
 
@@ -1,34 +1,822 @@
 use anyhow::{anyhow, Result};
+use rand::{prelude::StdRng, Rng};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use crate::gamedata::gamedata::ItemName;
+use crate::gamedata::{
+    carry::{Carry, Overflow},
+    gamedata::{GameData, ItemName},
+    item::{Ability, Buffs, Item},
+};
 
-pub struct Inventory(pub HashMap<ItemName, u64>);
+/// items that didn't fit in an inventory, and were dropped instead.
+pub type Dropped = HashMap<ItemName, u64>;
+
+/// one particular copy of a unique item.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemInstance {
+    pub id: u64,
+    pub item: ItemName,
+    /// uses left before it breaks, if it can wear out.
+    pub durability: Option<u64>,
+    /// in name order.
+    pub affixes: Vec<String>,
+}
+
+impl ItemInstance {
+    /// a brand new instance of `item`, with its affixes rolled.
+    pub fn roll(item: &Item, rng: &mut StdRng) -> Self {
+        let mut names: Vec<&String> = item.affixes.keys().collect();
+        names.sort();
+        let affixes = names
+            .into_iter()
+            .filter(|name| rng.gen::<f64>() < item.affixes[*name].chance)
+            .cloned()
+            .collect();
+        ItemInstance {
+            id: rng.gen(),
+            item: item.name.clone(),
+            durability: item.durability,
+            affixes,
+        }
+    }
+
+    /// the item's own buffs, along with its affixes'.
+    pub fn buffs(&self, g: &GameData) -> Buffs {
+        let mut buffs = Buffs::new();
+        if let Some(item) = g.items.get(&self.item) {
+            buffs.stack(&item.buffs);
+            for name in &self.affixes {
+                if let Some(affix) = item.affixes.get(name) {
+                    buffs.stack(&affix.buffs);
+                }
+            }
+        }
+        buffs
+    }
+}
+
+/// what using an ability did to the item it came from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Wear {
+    Untouched,
+    /// it wore down, and has this much durability left.
+    Worn(u64),
+    /// it broke, or was used up.
+    Destroyed,
+}
+
+/// how much one inventory can hold.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub carry: Carry,
+    /// the most weight the inventory can hold, if there's a limit.
+    pub capacity: Option<f64>,
+}
+
+/// items, and how many of each there are. Most items are just counted, but
+/// unique items are held as instances, each with its own durability and
+/// affixes. Plain copies of unique items can still be added with `apply`,
+/// but anything that hands out items should `make` them. An inventory can
+/// have limits on how much it holds, which every change to it has to stay
+/// within. Limits aren't saved along with the items,
+/// so whoever loads an inventory has to set them again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Inventory {
+    items: HashMap<ItemName, u64>,
+    #[serde(default = "Vec::new")]
+    instances: Vec<ItemInstance>,
+    #[serde(skip)]
+    limits: Option<Limits>,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Inventory {
+    pub fn new() -> Self {
+        Inventory {
+            items: HashMap::new(),
+            instances: vec![],
+            limits: None,
+        }
+    }
+
+    /// a new inventory without limits holding `items`, with new instances of
+    /// the unique ones.
+    pub fn rolled(g: &GameData, items: &HashMap<ItemName, u64>, rng: &mut StdRng) -> Result<Self> {
+        let mut inventory = Inventory::new();
+        let items = items
+            .iter()
+            .filter(|(_, v)| **v > 0)
+            .map(|(k, v)| (k.clone(), *v))
+            .collect();
+        inventory.make(g, &items, rng)?;
+        Ok(inventory)
+    }
+
+    /// the plain (not instanced) items.
+    pub fn items(&self) -> &HashMap<ItemName, u64> {
+        &self.items
+    }
+
+    pub fn instances(&self) -> &[ItemInstance] {
+        &self.instances
+    }
+
+    pub fn instance(&self, id: u64) -> Option<&ItemInstance> {
+        self.instances.iter().find(|i| i.id == id)
+    }
+
+    /// how many of every item there are, counting instances.
+    pub fn counts(&self) -> HashMap<ItemName, u64> {
+        let mut counts = self.items.clone();
+        for instance in &self.instances {
+            *counts.entry(instance.item.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    pub fn limits(&self) -> Option<&Limits> {
+        self.limits.as_ref()
+    }
+
+    /// set (or take away) the inventory's limits. Whatever it already holds
+    /// stays, even if it's over the new limits, but nothing more can be added
+    /// until there's room.
+    pub fn set_limits(&mut self, limits: Option<Limits>) {
+        self.limits = limits;
+    }
+
+    /// how many of `item` there are, counting instances.
     pub fn get(&self, item: &ItemName) -> u64 {
-        if let Some(num) = self.0.get(item) {
-            num.clone()
-        } else {
-            0
+        *self.items.get(item).unwrap_or(&0)
+            + self.instances.iter().filter(|i| &i.item == item).count() as u64
+    }
+
+    /// the instance of `item` that gets used first: whichever is closest to
+    /// breaking.
+    fn most_worn(&self, item: &ItemName) -> Option<usize> {
+        self.instances
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| &i.item == item)
+            .min_by_key(|(_, i)| i.durability.unwrap_or(u64::MAX))
+            .map(|(i, _)| i)
+    }
+
+    /// the total weight of everything in the inventory.
+    pub fn weight(&self) -> f64 {
+        match &self.limits {
+            Some(limits) => self
+                .counts()
+                .iter()
+                .map(|(item, count)| limits.carry.weight(item) * *count as f64)
+                .sum(),
+            None => 0.0,
+        }
+    }
+
+    pub fn change(&mut self, item: ItemName, amount: i64) -> Result<Dropped> {
+        let mut deltas = HashMap::new();
+        deltas.insert(item, amount);
+        self.apply(&deltas)
+    }
+
+    pub fn set(&mut self, item: ItemName, num: u64) -> Result<Dropped> {
+        let have = self.get(&item);
+        self.change(item, num as i64 - have as i64)
+    }
+
+    /// how many more of each item would be needed for `deltas` to go through,
+    /// in name order. Empty if there's enough of everything.
+    pub fn shortfalls(&self, deltas: &HashMap<ItemName, i64>) -> Vec<(ItemName, u64)> {
+        let mut shortfalls: Vec<(ItemName, u64)> = deltas
+            .iter()
+            .filter(|(item, delta)| **delta < 0 && self.get(item) < delta.unsigned_abs())
+            .map(|(item, delta)| (item.clone(), delta.unsigned_abs() - self.get(item)))
+            .collect();
+        shortfalls.sort();
+        shortfalls
+    }
+
+    /// how many of each item added by `deltas` wouldn't fit, in name order.
+    /// Whatever `deltas` takes away makes room first, and then the items are
+    /// added in name order.
+    pub fn overflow(&self, deltas: &HashMap<ItemName, i64>) -> Vec<(ItemName, u64)> {
+        let mut overflow = vec![];
+        let limits = match &self.limits {
+            Some(limits) => limits,
+            None => return overflow,
+        };
+        let mut after = self.counts();
+        for (item, delta) in deltas {
+            if *delta < 0 {
+                let count = after.entry(item.clone()).or_insert(0);
+                *count = count.saturating_sub(delta.unsigned_abs());
+            }
+        }
+        after.retain(|_, v| *v > 0);
+        let mut weight: f64 = after
+            .iter()
+            .map(|(item, count)| limits.carry.weight(item) * *count as f64)
+            .sum();
+        let mut added: Vec<(&ItemName, u64)> = deltas
+            .iter()
+            .filter(|(_, delta)| **delta > 0)
+            .map(|(item, delta)| (item, *delta as u64))
+            .collect();
+        added.sort();
+        for (item, amount) in added {
+            let have = *after.get(item).unwrap_or(&0);
+            let mut room = match limits.carry.stack(item) {
+                Some(stack) => stack.saturating_sub(have),
+                None => u64::MAX - have,
+            };
+            if have == 0 {
+                if let Some(slots) = limits.carry.slots {
+                    if after.len() as u64 >= slots {
+                        room = 0;
+                    }
+                }
+            }
+            let item_weight = limits.carry.weight(item);
+            if let (Some(capacity), true) = (limits.capacity, item_weight > 0.0) {
+                let fits = ((capacity - weight) / item_weight).floor().max(0.0);
+                room = room.min(fits as u64);
+            }
+            let fits = amount.min(room);
+            if fits > 0 {
+                after.insert(item.clone(), have + fits);
+                weight += item_weight * fits as f64;
+            }
+            if fits < amount {
+                overflow.push((item.clone(), amount - fits));
+            }
+        }
+        overflow
+    }
+
+    /// a dry run of `apply`: whether the changes would go through, without
+    /// making them. Anything that wouldn't fit counts as a failure here, even
+    /// if the inventory's limits would have it dropped.
+    pub fn check(&self, deltas: &HashMap<ItemName, i64>) -> Result<()> {
+        shortfall_error(self.shortfalls(deltas))?;
+        self.checked_add(deltas)?;
+        overflow_error(self.overflow(deltas))
+    }
+
+    /// make sure none of the amounts would get too big to count.
+    fn checked_add(&self, deltas: &HashMap<ItemName, i64>) -> Result<()> {
+        for (item, delta) in deltas {
+            if *delta > 0 && self.get(item).checked_add(*delta as u64).is_none() {
+                return Err(anyhow!(format!("can't hold any more {}", item.as_str())));
+            }
+        }
+        Ok(())
+    }
+
+    /// change the amounts of several items at once. Either every change is
+    /// made, or (if there isn't enough of something) none of them are, and
+    /// the error lists everything that's missing. Anything that doesn't fit
+    /// within the inventory's limits either fails the whole change too, or is
+    /// left out and returned, depending on the gamemode. Items are taken from
+    /// the plain copies first, and then from the most worn instances. Added
+    /// items are always plain copies, see `make` for rolling new instances.
+    pub fn apply(&mut self, deltas: &HashMap<ItemName, i64>) -> Result<Dropped> {
+        shortfall_error(self.shortfalls(deltas))?;
+        self.checked_add(deltas)?;
+        let overflow = self.overflow(deltas);
+        let drop = self.limits.as_ref().map(|l| l.carry.overflow) == Some(Overflow::Drop);
+        if !drop {
+            overflow_error(overflow.clone())?;
+        }
+        let dropped: Dropped = overflow.into_iter().collect();
+        for (item, delta) in deltas {
+            let plain = *self.items.get(item).unwrap_or(&0);
+            let count = if *delta < 0 {
+                let taken = plain.min(delta.unsigned_abs());
+                for _ in taken..delta.unsigned_abs() {
+                    if let Some(i) = self.most_worn(item) {
+                        self.instances.remove(i);
+                    }
+                }
+                plain - taken
+            } else {
+                plain + *delta as u64 - dropped.get(item).unwrap_or(&0)
+            };
+            if count == 0 {
+                self.items.remove(item);
+            } else {
+                self.items.insert(item.clone(), count);
+            }
         }
+        Ok(dropped)
     }
 
-    pub fn change(&mut self, item: ItemName, amount: i64) -> Result<()> {
-        let num_item = self.get(&item);
-        if ((num_item as i64) + amount) < 0 {
+    /// add an instance of a unique item. If it doesn't fit, it's either
+    /// rejected or handed back to be dropped, depending on the gamemode.
+    pub fn add_instance(&mut self, instance: ItemInstance) -> Result<Option<ItemInstance>> {
+        if self.instance(instance.id).is_some() {
             return Err(anyhow!(format!(
-                "not enough of {:?} in this inventory!",
-                item
+                "there's already an item with id {} here",
+                instance.id
             )));
-        } else if ((num_item as i64) + amount) == 0 {
-            self.0.remove(&item);
+        }
+        let mut deltas = HashMap::new();
+        deltas.insert(instance.item.clone(), 1);
+        let overflow = self.overflow(&deltas);
+        if overflow.is_empty() {
+            self.instances.push(instance);
+            Ok(None)
+        } else if self.limits.as_ref().map(|l| l.carry.overflow) == Some(Overflow::Drop) {
+            Ok(Some(instance))
         } else {
-            self.set(item, ((num_item as i64) + amount) as u64);
+            overflow_error(overflow).map(|_| None)
+        }
+    }
+
+    /// take an instance out of the inventory.
+    pub fn take_instance(&mut self, id: u64) -> Result<ItemInstance> {
+        match self.instances.iter().position(|i| i.id == id) {
+            Some(i) => Ok(self.instances.remove(i)),
+            None => Err(anyhow!(format!("there's no item with id {} here", id))),
+        }
+    }
+
+    /// add some newly made items. Unique items get new instances, with their
+    /// affixes rolled. Like `apply`, anything that doesn't fit either fails
+    /// the whole thing or is dropped.
+    pub fn make(
+        &mut self,
+        g: &GameData,
+        items: &HashMap<ItemName, u64>,
+        rng: &mut StdRng,
+    ) -> Result<Dropped> {
+        let deltas: HashMap<ItemName, i64> =
+            items.iter().map(|(k, v)| (k.clone(), *v as i64)).collect();
+        self.checked_add(&deltas)?;
+        if self.limits.as_ref().map(|l| l.carry.overflow) != Some(Overflow::Drop) {
+            overflow_error(self.overflow(&deltas))?;
+        }
+        let mut dropped = Dropped::new();
+        let mut names: Vec<&ItemName> = items.keys().collect();
+        names.sort();
+        for name in names {
+            let item = g
+                .items
+                .get(name)
+                .ok_or_else(|| anyhow!(format!("{:?} is not an item", name)))?;
+            if item.unique {
+                for _ in 0..items[name] {
+                    if self.add_instance(ItemInstance::roll(item, rng))?.is_some() {
+                        *dropped.entry(name.clone()).or_insert(0) += 1;
+                    }
+                }
+            } else {
+                dropped.extend(self.change(name.clone(), items[name] as i64)?);
+            }
+        }
+        Ok(dropped)
+    }
+
+    /// move some items from this inventory to another, all or nothing.
+    /// `instances` are the ids of particular instances to move. `items` are
+    /// taken the same way `apply` takes them, plain copies first and then the
+    /// most worn instances, on top of those. Instances move across as they
+    /// are, durability, affixes and all. Nothing is ever dropped: if `to`
+    /// doesn't have room for all of it, nothing moves.
+    pub fn transfer(
+        &mut self,
+        to: &mut Inventory,
+        items: &HashMap<ItemName, u64>,
+        instances: &[u64],
+    ) -> Result<()> {
+        let mut moving: HashMap<ItemName, i64> =
+            items.iter().map(|(k, v)| (k.clone(), *v as i64)).collect();
+        for (i, id) in instances.iter().enumerate() {
+            let instance = self
+                .instance(*id)
+                .ok_or_else(|| anyhow!(format!("there's no item with id {} here", id)))?;
+            if instances[..i].contains(id) {
+                return Err(anyhow!(format!("item {} can only be moved once", id)));
+            }
+            if to.instance(*id).is_some() {
+                return Err(anyhow!(format!(
+                    "there's already an item with id {} there",
+                    id
+                )));
+            }
+            *moving.entry(instance.item.clone()).or_insert(0) += 1;
+        }
+        let take: HashMap<ItemName, i64> =
+            moving.iter().map(|(k, v)| (k.clone(), -*v)).collect();
+        self.check(&take)?;
+        to.check(&moving)?;
+
+        let mut moved = vec![];
+        for id in instances {
+            moved.push(self.take_instance(*id)?);
+        }
+        let mut plain = HashMap::new();
+        for (item, count) in items {
+            let have = *self.items.get(item).unwrap_or(&0);
+            plain.insert(item.clone(), have.min(*count) as i64);
+            for _ in have..*count {
+                let i = self
+                    .most_worn(item)
+                    .ok_or_else(|| anyhow!(format!("need more {}", item.as_str())))?;
+                moved.push(self.instances.remove(i));
+            }
+        }
+        let take: HashMap<ItemName, i64> = plain.iter().map(|(k, v)| (k.clone(), -*v)).collect();
+        self.apply(&take)?;
+        // `to.check` made sure everything fits, so none of this can be dropped
+        let dropped = to.apply(&plain)?;
+        if !dropped.is_empty() {
+            return Err(anyhow!(format!("{:?} didn't fit", dropped)));
+        }
+        for instance in moved {
+            if let Some(instance) = to.add_instance(instance)? {
+                return Err(anyhow!(format!("{} didn't fit", instance.item.as_str())));
+            }
         }
         Ok(())
     }
 
-    pub fn set(&mut self, item: ItemName, num: u64) {
-        self.0.insert(item, num);
+    /// check that everything `ability` needs is here. `source` is the item
+    /// the ability belongs to, which also gets used up if the ability
+    /// destroys it. If the inventory rejects anything that doesn't fit, this
+    /// also checks there's room for whatever the ability makes.
+    pub fn check_ability(&self, ability: &Ability, source: Option<&ItemName>) -> Result<()> {
+        let mut needed = ability.require_items.clone();
+        if let Some(source) = source {
+            let mut count = 1;
+            if ability.destroy_item {
+                count += ability.remove_items.get(source).unwrap_or(&0);
+            }
+            let entry = needed.entry(source.clone()).or_insert(0);
+            *entry = (*entry).max(count);
+        }
+        let needed = needed.into_iter().map(|(k, v)| (k, -(v as i64))).collect();
+        shortfall_error(self.shortfalls(&needed))?;
+        match &self.limits {
+            Some(limits) if limits.carry.overflow == Overflow::Reject => {
+                let destroyed = source.filter(|_| ability.destroy_item);
+                overflow_error(self.overflow(&Self::ability_deltas(ability, destroyed, true)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// every change using `ability` makes to an inventory. `destroyed` is the
+    /// plain item it uses up, if any, and `made` is whether to include its
+    /// make_items.
+    fn ability_deltas(
+        ability: &Ability,
+        destroyed: Option<&ItemName>,
+        made: bool,
+    ) -> HashMap<ItemName, i64> {
+        let mut deltas = HashMap::new();
+        for (item, count) in &ability.remove_items {
+            *deltas.entry(item.clone()).or_insert(0) -= *count as i64;
+        }
+        if let Some(destroyed) = destroyed {
+            *deltas.entry(destroyed.clone()).or_insert(0) -= 1;
+        }
+        if made {
+            for (item, count) in &ability.make_items {
+                *deltas.entry(item.clone()).or_insert(0) += *count as i64;
+            }
+        }
+        deltas
+    }
+
+    /// take away everything `ability` uses up: its remove_items, and `source`
+    /// if it destroys it. Either all of it is taken, or (if anything is
+    /// missing) none of it is. If `source` is held as instances, the most
+    /// worn one is used: it loses a point of durability, and is only
+    /// destroyed when that hits 0 (or if it never wears out, and the ability
+    /// destroys it). Returns what happened to `source`.
+    pub fn pay_for(&mut self, ability: &Ability, source: Option<&ItemName>) -> Result<Wear> {
+        self.check_ability(ability, source)?;
+        let used = source
+            .and_then(|s| self.most_worn(s))
+            .filter(|i| self.instances[*i].durability.is_some() || ability.destroy_item);
+        let destroyed = source.filter(|_| ability.destroy_item && used.is_none());
+        // the instance being used comes out first, so that paying for
+        // remove_items can't take it instead
+        let instance = used.map(|i| (i, self.instances.remove(i)));
+        if let Err(e) = self.apply(&Self::ability_deltas(ability, destroyed, false)) {
+            if let Some((i, instance)) = instance {
+                self.instances.insert(i, instance);
+            }
+            return Err(e);
+        }
+        let (i, mut instance) = match instance {
+            Some(instance) => instance,
+            None if destroyed.is_some() => return Ok(Wear::Destroyed),
+            None => return Ok(Wear::Untouched),
+        };
+        match instance.durability {
+            Some(durability) if durability > 1 => {
+                instance.durability = Some(durability - 1);
+                self.instances.insert(i.min(self.instances.len()), instance);
+                Ok(Wear::Worn(durability - 1))
+            }
+            _ => Ok(Wear::Destroyed),
+        }
+    }
+
+    /// use `ability`'s items all in one go: check its require_items, take away
+    /// whatever it uses up, and add its make_items. If any of that fails, the
+    /// inventory is left as it was. Returns what happened to `source`, and
+    /// whatever was made but didn't fit.
+    pub fn use_ability(
+        &mut self,
+        g: &GameData,
+        ability: &Ability,
+        source: Option<&ItemName>,
+        rng: &mut StdRng,
+    ) -> Result<(Wear, Dropped)> {
+        self.check_ability(ability, source)?;
+        let before = self.clone();
+        let result = self
+            .pay_for(ability, source)
+            .and_then(|wear| Ok((wear, self.make(g, &ability.make_items, rng)?)));
+        if result.is_err() {
+            *self = before;
+        }
+        result
+    }
+}
+
+/// an error listing everything that won't fit, e.g. "no room for 3 more
+/// iron", or nothing if everything will.
+fn overflow_error(overflow: Vec<(ItemName, u64)>) -> Result<()> {
+    if overflow.is_empty() {
+        return Ok(());
+    }
+    let extra: Vec<String> = overflow
+        .iter()
+        .map(|(item, count)| format!("{} more {}", count, item.as_str()))
+        .collect();
+    Err(anyhow!(format!("no room for {}", extra.join(", "))))
+}
+
+/// an error listing everything that's missing, e.g. "need 2 more honour, 1
+/// more ink", or nothing if nothing is.
+fn shortfall_error(shortfalls: Vec<(ItemName, u64)>) -> Result<()> {
+    if shortfalls.is_empty() {
+        return Ok(());
+    }
+    let missing: Vec<String> = shortfalls
+        .iter()
+        .map(|(item, count)| format!("{} more {}", count, item.as_str()))
+        .collect();
+    Err(anyhow!(format!("need {}", missing.join(", "))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        gamedata::{gamedata::pvp, item::AbilityDeser},
+        world::get_rand,
+    };
+    use serde_jacl::de::from_str;
+
+    fn n(name: &str) -> ItemName {
+        ItemName::from(name.to_string())
+    }
+
+    fn deltas(changes: &[(&str, i64)]) -> HashMap<ItemName, i64> {
+        changes.iter().map(|(k, v)| (n(k), *v)).collect()
+    }
+
+    fn limited(g: &GameData, capacity: f64, overflow: Overflow) -> Inventory {
+        let mut carry = g.carry.clone();
+        carry.overflow = overflow;
+        let mut inventory = Inventory::new();
+        inventory.set_limits(Some(Limits {
+            carry,
+            capacity: Some(capacity),
+        }));
+        inventory
+    }
+
+    fn ability(g: &GameData, jacl: &str) -> Ability {
+        let deser: AbilityDeser = from_str(jacl).unwrap();
+        deser
+            .into_ability(
+                &g.dmg,
+                &g.items.keys().cloned().collect(),
+                &g.statuses.keys().cloned().collect(),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn apply_is_all_or_nothing() {
+        let mut inventory = Inventory::new();
+        inventory
+            .apply(&deltas(&[("iron", 3), ("stick", 1)]))
+            .unwrap();
+        let err = inventory
+            .apply(&deltas(&[("iron", -2), ("stick", -2), ("wood", 1)]))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "need 1 more stick");
+        assert_eq!(inventory.get(&n("iron")), 3);
+        assert_eq!(inventory.get(&n("wood")), 0);
+        inventory
+            .apply(&deltas(&[("iron", -2), ("stick", -1)]))
+            .unwrap();
+        assert_eq!(inventory.get(&n("iron")), 1);
+        assert!(!inventory.items().contains_key(&n("stick")));
+    }
+
+    #[test]
+    fn shortfalls_list_everything_missing() {
+        let mut inventory = Inventory::new();
+        inventory.change(n("iron"), 1).unwrap();
+        let changes = deltas(&[("iron", -3), ("stick", -1), ("wood", 2)]);
+        assert_eq!(
+            inventory.shortfalls(&changes),
+            vec![(n("iron"), 2), (n("stick"), 1)]
+        );
+        assert!(inventory.check(&changes).is_err());
+        assert_eq!(inventory.get(&n("iron")), 1);
+    }
+
+    #[test]
+    fn extra_weight_is_dropped() {
+        let g = pvp();
+        // iron weighs 2
+        let mut inventory = limited(&g, 10.0, Overflow::Drop);
+        let dropped = inventory.change(n("iron"), 7).unwrap();
+        assert_eq!(dropped.get(&n("iron")), Some(&2));
+        assert_eq!(inventory.get(&n("iron")), 5);
+        assert_eq!(inventory.weight(), 10.0);
+    }
+
+    #[test]
+    fn extra_weight_is_rejected() {
+        let g = pvp();
+        let mut inventory = limited(&g, 10.0, Overflow::Reject);
+        let err = inventory.change(n("iron"), 7).unwrap_err();
+        assert_eq!(err.to_string(), "no room for 2 more iron");
+        assert_eq!(inventory.get(&n("iron")), 0);
+        // taking things out makes room first
+        inventory.change(n("iron"), 5).unwrap();
+        inventory
+            .apply(&deltas(&[("iron", -1), ("stick", 2)]))
+            .unwrap();
+        assert_eq!(inventory.get(&n("stick")), 2);
+    }
+
+    #[test]
+    fn stacks_are_limited() {
+        let g = pvp();
+        // oranges stack up to 20
+        let mut inventory = limited(&g, 1000.0, Overflow::Drop);
+        let dropped = inventory.change(n("orange"), 25).unwrap();
+        assert_eq!(dropped.get(&n("orange")), Some(&5));
+        assert_eq!(inventory.get(&n("orange")), 20);
+    }
+
+    #[test]
+    fn tighter_limits_keep_what_is_there() {
+        let g = pvp();
+        let mut inventory = limited(&g, 10.0, Overflow::Reject);
+        inventory.change(n("iron"), 5).unwrap();
+        inventory.set_limits(limited(&g, 4.0, Overflow::Reject).limits);
+        assert_eq!(inventory.get(&n("iron")), 5);
+        assert!(inventory.change(n("iron"), 1).is_err());
+        inventory.change(n("iron"), -4).unwrap();
+        inventory.change(n("iron"), 1).unwrap();
+    }
+
+    #[test]
+    fn use_ability_is_all_or_nothing() {
+        let g = pvp();
+        let mut rng = get_rand(0);
+        let craft = &g.items[&n("workbench")].abilities["iron_sword"];
+        let mut inventory = Inventory::new();
+        inventory
+            .apply(&deltas(&[("stick", 1), ("iron", 5)]))
+            .unwrap();
+        assert!(inventory
+            .use_ability(&g, craft, Some(&n("workbench")), &mut rng)
+            .is_err());
+        inventory.change(n("workbench"), 1).unwrap();
+        assert!(inventory
+            .use_ability(&g, craft, Some(&n("workbench")), &mut rng)
+            .is_err());
+        assert_eq!(inventory.get(&n("iron")), 5);
+        inventory.change(n("iron"), 5).unwrap();
+        let (wear, dropped) = inventory
+            .use_ability(&g, craft, Some(&n("workbench")), &mut rng)
+            .unwrap();
+        assert_eq!(wear, Wear::Untouched);
+        assert!(dropped.is_empty());
+        assert_eq!(inventory.get(&n("iron")), 0);
+        assert_eq!(inventory.get(&n("stick")), 0);
+        // swords are unique, so they're made as instances
+        assert!(inventory.items().get(&n("iron_sword")).is_none());
+        assert_eq!(inventory.instances().len(), 1);
+        assert_eq!(inventory.instances()[0].durability, Some(40));
+    }
+    #[test]
+    fn the_most_worn_instance_wears_out_first() {
+        let g = pvp();
+        let mut items = HashMap::new();
+        items.insert(n("iron_sword"), 2);
+        let mut inventory = Inventory::rolled(&g, &items, &mut get_rand(0)).unwrap();
+        assert_eq!(inventory.instances().len(), 2);
+        inventory.instances[1].durability = Some(1);
+        let worn = inventory.instances[1].id;
+        let stab = &g.items[&n("iron_sword")].abilities["stab"];
+        let source = Some(&n("iron_sword"));
+        assert_eq!(inventory.pay_for(stab, source).unwrap(), Wear::Destroyed);
+        assert!(inventory.instance(worn).is_none());
+        assert_eq!(inventory.pay_for(stab, source).unwrap(), Wear::Worn(39));
+        assert_eq!(inventory.get(&n("iron_sword")), 1);
+    }
+
+    #[test]
+    fn remove_items_leave_the_used_instance_alone() {
+        let g = pvp();
+        let mut items = HashMap::new();
+        items.insert(n("iron_sword"), 2);
+        let mut inventory = Inventory::rolled(&g, &items, &mut get_rand(0)).unwrap();
+        inventory.instances[1].durability = Some(5);
+        let used = inventory.instances[1].id;
+        let sacrifice = ability(&g, r#"remove_items : { "iron_sword" : 1 }"#);
+        assert_eq!(
+            inventory
+                .pay_for(&sacrifice, Some(&n("iron_sword")))
+                .unwrap(),
+            Wear::Worn(4)
+        );
+        assert_eq!(inventory.instances().len(), 1);
+        assert_eq!(inventory.instances()[0].id, used);
+    }
+
+    #[test]
+    fn transfers_move_instances() {
+        let g = pvp();
+        let mut items = HashMap::new();
+        items.insert(n("iron_sword"), 2);
+        items.insert(n("iron"), 3);
+        let mut from = Inventory::rolled(&g, &items, &mut get_rand(0)).unwrap();
+        let mut to = Inventory::new();
+        let picked = from.instances()[1].clone();
+        let mut iron = HashMap::new();
+        iron.insert(n("iron"), 2);
+        from.transfer(&mut to, &iron, &[picked.id]).unwrap();
+        assert_eq!(to.instance(picked.id), Some(&picked));
+        assert!(from.instance(picked.id).is_none());
+        assert_eq!(to.get(&n("iron")), 2);
+        assert_eq!(from.get(&n("iron")), 1);
+
+        // an instance that isn't there fails the whole transfer
+        assert!(from.transfer(&mut to, &iron, &[picked.id]).is_err());
+        let mut too_many = HashMap::new();
+        too_many.insert(n("iron_sword"), 2);
+        assert!(from.transfer(&mut to, &too_many, &[]).is_err());
+        assert_eq!(from.get(&n("iron")), 1);
+        assert_eq!(from.get(&n("iron_sword")), 1);
+
+        // counted unique items move as instances too
+        let kept = from.instances()[0].clone();
+        let mut sword = HashMap::new();
+        sword.insert(n("iron_sword"), 1);
+        from.transfer(&mut to, &sword, &[]).unwrap();
+        assert_eq!(to.instance(kept.id), Some(&kept));
+        assert_eq!(to.instances().len(), 2);
+    }
+
+    #[test]
+    fn transfers_never_drop_anything() {
+        let g = pvp();
+        let mut items = HashMap::new();
+        items.insert(n("iron_sword"), 2);
+        items.insert(n("iron"), 6);
+        let mut from = Inventory::rolled(&g, &items, &mut get_rand(0)).unwrap();
+        // swords weigh 8 and iron weighs 2
+        let mut to = limited(&g, 10.0, Overflow::Drop);
+        let mut iron = HashMap::new();
+        iron.insert(n("iron"), 6);
+        let err = from.transfer(&mut to, &iron, &[]).unwrap_err();
+        assert_eq!(err.to_string(), "no room for 1 more iron");
+        let ids: Vec<u64> = from.instances().iter().map(|i| i.id).collect();
+        assert!(from.transfer(&mut to, &HashMap::new(), &ids).is_err());
+        assert_eq!(from.counts(), items);
+        assert!(to.counts().is_empty());
+        iron.insert(n("iron"), 1);
+        from.transfer(&mut to, &iron, &ids[..1]).unwrap();
+        assert_eq!(to.weight(), 10.0);
     }
 }
@@ -0,0 +1,15 @@
+pub mod active_buffs;
+pub mod battle;
+pub mod equipment;
+pub mod gamedata;
+pub mod inventory;
+pub mod noise;
+pub mod pathfinding;
+pub mod player;
+pub mod rgb;
+pub mod sim;
+pub mod vector3;
+pub mod visibility;
+pub mod world;
+pub mod mob;
+pub mod mob_ai;
@@ -1,16 +1,12 @@
 use anyhow::Result;
 use serde_jacl::de::from_str;
 use std::fs;
-use gamedata::{item::Item, gamedata::{DmgType, GameMode}, mobtemplate::MobTemplate, block::Block};
-use world::World;
-
-mod gamedata;
-mod inventory;
-mod noise;
-mod rgb;
-mod vector3;
-mod world;
-mod mob;
+use mirae_server_new::{
+    gamedata::{item::Item, gamedata::{DmgType, GameMode}, mobtemplate::MobTemplate, block::Block},
+    mob_ai,
+    vector3::Vector3,
+    world::World,
+};
 
 fn main() -> Result<()> {
     let m: GameMode = from_str(&fs::read_to_string("pvp/gamemode.jacl")?)?;
@@ -28,7 +24,14 @@ fn main() -> Result<()> {
     println!("{:?}", mobs);
     println!("");
     println!("{:?}", blocks);
-    let _ = World::from_seed(0, &g)?;
+    let mut world = World::from_seed(0, &g)?;
+    let dim = world.dim();
+    let players = [Vector3::new(dim.x() / 2, dim.y() / 2, 0)];
+    for _ in 0..10 {
+        mob_ai::tick(&mut world, &players, &g)?;
+    }
+    println!();
+    println!("{} mobs spawned after 10 ticks", world.num_spawned_mobs());
 
     Ok(())
 }
@@ -1,8 +1,18 @@
-use crate::{gamedata::mobtemplate::MobTemplate, vector3::Vector3};
+use crate::{
+    battle::combatant::Combatant,
+    gamedata::{gamedata::MobName, mobtemplate::MobTemplate},
+    mob_ai::MobState,
+    vector3::Vector3,
+};
 
 #[derive(Debug, Clone)]
 pub struct Mob {
-    id : u64
+    id: u64,
+    name: MobName,
+    home: Vector3,
+    state: MobState,
+    damage_taken: f64,
+    looted: bool,
 }
 
 impl Mob {
@@ -10,9 +20,59 @@ impl Mob {
         self.id
     }
 
-    pub fn new(id : u64, loc : Vector3, template : &MobTemplate) -> Self {
+    pub fn name(&self) -> &MobName {
+        &self.name
+    }
+
+    pub fn home(&self) -> Vector3 {
+        self.home
+    }
+
+    pub fn state(&self) -> MobState {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: MobState) {
+        self.state = state;
+    }
+
+    pub fn new(id: u64, loc: Vector3, template: &MobTemplate) -> Self {
         Self {
-            id
+            id,
+            name: template.name.clone(),
+            home: loc,
+            state: MobState::Idle,
+            damage_taken: 0.0,
+            looted: false,
+        }
+    }
+
+    pub fn damage(&mut self, amount: f64) {
+        self.damage_taken += amount;
+    }
+
+    pub fn loot(&mut self) {
+        self.looted = true;
+    }
+
+    /// remember what a battle did to this mob, given how it was when the
+    /// battle started and how it ended up. Any health it lost counts as
+    /// damage, and any items taken from it (or used up) count as looting.
+    pub fn fought(&mut self, start: &Combatant, end: &Combatant) {
+        let lost = start.health - end.health;
+        if lost > 0.0 {
+            self.damage(lost);
+        }
+        if end.inventory.counts() != start.inventory.counts() {
+            self.loot();
         }
     }
+
+    /// true if nothing has happened to this mob since it was spawned, and it
+    /// isn't busy chasing someone or heading home, meaning it can be thrown
+    /// away and spawned again from its template. It might have wandered away
+    /// from home though.
+    pub fn is_pristine(&self) -> bool {
+        self.damage_taken == 0.0 && !self.looted && self.state == MobState::Idle
+    }
 }
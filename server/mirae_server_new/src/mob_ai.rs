@@ -0,0 +1,283 @@
+use crate::{
+    gamedata::{block::Block, gamedata::GameData, mobtemplate::Behavior},
+    mob::Mob,
+    pathfinding,
+    vector3::Vector3,
+    world::World,
+};
+use anyhow::{anyhow, Result};
+use rand::Rng;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MobState {
+    /// wandering around near home.
+    Idle,
+    /// following the nearest player.
+    Chasing,
+    /// lost track of its target, walking back home.
+    Returning,
+}
+
+/// advance every mob that could interact with one of the players by one step,
+/// then despawn idle mobs that are well away from every player.
+pub fn tick(world: &mut World, players: &[Vector3], g: &GameData) -> Result<()> {
+    // mobs only have a state once they're spawned, so make sure that every mob
+    // that could notice a player this tick exists.
+    let active_radius = g
+        .mob_templates
+        .values()
+        .map(|t| t.behavior.aggro_radius)
+        .max()
+        .unwrap_or(0) as usize;
+    world.spawn_mobs_near(players, active_radius, g)?;
+
+    for (loc, mut mob) in world.spawned_mobs() {
+        // only players on the same layer can be chased, so idle mobs on other
+        // layers have nothing to do. Mobs that were chasing still need to
+        // head home.
+        if mob.state() == MobState::Idle && players.iter().all(|p| p.z() != loc.z()) {
+            continue;
+        }
+        let behavior = &g
+            .mob_templates
+            .get(mob.name())
+            .ok_or_else(|| anyhow!(format!("invalid mob name {:?}", mob.name())))?
+            .behavior;
+        let roll = (world.rng().gen(), world.rng().gen_range(0, 4));
+        let (state, step) = decide(world, g, &mob, loc, behavior, players, roll)?;
+        if let Some(step) = step {
+            world.move_mob(loc, step)?;
+        }
+        mob.set_state(state);
+        world.update_mob(mob)?;
+    }
+    // twice the radius mobs are spawned in, so that mobs near the edge don't
+    // keep getting spawned and despawned
+    world.despawn_idle_mobs(players, (active_radius * 2) as f64)?;
+    Ok(())
+}
+
+/// work out what state the mob at `loc` should be in, and where (if anywhere)
+/// it should step to this tick. `roll` is a random number in [0, 1) deciding
+/// whether an idle mob moves, and a random direction for it to move in.
+fn decide(
+    world: &World,
+    g: &GameData,
+    mob: &Mob,
+    loc: Vector3,
+    behavior: &Behavior,
+    players: &[Vector3],
+    (roll, dir): (f64, usize),
+) -> Result<(MobState, Option<Vector3>)> {
+    let home = mob.home();
+    let leashed = home.dist(&loc) <= behavior.leash_radius as f64;
+    let target = players
+        .iter()
+        .filter(|p| p.z() == loc.z() && p.dist(&loc) <= behavior.aggro_radius as f64)
+        .min_by(|a, b| a.sqr_dist(&loc).total_cmp(&b.sqr_dist(&loc)));
+
+    match target {
+        Some(target) if leashed && behavior.aggro_radius > 0 => Ok((
+            MobState::Chasing,
+            step_towards(world, g, loc, *target, behavior, players)?,
+        )),
+        _ => {
+            let returning = mob.state() != MobState::Idle
+                && home.dist(&loc) > behavior.home_radius as f64;
+            if returning {
+                Ok((
+                    MobState::Returning,
+                    step_towards(world, g, loc, home, behavior, players)?,
+                ))
+            } else if roll < behavior.wander_chance {
+                let step = neighbours(loc, world.dim())
+                    .into_iter()
+                    .nth(dir)
+                    .flatten()
+                    .filter(|n| home.dist(n) <= behavior.home_radius as f64);
+                match step {
+                    Some(n) if can_enter(world, g, n, players)? => Ok((MobState::Idle, Some(n))),
+                    _ => Ok((MobState::Idle, None)),
+                }
+            } else {
+                Ok((MobState::Idle, None))
+            }
+        }
+    }
+}
+
+/// the first step on the way from `loc` to `target`, if there is a way there
+/// and the step isn't blocked by someone else.
+fn step_towards(
+    world: &World,
+    g: &GameData,
+    loc: Vector3,
+    target: Vector3,
+    behavior: &Behavior,
+    players: &[Vector3],
+) -> Result<Option<Vector3>> {
+    // a mob never goes further than its leash, so there's no point searching
+    // past the area it could possibly cover.
+    let side = behavior.leash_radius as usize * 2 + 1;
+    // mobs stay on their own layer, so they don't go into the blocks that
+    // lead to other layers
+    let cost = |b: &Block| if b.z_passable { f64::INFINITY } else { 1.0 };
+    let path = pathfinding::find_path(world, g, loc, target, side * side, Some(&cost))?;
+    match path.and_then(|p| p.first().cloned()) {
+        Some(step) if can_enter(world, g, step, players)? => Ok(Some(step)),
+        _ => Ok(None),
+    }
+}
+
+fn can_enter(world: &World, g: &GameData, loc: Vector3, players: &[Vector3]) -> Result<bool> {
+    Ok(!world.get_block(loc, g)?.solid && !world.has_mob(loc)? && !players.contains(&loc))
+}
+
+/// the tiles next to `loc` on the same layer, in the order up, right, down,
+/// left. Neighbours that would be out of bounds are `None`.
+fn neighbours(loc: Vector3, dim: Vector3) -> Vec<Option<Vector3>> {
+    vec![
+        loc.y().checked_sub(1).map(|y| Vector3::new(loc.x(), y, loc.z())),
+        Some(loc.x() + 1)
+            .filter(|x| *x < dim.x())
+            .map(|x| Vector3::new(x, loc.y(), loc.z())),
+        Some(loc.y() + 1)
+            .filter(|y| *y < dim.y())
+            .map(|y| Vector3::new(loc.x(), y, loc.z())),
+        loc.x().checked_sub(1).map(|x| Vector3::new(x, loc.y(), loc.z())),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamedata::gamedata::{pvp, MobName};
+
+    /// the pvp gamemode, with lowly_ben behaving the given way.
+    fn gamedata(behavior: Behavior) -> GameData {
+        let mut g = pvp();
+        let name = MobName::from("lowly_ben".to_string());
+        g.mob_templates.get_mut(&name).unwrap().behavior = behavior;
+        g
+    }
+
+    fn chaser() -> Behavior {
+        Behavior {
+            home_radius: 0,
+            aggro_radius: 3,
+            leash_radius: 5,
+            wander_chance: 0.0,
+        }
+    }
+
+    fn world_with_mob(g: &GameData, dim: Vector3, home: Vector3) -> World {
+        let mut world = World::filled(dim, "grass", g);
+        world.put_mob(home, "lowly_ben", g);
+        world
+    }
+
+    /// where the one mob in the world is, and what it's doing.
+    fn only_mob(world: &World) -> (Vector3, MobState) {
+        let mobs = world.spawned_mobs();
+        assert_eq!(mobs.len(), 1);
+        (mobs[0].0, mobs[0].1.state())
+    }
+
+    #[test]
+    fn chases_players_then_heads_home() {
+        let g = gamedata(chaser());
+        let home = Vector3::new(5, 5, 0);
+        let mut world = world_with_mob(&g, Vector3::new(20, 20, 1), home);
+        tick(&mut world, &[Vector3::new(8, 5, 0)], &g).unwrap();
+        assert_eq!(only_mob(&world), (Vector3::new(6, 5, 0), MobState::Chasing));
+        tick(&mut world, &[Vector3::new(8, 5, 0)], &g).unwrap();
+        assert_eq!(only_mob(&world), (Vector3::new(7, 5, 0), MobState::Chasing));
+        // the player is now out of range, but close enough to keep the mob
+        // from despawning
+        let away = [Vector3::new(12, 5, 0)];
+        tick(&mut world, &away, &g).unwrap();
+        assert_eq!(
+            only_mob(&world),
+            (Vector3::new(6, 5, 0), MobState::Returning)
+        );
+        tick(&mut world, &away, &g).unwrap();
+        assert_eq!(only_mob(&world), (home, MobState::Returning));
+        // once it's home and idle, it's far enough away to be despawned
+        tick(&mut world, &away, &g).unwrap();
+        assert_eq!(world.num_spawned_mobs(), 0);
+        assert!(world.has_mob(home).unwrap());
+    }
+
+    #[test]
+    fn gives_up_at_the_end_of_its_leash() {
+        let g = gamedata(Behavior {
+            leash_radius: 1,
+            ..chaser()
+        });
+        let mut world = world_with_mob(&g, Vector3::new(20, 20, 1), Vector3::new(5, 5, 0));
+        let players = [Vector3::new(8, 5, 0)];
+        tick(&mut world, &players, &g).unwrap();
+        tick(&mut world, &players, &g).unwrap();
+        assert_eq!(only_mob(&world), (Vector3::new(7, 5, 0), MobState::Chasing));
+        tick(&mut world, &players, &g).unwrap();
+        assert_eq!(
+            only_mob(&world),
+            (Vector3::new(6, 5, 0), MobState::Returning)
+        );
+    }
+
+    #[test]
+    fn ignores_players_on_other_layers() {
+        let g = gamedata(chaser());
+        let home = Vector3::new(5, 5, 0);
+        let mut world = world_with_mob(&g, Vector3::new(20, 20, 2), home);
+        tick(&mut world, &[Vector3::new(6, 5, 1)], &g).unwrap();
+        assert_eq!(world.num_spawned_mobs(), 0);
+        assert!(world.has_mob(home).unwrap());
+    }
+
+    #[test]
+    fn never_walks_into_walls_or_players() {
+        let g = gamedata(chaser());
+        let mut world = world_with_mob(&g, Vector3::new(20, 20, 1), Vector3::new(5, 5, 0));
+        world.set_block(Vector3::new(6, 5, 0), "rock", &g);
+        let players = [Vector3::new(7, 5, 0)];
+        for _ in 0..5 {
+            tick(&mut world, &players, &g).unwrap();
+            let (loc, _) = only_mob(&world);
+            assert!(!world.get_block(loc, &g).unwrap().solid);
+            assert_ne!(loc, players[0]);
+        }
+        // it went around the wall, and is now next to the player
+        assert_eq!(only_mob(&world).0.dist(&players[0]), 1.0);
+    }
+
+    #[test]
+    fn wanders_within_its_home_radius() {
+        let g = gamedata(Behavior {
+            home_radius: 1,
+            wander_chance: 0.5,
+            ..chaser()
+        });
+        let behavior = &g.mob_templates[&MobName::from("lowly_ben".to_string())].behavior;
+        let home = Vector3::new(5, 5, 0);
+        let mut world = world_with_mob(&g, Vector3::new(20, 20, 1), home);
+        let mob = world.get_mob(home, &g).unwrap();
+        let edge = Vector3::new(6, 5, 0);
+        world.move_mob(home, edge).unwrap();
+        let steps: Vec<Option<Vector3>> = (0..4)
+            .map(|dir| {
+                decide(&world, &g, &mob, edge, behavior, &[], (0.0, dir))
+                    .unwrap()
+                    .1
+            })
+            .collect();
+        // up, right, down and left: only going left stays close enough
+        assert_eq!(steps, vec![None, None, None, Some(home)]);
+        // and it only moves as often as its wander chance says
+        assert_eq!(
+            decide(&world, &g, &mob, edge, behavior, &[], (0.5, 3)).unwrap(),
+            (MobState::Idle, None)
+        );
+    }
+}
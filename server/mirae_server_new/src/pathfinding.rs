@@ -0,0 +1,292 @@
+use crate::{
+    gamedata::{block::Block, gamedata::GameData},
+    vector3::Vector3,
+    world::World,
+};
+use anyhow::{anyhow, Result};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+/// the cost of stepping onto a block. Returning `f64::INFINITY` makes the block
+/// impassable. Costs below 1 are allowed, but the path found may then not be
+/// the cheapest one.
+pub type CostFn<'a> = &'a dyn Fn(&Block) -> f64;
+
+struct Node {
+    estimate: f64,
+    loc: Vector3,
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for Node {}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Node {
+    // reversed, so that the BinaryHeap pops the cheapest node first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .partial_cmp(&self.estimate)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+fn manhattan(a: Vector3, b: Vector3) -> f64 {
+    let d = |a: usize, b: usize| (a as f64 - b as f64).abs();
+    d(a.x(), b.x()) + d(a.y(), b.y()) + d(a.z(), b.z())
+}
+
+/// whether a block can be stepped onto. z_passable blocks (e.g. water, or a
+/// hole in the ground) are the ways between layers, so they can be entered
+/// even if they're solid.
+fn can_enter(block: &Block) -> bool {
+    !block.solid || block.z_passable
+}
+
+/// the tiles that can be reached from `loc` in one step.
+/// Horizontal steps can go onto any block that can be entered. Vertical steps
+/// are only possible out of a z_passable block, onto a block that can be
+/// entered, so a path changes layer by going into a z_passable block and then
+/// up or down from it.
+pub fn neighbours(world: &World, g: &GameData, loc: Vector3) -> Result<Vec<Vector3>> {
+    let dim = world.dim();
+    let from = world.get_block(loc, g)?;
+    let mut horizontal = vec![];
+    if loc.x() > 0 {
+        horizontal.push(Vector3::new(loc.x() - 1, loc.y(), loc.z()));
+    }
+    if loc.x() + 1 < dim.x() {
+        horizontal.push(Vector3::new(loc.x() + 1, loc.y(), loc.z()));
+    }
+    if loc.y() > 0 {
+        horizontal.push(Vector3::new(loc.x(), loc.y() - 1, loc.z()));
+    }
+    if loc.y() + 1 < dim.y() {
+        horizontal.push(Vector3::new(loc.x(), loc.y() + 1, loc.z()));
+    }
+    let mut vertical = vec![];
+    if from.z_passable {
+        if loc.z() > 0 {
+            vertical.push(Vector3::new(loc.x(), loc.y(), loc.z() - 1));
+        }
+        if loc.z() + 1 < dim.z() {
+            vertical.push(Vector3::new(loc.x(), loc.y(), loc.z() + 1));
+        }
+    }
+
+    let mut res = vec![];
+    for n in horizontal.into_iter().chain(vertical) {
+        if can_enter(world.get_block(n, g)?) {
+            res.push(n);
+        }
+    }
+    Ok(res)
+}
+
+/// find the cheapest path from `start` to `end` using A*.
+/// The returned path doesn't include `start`, but does include `end`.
+/// `max_nodes` is the most tiles we'll look at before giving up, and `cost`
+/// optionally overrides the cost of stepping onto each block (1 by default).
+/// Returns `None` if there is no path, or if it couldn't be found within the
+/// search budget.
+pub fn find_path(
+    world: &World,
+    g: &GameData,
+    start: Vector3,
+    end: Vector3,
+    max_nodes: usize,
+    cost: Option<CostFn>,
+) -> Result<Option<Vec<Vector3>>> {
+    // make sure both ends are actually in the world
+    world.get_block(start, g)?;
+    world.get_block(end, g)?;
+
+    let mut open = BinaryHeap::new();
+    let mut best: HashMap<Vector3, (f64, Option<Vector3>)> = HashMap::new();
+    open.push(Node {
+        estimate: manhattan(start, end),
+        loc: start,
+    });
+    best.insert(start, (0.0, None));
+
+    let mut expanded = 0;
+    while let Some(Node { estimate, loc }) = open.pop() {
+        let (so_far, _) = best[&loc];
+        // stale entry, we've since found a cheaper way here
+        if estimate > so_far + manhattan(loc, end) {
+            continue;
+        }
+        if loc == end {
+            let mut path = vec![];
+            let mut curr = end;
+            while let Some((_, Some(prev))) = best.get(&curr) {
+                path.push(curr);
+                curr = *prev;
+            }
+            path.reverse();
+            return Ok(Some(path));
+        }
+        expanded += 1;
+        if expanded > max_nodes {
+            return Ok(None);
+        }
+        for n in neighbours(world, g, loc)? {
+            let step = match cost {
+                Some(cost) => cost(world.get_block(n, g)?),
+                None => 1.0,
+            };
+            if step.is_nan() || step < 0.0 {
+                return Err(anyhow!(format!("invalid movement cost {} at {:?}", step, n)));
+            }
+            if step.is_infinite() {
+                continue;
+            }
+            let total = so_far + step;
+            let cheaper = match best.get(&n) {
+                Some((c, _)) => total < *c,
+                None => true,
+            };
+            if cheaper {
+                best.insert(n, (total, Some(loc)));
+                open.push(Node {
+                    estimate: total + manhattan(n, end),
+                    loc: n,
+                });
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamedata::gamedata::pvp;
+
+    fn at(x: usize, y: usize) -> Vector3 {
+        Vector3::new(x, y, 0)
+    }
+
+    fn is_step(a: Vector3, b: Vector3) -> bool {
+        manhattan(a, b) == 1.0
+    }
+
+    #[test]
+    fn straight_across_open_ground() {
+        let g = pvp();
+        let world = World::filled(Vector3::new(5, 5, 1), "grass", &g);
+        let path = find_path(&world, &g, at(0, 0), at(4, 3), 100, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(path.len(), 7);
+        assert_eq!(path.last(), Some(&at(4, 3)));
+        assert!(is_step(at(0, 0), path[0]));
+        assert!(path.windows(2).all(|w| is_step(w[0], w[1])));
+    }
+
+    #[test]
+    fn around_a_wall() {
+        let g = pvp();
+        let mut world = World::filled(Vector3::new(5, 5, 1), "grass", &g);
+        for y in 0..4 {
+            world.set_block(at(2, y), "rock", &g);
+        }
+        let path = find_path(&world, &g, at(0, 0), at(4, 0), 100, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(path.len(), 12);
+        assert!(path.contains(&at(2, 4)));
+        for loc in path {
+            assert!(!world.get_block(loc, &g).unwrap().solid);
+        }
+    }
+
+    #[test]
+    fn no_way_through() {
+        let g = pvp();
+        let mut world = World::filled(Vector3::new(5, 5, 1), "grass", &g);
+        for y in 0..5 {
+            world.set_block(at(2, y), "rock", &g);
+        }
+        assert_eq!(
+            find_path(&world, &g, at(0, 0), at(4, 0), 100, None).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn changes_layer_through_z_passable_blocks() {
+        let g = pvp();
+        // the bottom layer is cut in two by a wall, but there's water either
+        // side of it that goes up to the open top layer
+        let mut world = World::filled(Vector3::new(5, 5, 2), "grass", &g);
+        for y in 0..5 {
+            world.set_block(at(2, y), "rock", &g);
+        }
+        for x in &[1, 3] {
+            world.set_block(Vector3::new(*x, 2, 0), "water", &g);
+            world.set_block(Vector3::new(*x, 2, 1), "water", &g);
+        }
+        let path = find_path(&world, &g, at(0, 0), at(4, 4), 100, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(path.last(), Some(&at(4, 4)));
+        assert!(is_step(at(0, 0), path[0]));
+        assert!(path.windows(2).all(|w| is_step(w[0], w[1])));
+        assert!(path.contains(&Vector3::new(1, 2, 1)));
+        assert!(path.contains(&Vector3::new(3, 2, 1)));
+
+        // solid blocks that aren't z_passable still can't be entered, and
+        // there's no going up or down out of anything else
+        world.set_block(Vector3::new(3, 2, 1), "rock", &g);
+        world.set_block(Vector3::new(3, 2, 0), "grass", &g);
+        assert_eq!(
+            find_path(&world, &g, at(0, 0), at(4, 4), 100, None).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn avoids_expensive_blocks() {
+        let g = pvp();
+        let mut world = World::filled(Vector3::new(5, 3, 1), "grass", &g);
+        for x in 1..4 {
+            world.set_block(at(x, 1), "mud", &g);
+        }
+        let cost = |b: &Block| if b.name.as_str() == "mud" { 10.0 } else { 1.0 };
+        let path = find_path(&world, &g, at(0, 1), at(4, 1), 100, Some(&cost))
+            .unwrap()
+            .unwrap();
+        assert_eq!(path.len(), 6);
+        assert!(path.iter().all(|loc| loc.y() != 1 || loc.x() == 4));
+    }
+
+    #[test]
+    fn gives_up_past_the_budget() {
+        let g = pvp();
+        let world = World::filled(Vector3::new(20, 20, 1), "grass", &g);
+        assert_eq!(
+            find_path(&world, &g, at(0, 0), at(19, 19), 5, None).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn fails_outside_the_world() {
+        let g = pvp();
+        let world = World::filled(Vector3::new(5, 5, 1), "grass", &g);
+        assert!(find_path(&world, &g, at(0, 0), at(5, 0), 100, None).is_err());
+    }
+}
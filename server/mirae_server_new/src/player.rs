@@ -0,0 +1,656 @@
+use crate::{
+    active_buffs::{ActiveBuff, ActiveBuffs},
+    battle::combatant::Combatant,
+    equipment::Equipment,
+    gamedata::{
+        consumable::Consumable,
+        gamedata::{GameData, ItemName, StatType},
+        item::Buffs,
+    },
+    inventory::{Dropped, Inventory, Wear},
+    rgb::RGB,
+    vector3::Vector3,
+    visibility::Visibility,
+    world::World,
+};
+use anyhow::{anyhow, Result};
+use rand::prelude::StdRng;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path};
+
+/// how much remembered (but not currently visible) tiles are darkened by on
+/// the map.
+const REMEMBERED_FADE: f64 = 0.6;
+
+fn stat(stats: &HashMap<StatType, f64>, stat: &str) -> f64 {
+    *stats.get(&StatType::from(stat.to_string())).unwrap_or(&0.0)
+}
+
+/// every tile a player has ever seen, one bit per tile.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExploredMap {
+    dim: Vector3,
+    bits: Vec<u64>,
+}
+
+impl ExploredMap {
+    pub fn new(dim: Vector3) -> Self {
+        ExploredMap {
+            dim,
+            bits: vec![0; dim.dim() / 64 + 1],
+        }
+    }
+
+    fn index(&self, loc: Vector3) -> Option<usize> {
+        if loc.x() >= self.dim.x() || loc.y() >= self.dim.y() || loc.z() >= self.dim.z() {
+            None
+        } else {
+            Some(loc.z() * self.dim.x() * self.dim.y() + loc.y() * self.dim.x() + loc.x())
+        }
+    }
+
+    pub fn is_explored(&self, loc: Vector3) -> bool {
+        match self.index(loc) {
+            Some(i) => self.bits[i / 64] & (1 << (i % 64)) != 0,
+            None => false,
+        }
+    }
+
+    pub fn explore(&mut self, vis: &Visibility) {
+        for loc in vis.visible() {
+            if let Some(i) = self.index(loc) {
+                self.bits[i / 64] |= 1 << (i % 64);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Player {
+    name: String,
+    pos: Vector3,
+    explored: ExploredMap,
+    #[serde(default = "Inventory::new")]
+    inventory: Inventory,
+    #[serde(default = "Equipment::new")]
+    equipment: Equipment,
+    /// buffs from items the player has consumed.
+    #[serde(default = "ActiveBuffs::new")]
+    consumed: ActiveBuffs,
+    /// `None` only for players saved before they had health and energy,
+    /// until they're loaded with both full.
+    #[serde(default)]
+    health: Option<f64>,
+    #[serde(default)]
+    energy: Option<f64>,
+}
+
+impl Player {
+    pub fn new(name: String, pos: Vector3, world: &World, g: &GameData) -> Self {
+        let mut player = Player {
+            name,
+            pos,
+            explored: ExploredMap::new(world.dim()),
+            inventory: Inventory::new(),
+            equipment: Equipment::new(),
+            consumed: ActiveBuffs::new(),
+            health: None,
+            energy: None,
+        };
+        player.refresh_stats(g);
+        player
+    }
+
+    /// the player's stats at time `now`, after what they're wearing and what
+    /// they've consumed that hasn't run out.
+    pub fn stats(&mut self, g: &GameData, now: f64) -> HashMap<StatType, f64> {
+        self.expire_buffs(g, now);
+        self.current_stats(g)
+    }
+
+    /// the player's stats with whatever buffs they have right now, whether or
+    /// not any have run out.
+    fn current_stats(&self, g: &GameData) -> HashMap<StatType, f64> {
+        let buffs = self.current_buffs(g);
+        g.stat_defaults
+            .iter()
+            .map(|(s, v)| (s.clone(), v * buffs.stat(s)))
+            .collect()
+    }
+
+    fn current_buffs(&self, g: &GameData) -> Buffs {
+        let mut buffs = self.equipment.buffs(g);
+        buffs.stack(&self.consumed.buffs());
+        buffs
+    }
+
+    /// keep everything that depends on the player's stats in line with them:
+    /// their inventory's carry capacity, and their health and energy, which
+    /// can't be over the max. This has to be done again whenever what they're
+    /// wearing or have consumed changes.
+    fn refresh_stats(&mut self, g: &GameData) {
+        let stats = self.current_stats(g);
+        let (max_health, max_energy) = (stat(&stats, "max_health"), stat(&stats, "max_energy"));
+        self.health = Some(self.health.map_or(max_health, |h| h.min(max_health)));
+        self.energy = Some(self.energy.map_or(max_energy, |e| e.min(max_energy)));
+        self.inventory.set_limits(Some(g.carry.limits(&stats)));
+    }
+
+    /// take away every consumed buff that's run out by time `now`. This is the
+    /// only place they run out outside of battle, so the player's stats are
+    /// refreshed here too.
+    fn expire_buffs(&mut self, g: &GameData, now: f64) {
+        if !self.consumed.expire(now).is_empty() {
+            self.refresh_stats(g);
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn pos(&self) -> Vector3 {
+        self.pos
+    }
+
+    pub fn set_pos(&mut self, pos: Vector3) {
+        self.pos = pos;
+    }
+
+    pub fn explored(&self) -> &ExploredMap {
+        &self.explored
+    }
+
+    pub fn inventory(&self) -> &Inventory {
+        &self.inventory
+    }
+
+    pub fn inventory_mut(&mut self) -> &mut Inventory {
+        &mut self.inventory
+    }
+
+    pub fn equipment(&self) -> &Equipment {
+        &self.equipment
+    }
+
+    pub fn health(&self) -> f64 {
+        self.health.unwrap_or(0.0)
+    }
+
+    pub fn energy(&self) -> f64 {
+        self.energy.unwrap_or(0.0)
+    }
+
+    /// equip an item from the player's inventory. For unique items,
+    /// `instance` picks which one.
+    pub fn equip(&mut self, g: &GameData, item: &ItemName, instance: Option<u64>) -> Result<()> {
+        self.equipment
+            .equip(g, &mut self.inventory, item, instance)
+            .map_err(|e| anyhow!(format!("{} can't equip {}: {}", self.name, item.as_str(), e)))?;
+        self.refresh_stats(g);
+        Ok(())
+    }
+
+    /// put an equipped item back in the player's inventory.
+    pub fn unequip(&mut self, g: &GameData, item: &ItemName, instance: Option<u64>) -> Result<()> {
+        self.equipment
+            .unequip(&mut self.inventory, item, instance)
+            .map_err(|e| anyhow!(format!("{} can't unequip {}: {}", self.name, item.as_str(), e)))?;
+        self.refresh_stats(g);
+        Ok(())
+    }
+
+    /// consume one of an item from the player's inventory at time `now` (in
+    /// seconds): apply its health and energy, and start its buffs. Health and
+    /// energy don't go over the max, and nothing changes if it would leave the
+    /// player with no health. Buffs that only last for some turns are only
+    /// counted down in battle (see `fought`). Returns what it did.
+    pub fn consume<'g>(&mut self, g: &'g GameData, item: &ItemName, now: f64) -> Result<&'g Consumable> {
+        let consumable = g
+            .items
+            .get(item)
+            .ok_or_else(|| anyhow!(format!("{:?} is not an item", item)))?
+            .consumable
+            .as_ref()
+            .ok_or_else(|| anyhow!(format!("{} can't be consumed", item.as_str())))?;
+        if self.inventory.get(item) == 0 {
+            return Err(anyhow!(format!(
+                "{} doesn't have any {}",
+                self.name,
+                item.as_str()
+            )));
+        }
+        let (health, energy) = (self.health(), self.energy());
+        if health + consumable.health <= 0.0 {
+            return Err(anyhow!(format!(
+                "{} only has {} health, and {} would take {}",
+                self.name,
+                health,
+                item.as_str(),
+                -consumable.health
+            )));
+        }
+        self.inventory.change(item.clone(), -1)?;
+        self.expire_buffs(g, now);
+        if let Some(timed) = &consumable.timed {
+            self.consumed.add(item, timed, now);
+        }
+        let stats = self.current_stats(g);
+        // like in battle, going over the max doesn't take away what's already
+        // there
+        self.health =
+            Some((health + consumable.health).min(stat(&stats, "max_health").max(health)));
+        self.energy = Some(
+            (energy + consumable.energy)
+                .min(stat(&stats, "max_energy").max(energy))
+                .max(0.0),
+        );
+        self.refresh_stats(g);
+        Ok(consumable)
+    }
+
+    /// the player's buffs from consumed items that haven't run out by time
+    /// `now`. Any that have are taken away.
+    pub fn active_buffs(&mut self, g: &GameData, now: f64) -> &[ActiveBuff] {
+        self.expire_buffs(g, now);
+        self.consumed.active()
+    }
+
+    /// everything buffing the player at time `now`: what they're wearing, and
+    /// what they've consumed.
+    pub fn buffs(&mut self, g: &GameData, now: f64) -> Buffs {
+        self.expire_buffs(g, now);
+        self.current_buffs(g)
+    }
+
+    /// the player as they'd go into battle at time `now`, with the abilities
+    /// of everything they're carrying or wearing, and the bonuses of every
+    /// set they're wearing. Consumed buffs that run out by time go on
+    /// running out by the battle's clock, so it should count from `now`.
+    pub fn combatant(&mut self, g: &GameData, now: f64) -> Combatant {
+        self.expire_buffs(g, now);
+        let mut combatant = Combatant::new(
+            self.name.clone(),
+            g,
+            &HashMap::new(),
+            self.equipment.buffs(g),
+            self.inventory.clone(),
+        );
+        combatant.pos = Some(self.pos);
+        combatant.consumed = self.consumed.clone();
+        combatant.reset_limits(g);
+        combatant.health = self.health();
+        combatant.energy = self.energy();
+        let mut items: Vec<ItemName> = self.inventory.counts().into_keys().collect();
+        items.extend(self.equipment.equipped().into_iter().map(|(_, e)| e.item.clone()));
+        items.sort();
+        items.dedup();
+        for item in items.iter().filter_map(|i| g.items.get(i)) {
+            combatant.add_item_abilities(item);
+        }
+        for (set, bonus) in self.equipment.set_bonuses(g) {
+            combatant.add_set_abilities(set, bonus);
+        }
+        combatant
+    }
+
+    /// take back how the player ended up after a battle they went into as
+    /// `end`'s combatant: their health and energy, what they're carrying, and
+    /// what's left of their consumed buffs, including the ones that only last
+    /// for some turns.
+    pub fn fought(&mut self, g: &GameData, end: &Combatant) {
+        self.health = Some(end.health);
+        self.energy = Some(end.energy);
+        self.inventory = end.inventory.clone();
+        self.consumed = end.consumed.clone();
+        self.refresh_stats(g);
+    }
+
+    /// regenerate for `secs` seconds outside of battle from time `now`, the
+    /// same way a combatant does. Returns how much health and energy were
+    /// regained.
+    pub fn rest(&mut self, g: &GameData, now: f64, secs: f64) -> (f64, f64) {
+        let mut combatant = self.combatant(g, now);
+        let regained = combatant.rest(secs);
+        self.health = Some(combatant.health);
+        self.energy = Some(combatant.energy);
+        regained
+    }
+
+    /// use one of an item's abilities outside of battle, e.g. to craft
+    /// something at a workbench. The player has to have the item, and
+    /// everything the ability needs. Nothing changes if they don't. Returns
+    /// what happened to the item, and whatever was made but didn't fit in
+    /// their inventory.
+    pub fn use_item(
+        &mut self,
+        g: &GameData,
+        item: &ItemName,
+        ability: &str,
+        rng: &mut StdRng,
+    ) -> Result<(Wear, Dropped)> {
+        let a = g
+            .items
+            .get(item)
+            .ok_or_else(|| anyhow!(format!("{:?} is not an item", item)))?
+            .abilities
+            .get(ability)
+            .ok_or_else(|| anyhow!(format!("{:?} has no ability called {}", item, ability)))?;
+        self.inventory
+            .use_ability(g, a, Some(item), rng)
+            .map_err(|e| anyhow!(format!("{} can't use {}: {}", self.name, ability, e)))
+    }
+
+    /// work out what the player can currently see with the given view
+    /// distance, and remember all of it.
+    pub fn look(&mut self, world: &World, g: &GameData, view: usize) -> Result<Visibility> {
+        let vis = Visibility::compute(world, g, self.pos, view)?;
+        self.explored.explore(&vis);
+        Ok(vis)
+    }
+
+    /// a `size` x `size` map of the player's layer, centered on the player.
+    /// Tiles the player has never seen are `None`, and tiles the player has
+    /// seen before but can't see right now are faded.
+    pub fn map_view(&self, world: &World, vis: &Visibility, size: usize) -> Result<Vec<Vec<Option<RGB>>>> {
+        let dim = world.dim();
+        if size == 0 || size > dim.x().max(dim.y()) {
+            return Err(anyhow!(format!("you can't display a map of size {}", size)));
+        }
+        let min_x = self.pos.x().saturating_sub(size / 2).min(dim.x().saturating_sub(size));
+        let min_y = self.pos.y().saturating_sub(size / 2).min(dim.y().saturating_sub(size));
+        let mut rows = vec![];
+        for y in min_y..(min_y + size).min(dim.y()) {
+            let mut row = vec![];
+            for x in min_x..(min_x + size).min(dim.x()) {
+                let loc = Vector3::new(x, y, self.pos.z());
+                row.push(if vis.is_visible(loc) {
+                    Some(world.get_color(loc)?)
+                } else if self.explored.is_explored(loc) {
+                    Some(world.get_color(loc)?.faded(REMEMBERED_FADE))
+                } else {
+                    None
+                });
+            }
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+
+    /// where the player called `name` is saved. Names that could point
+    /// anywhere else are rejected.
+    fn save_path(dir: &str, name: &str) -> Result<String> {
+        if name.is_empty()
+            || name.contains("..")
+            || name.contains(['/', '\\', '\0'])
+        {
+            return Err(anyhow!(format!("{:?} can't be used as a player's name", name)));
+        }
+        Ok(format!("{}/players/{}.json", dir, name))
+    }
+
+    pub fn save(&self, dir: &str) -> Result<()> {
+        let path = Self::save_path(dir, &self.name)?;
+        if let Some(parent) = Path::new(&path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(dir: &str, name: &str, world: &World, g: &GameData) -> Result<Player> {
+        let mut player: Player =
+            serde_json::from_str(&fs::read_to_string(Self::save_path(dir, name)?)?)?;
+        if player.explored.dim != world.dim() {
+            return Err(anyhow!(format!(
+                "{} was saved in a world of dim {:?}, but this world is {:?}",
+                name,
+                player.explored.dim,
+                world.dim()
+            )));
+        }
+        if player.explored.bits.len() != player.explored.dim.dim() / 64 + 1 {
+            return Err(anyhow!(format!(
+                "{}'s explored map is corrupt, it has {} words for a world of dim {:?}",
+                name,
+                player.explored.bits.len(),
+                player.explored.dim
+            )));
+        }
+        // limits aren't saved, so they always follow the current gamemode
+        player.refresh_stats(g);
+        Ok(player)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        battle::{battle::Battle, event::BattleEvent},
+        gamedata::gamedata::pvp,
+        world::get_rand,
+    };
+
+    fn at(x: usize, y: usize) -> Vector3 {
+        Vector3::new(x, y, 0)
+    }
+
+    fn n(name: &str) -> ItemName {
+        ItemName::from(name.to_string())
+    }
+
+    fn s(name: &str) -> StatType {
+        StatType::from(name.to_string())
+    }
+
+    /// a new player carrying the given items.
+    fn carrying(g: &GameData, items: &[(&str, u64)]) -> Player {
+        let world = World::filled(Vector3::new(10, 10, 1), "grass", g);
+        let mut player = Player::new("ann".to_string(), at(1, 1), &world, g);
+        let items = items.iter().map(|(k, v)| (n(k), *v)).collect();
+        player.inventory = Inventory::rolled(g, &items, &mut get_rand(0)).unwrap();
+        player.refresh_stats(g);
+        player
+    }
+
+    /// a directory of its own for a test to save players in.
+    fn save_dir(test: &str) -> String {
+        let dir =
+            std::env::temp_dir().join(format!("mirae_player_{}_{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn remembers_what_it_has_seen() {
+        let g = pvp();
+        let world = World::filled(Vector3::new(10, 10, 1), "grass", &g);
+        let mut player = Player::new("ann".to_string(), at(1, 1), &world, &g);
+        player.look(&world, &g, 2).unwrap();
+        assert!(player.explored().is_explored(at(1, 1)));
+        assert!(player.explored().is_explored(at(2, 2)));
+        assert!(!player.explored().is_explored(at(8, 8)));
+        player.set_pos(at(8, 8));
+        player.look(&world, &g, 2).unwrap();
+        assert!(player.explored().is_explored(at(1, 1)));
+        assert!(player.explored().is_explored(at(8, 8)));
+    }
+
+    #[test]
+    fn map_view_fades_remembered_tiles_and_hides_unseen_ones() {
+        let g = pvp();
+        let mut world = World::filled(Vector3::new(10, 10, 1), "grass", &g);
+        world.set_block(at(1, 1), "sand", &g);
+        let mut player = Player::new("ann".to_string(), at(1, 1), &world, &g);
+        player.look(&world, &g, 2).unwrap();
+        player.set_pos(at(8, 8));
+        let vis = player.look(&world, &g, 2).unwrap();
+        let map = player.map_view(&world, &vis, 10).unwrap();
+        assert_eq!(map.len(), 10);
+        assert!(map.iter().all(|row| row.len() == 10));
+        let sand = world.get_color(at(1, 1)).unwrap();
+        assert_eq!(map[1][1], Some(sand.faded(REMEMBERED_FADE)));
+        assert_eq!(map[8][8], Some(world.get_color(at(8, 8)).unwrap()));
+        assert_eq!(map[1][8], None);
+        assert!(player.map_view(&world, &vis, 0).is_err());
+        assert!(player.map_view(&world, &vis, 11).is_err());
+
+        // smaller maps stay centered on the player, but inside the world
+        let map = player.map_view(&world, &vis, 4).unwrap();
+        assert_eq!(map[2][2], Some(world.get_color(at(8, 8)).unwrap()));
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let g = pvp();
+        let world = World::filled(Vector3::new(10, 10, 1), "grass", &g);
+        let dir = save_dir("saves_and_loads");
+        let mut player = Player::new("ann".to_string(), at(3, 4), &world, &g);
+        player.look(&world, &g, 2).unwrap();
+        player.save(&dir).unwrap();
+        let loaded = Player::load(&dir, "ann", &world, &g).unwrap();
+        assert_eq!(loaded.pos(), at(3, 4));
+        for x in 0..10 {
+            for y in 0..10 {
+                assert_eq!(
+                    loaded.explored().is_explored(at(x, y)),
+                    player.explored().is_explored(at(x, y))
+                );
+            }
+        }
+
+        // a player can't be loaded into a world of a different size
+        let bigger = World::filled(Vector3::new(12, 10, 1), "grass", &g);
+        assert!(Player::load(&dir, "ann", &bigger, &g).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn names_cant_point_outside_the_save_dir() {
+        for name in &["", "..", "../ann", "a/b", "a\\b", "a\0b"] {
+            assert!(Player::save_path("saves", name).is_err(), "{:?}", name);
+        }
+        assert_eq!(
+            Player::save_path("saves", "ann").unwrap(),
+            "saves/players/ann.json"
+        );
+    }
+
+    #[test]
+    fn rests_outside_of_battle() {
+        let g = pvp();
+        let world = World::filled(Vector3::new(10, 10, 1), "grass", &g);
+        let mut player = Player::new("ann".to_string(), at(1, 1), &world, &g);
+        assert_eq!((player.health(), player.energy()), (20.0, 10.0));
+        player.health = Some(10.0);
+        player.energy = Some(0.0);
+        // 0.1 health and 0.5 energy a second
+        assert_eq!(player.rest(&g, 0.0, 10.0), (1.0, 5.0));
+        assert_eq!((player.health(), player.energy()), (11.0, 5.0));
+        assert_eq!(player.rest(&g, 10.0, 100.0), (9.0, 5.0));
+        assert_eq!((player.health(), player.energy()), (20.0, 10.0));
+    }
+
+    #[test]
+    fn consuming_gives_health_and_energy_straight_away() {
+        let g = pvp();
+        let mut player = carrying(&g, &[("orange", 2), ("trump_orange", 2)]);
+        player.health = Some(10.0);
+        player.energy = Some(2.0);
+        player.consume(&g, &n("orange"), 0.0).unwrap();
+        assert_eq!(player.health(), 14.0);
+        assert_eq!(player.stats(&g, 0.0)[&s("speed")], 11.0);
+        player.consume(&g, &n("trump_orange"), 0.0).unwrap();
+        assert_eq!((player.health(), player.energy()), (10.0, 7.0));
+        assert_eq!(player.inventory().get(&n("trump_orange")), 1);
+
+        // nothing happens if it would leave the player with no health
+        player.health = Some(4.0);
+        assert!(player.consume(&g, &n("trump_orange"), 0.0).is_err());
+        assert_eq!(player.health(), 4.0);
+        assert_eq!(player.inventory().get(&n("trump_orange")), 1);
+
+        // and health doesn't go over the max
+        player.health = Some(19.0);
+        player.consume(&g, &n("orange"), 0.0).unwrap();
+        assert_eq!(player.health(), 20.0);
+        assert!(player.consume(&g, &n("orange"), 0.0).is_err());
+    }
+
+    #[test]
+    fn buffs_run_out_by_time() {
+        let mut g = pvp();
+        let timed = g.items.get_mut(&n("orange")).unwrap();
+        let timed = timed.consumable.as_mut().unwrap().timed.as_mut().unwrap();
+        timed.buffs.stat_buffs.insert(s("max_health"), 1.5);
+        timed.buffs.stat_buffs.insert(s("carry_capacity"), 2.0);
+        let mut player = carrying(&g, &[("orange", 1)]);
+        player.consume(&g, &n("orange"), 0.0).unwrap();
+        assert_eq!(player.health(), 24.0);
+        assert_eq!(player.active_buffs(&g, 59.0).len(), 1);
+        assert_eq!(player.inventory().limits().unwrap().capacity, Some(200.0));
+
+        // oranges last 60 seconds, and the player is cut down to the new max
+        // health and carry capacity as soon as they run out
+        assert!(player.active_buffs(&g, 60.0).is_empty());
+        assert_eq!(player.health(), 20.0);
+        assert_eq!(player.inventory().limits().unwrap().capacity, Some(100.0));
+        assert_eq!(player.stats(&g, 60.0)[&s("speed")], 10.0);
+    }
+
+    #[test]
+    fn turn_buffs_run_out_in_battle() {
+        let g = pvp();
+        let mut player = carrying(&g, &[("orange", 2)]);
+        player.consume(&g, &n("orange"), 0.0).unwrap();
+        let dummy = Combatant::new(
+            "dummy".to_string(),
+            &g,
+            &HashMap::new(),
+            Buffs::new(),
+            Inventory::new(),
+        );
+        // with no abilities, all the player can do is pass
+        let mut combatant = player.combatant(&g, 0.0);
+        combatant.abilities.clear();
+        let mut battle = Battle::new(&g, 0, vec![vec![combatant], vec![dummy]]).unwrap();
+        assert_eq!(battle.combatant(0).unwrap().stat("speed"), 11.0);
+        let expired = BattleEvent::BuffExpired {
+            who: 0,
+            item: n("orange"),
+        };
+        // oranges last 3 of the player's turns
+        for _ in 0..100 {
+            if battle.log().contains(&expired) {
+                break;
+            }
+            battle.turn(None).unwrap();
+        }
+        assert!(battle.log().contains(&expired));
+        player.fought(&g, battle.combatant(0).unwrap());
+        assert!(player.active_buffs(&g, 0.0).is_empty());
+        assert_eq!(player.stats(&g, 0.0)[&s("speed")], 10.0);
+        assert_eq!(player.inventory().get(&n("orange")), 1);
+        assert_eq!(player.health(), 20.0);
+    }
+
+    #[test]
+    fn combatants_get_item_and_set_abilities() {
+        let g = pvp();
+        let mut player = carrying(
+            &g,
+            &[("workbench", 1), ("heghead", 1), ("sonic's_shoes", 1)],
+        );
+        player.equip(&g, &n("heghead"), None).unwrap();
+        player.equip(&g, &n("sonic's_shoes"), None).unwrap();
+        player.health = Some(15.0);
+        let combatant = player.combatant(&g, 0.0);
+        assert!(combatant.abilities.contains_key("workbench:iron_sword"));
+        assert!(combatant.abilities.contains_key("sonic:spin_dash"));
+        // both pieces are faster on their own too
+        assert_eq!(combatant.stat("speed"), 37.5);
+        assert_eq!(combatant.health, 15.0);
+        assert_eq!(combatant.pos, Some(at(1, 1)));
+    }
+}
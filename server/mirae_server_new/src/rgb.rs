@@ -11,4 +11,10 @@ impl RGB {
     pub fn new(r: u8, g: u8, b: u8) -> RGB {
         RGB { r, g, b }
     }
+
+    /// the same color, darkened by `amount` (0 is unchanged, 1 is black).
+    pub fn faded(self, amount: f64) -> RGB {
+        let f = |c: u8| (c as f64 * (1.0 - amount.clamp(0.0, 1.0))) as u8;
+        RGB::new(f(self.r), f(self.g), f(self.b))
+    }
 }
@@ -0,0 +1,482 @@
+use crate::{
+    battle::{
+        battle::{Battle, Choice, Outcome},
+        combatant::{Combatant, CombatantId},
+        event::BattleEvent,
+        strategy,
+    },
+    equipment::Equipment,
+    gamedata::{
+        gamedata::{DmgType, GameData, ItemName, MobName, StatType},
+        item::Buffs,
+    },
+    inventory::Inventory,
+    world::get_rand,
+};
+use anyhow::{anyhow, Result};
+use rand::{prelude::StdRng, Rng};
+use serde::Deserialize;
+use serde_jacl::de::from_str;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt, fs,
+    hash::{Hash, Hasher},
+};
+
+/// a player's setup, loaded from a jacl file.
+#[derive(Deserialize, Debug)]
+pub struct LoadoutDeser {
+    name: String,
+    #[serde(default = "HashMap::new")]
+    stats: HashMap<String, f64>,
+    #[serde(default = "HashMap::new")]
+    items: HashMap<String, u64>,
+    #[serde(default = "Vec::new")]
+    wearing: Vec<String>,
+}
+
+impl LoadoutDeser {
+    pub fn into_loadout(self, g: &GameData) -> Result<Loadout> {
+        let mut stats = HashMap::new();
+        for (k, v) in self.stats {
+            let stat = StatType::from(k);
+            if !g.stat.contains(&stat) {
+                return Err(anyhow!(format!("{:?} is not a stat", stat)));
+            }
+            stats.insert(stat, v);
+        }
+        let mut items = HashMap::new();
+        for (k, v) in self.items {
+            items.insert(item_name(k, g)?, v);
+        }
+        let mut wearing = vec![];
+        for k in self.wearing {
+            wearing.push(item_name(k, g)?);
+        }
+        let loadout = Loadout {
+            name: self.name,
+            stats,
+            items,
+            wearing,
+        };
+        // make sure everything can be worn, whatever gets rolled
+        loadout.gear(g, &mut get_rand(0))?;
+        Ok(loadout)
+    }
+}
+
+fn item_name(name: String, g: &GameData) -> Result<ItemName> {
+    let name = ItemName::from(name);
+    if g.items.contains_key(&name) {
+        Ok(name)
+    } else {
+        Err(anyhow!(format!("{:?} is not an item", name)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Loadout {
+    pub name: String,
+    pub stats: HashMap<StatType, f64>,
+    pub items: HashMap<ItemName, u64>,
+    /// in the order they're put on.
+    pub wearing: Vec<ItemName>,
+}
+
+impl Loadout {
+    /// the loadout's inventory, with new instances of its unique items, and
+    /// what it's wearing. Worn items stay in the inventory too, so that their
+    /// abilities can still be used.
+    pub fn gear(&self, g: &GameData, rng: &mut StdRng) -> Result<(Inventory, Equipment)> {
+        let inventory = Inventory::rolled(g, &self.items, rng)?;
+        let mut unworn = inventory.clone();
+        let mut equipment = Equipment::new();
+        for name in &self.wearing {
+            let instance = unworn
+                .instances()
+                .iter()
+                .find(|i| &i.item == name)
+                .map(|i| i.id);
+            equipment
+                .equip(g, &mut unworn, name, instance)
+                .map_err(|e| anyhow!(format!("{} can't wear {:?}: {}", self.name, name, e)))?;
+        }
+        Ok((inventory, equipment))
+    }
+}
+
+/// one side of a simulated matchup.
+#[derive(Debug, Clone)]
+pub enum Side {
+    Mob(MobName),
+    /// someone with default stats and just the one item.
+    Item(ItemName),
+    Loadout(Loadout),
+}
+
+impl Side {
+    /// parse a side from the command line: "mob:name", "item:name", or
+    /// "loadout:path/to/loadout.jacl".
+    pub fn parse(s: &str, g: &GameData) -> Result<Side> {
+        let mut parts = s.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("mob"), Some(name)) => {
+                let name = MobName::from(name.to_string());
+                if g.mob_templates.contains_key(&name) {
+                    Ok(Side::Mob(name))
+                } else {
+                    Err(anyhow!(format!("{:?} is not a mob", name)))
+                }
+            }
+            (Some("item"), Some(name)) => Ok(Side::Item(item_name(name.to_string(), g)?)),
+            (Some("loadout"), Some(path)) => {
+                let deser: LoadoutDeser = from_str(&fs::read_to_string(path)?)?;
+                Ok(Side::Loadout(deser.into_loadout(g)?))
+            }
+            _ => Err(anyhow!(format!(
+                "invalid side {:?}, expected mob:<name>, item:<name> or loadout:<path>",
+                s
+            ))),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Side::Mob(name) => name.as_str().to_string(),
+            Side::Item(name) => name.as_str().to_string(),
+            Side::Loadout(loadout) => loadout.name.clone(),
+        }
+    }
+
+    pub fn combatant(&self, g: &GameData, rng: &mut StdRng) -> Result<Combatant> {
+        match self {
+            Side::Mob(name) => {
+                let template = g
+                    .mob_templates
+                    .get(name)
+                    .ok_or_else(|| anyhow!(format!("{:?} is not a mob", name)))?;
+                Combatant::from_mob(template, g, rng)
+            }
+            Side::Item(name) => {
+                let mut items = HashMap::new();
+                items.insert(name.clone(), 1);
+                let mut combatant = Combatant::new(
+                    self.name(),
+                    g,
+                    &HashMap::new(),
+                    Buffs::new(),
+                    Inventory::rolled(g, &items, rng)?,
+                );
+                combatant.add_item_abilities(&g.items[name]);
+                Ok(combatant)
+            }
+            Side::Loadout(loadout) => {
+                let (inventory, equipment) = loadout.gear(g, rng)?;
+                let mut combatant = Combatant::new(
+                    loadout.name.clone(),
+                    g,
+                    &loadout.stats,
+                    equipment.buffs(g),
+                    inventory,
+                );
+                let mut items: Vec<&ItemName> = loadout.items.keys().collect();
+                items.sort();
+                for item in items {
+                    combatant.add_item_abilities(&g.items[item]);
+                }
+                for (set, bonus) in equipment.set_bonuses(g) {
+                    combatant.add_set_abilities(set, bonus);
+                }
+                Ok(combatant)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub battles: u64,
+    /// battle i uses seed + i.
+    pub seed: u64,
+    /// battles that go on longer than this are called a draw.
+    pub max_turns: u64,
+    /// how many seconds each turn is taken to last, for cooldowns counted in
+    /// seconds.
+    pub turn_secs: f64,
+    /// if set, every battle's replay is saved here, named after its seed.
+    pub replay_dir: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SideReport {
+    pub name: String,
+    pub wins: u64,
+    pub damage: HashMap<DmgType, f64>,
+    pub abilities: HashMap<String, u64>,
+}
+
+impl SideReport {
+    pub fn total_damage(&self) -> f64 {
+        self.damage.values().sum()
+    }
+
+    /// the proportion of this side's damage that was each damage type, most
+    /// first.
+    pub fn damage_share(&self) -> Vec<(DmgType, f64)> {
+        let total = self.total_damage();
+        let mut share: Vec<(DmgType, f64)> = self
+            .damage
+            .iter()
+            .map(|(k, v)| (k.clone(), if total > 0.0 { v / total } else { 0.0 }))
+            .collect();
+        share.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        share
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimReport {
+    pub battles: u64,
+    pub draws: u64,
+    /// battles that hit the turn limit. These are also counted as draws.
+    pub timeouts: u64,
+    pub total_turns: u64,
+    pub sides: [SideReport; 2],
+}
+
+impl SimReport {
+    pub fn win_rate(&self, side: usize) -> f64 {
+        self.sides[side].wins as f64 / self.battles.max(1) as f64
+    }
+
+    pub fn avg_turns(&self) -> f64 {
+        self.total_turns as f64 / self.battles.max(1) as f64
+    }
+}
+
+impl fmt::Display for SimReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} vs {}, {} battles",
+            self.sides[0].name, self.sides[1].name, self.battles
+        )?;
+        writeln!(f, "average turns: {:.1}", self.avg_turns())?;
+        writeln!(
+            f,
+            "draws: {:.1}% ({} hit the turn limit)",
+            100.0 * self.draws as f64 / self.battles.max(1) as f64,
+            self.timeouts
+        )?;
+        for (i, side) in self.sides.iter().enumerate() {
+            writeln!(f)?;
+            writeln!(f, "{}", side.name)?;
+            writeln!(f, "  win rate: {:.1}%", 100.0 * self.win_rate(i))?;
+            writeln!(
+                f,
+                "  damage per battle: {:.1}",
+                side.total_damage() / self.battles.max(1) as f64
+            )?;
+            for (dmg, share) in side.damage_share() {
+                writeln!(f, "    {}: {:.1}%", dmg.as_str(), 100.0 * share)?;
+            }
+            writeln!(f, "  ability usage:")?;
+            let mut abilities: Vec<(&String, &u64)> = side.abilities.iter().collect();
+            abilities.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            for (ability, count) in abilities {
+                writeln!(f, "    {}: {}", ability, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// play out one battle. Mobs pick what to do using their strategy, and
+/// everyone else picks randomly from whatever abilities they can use, and
+/// whoever they can use them on. Returns the finished (or timed out) battle.
+pub fn run_battle<'a>(
+    g: &'a GameData,
+    sides: &[Side; 2],
+    seed: u64,
+    config: &SimConfig,
+) -> Result<Battle<'a>> {
+    // the battle's own rng is seeded with `seed`, so everyone's choices come
+    // from a different stream, or they'd just repeat the battle's rolls
+    let mut hasher = DefaultHasher::new();
+    (seed, "choices").hash(&mut hasher);
+    let mut rng = get_rand(hasher.finish());
+    let first = sides[0].combatant(g, &mut rng)?;
+    let second = sides[1].combatant(g, &mut rng)?;
+    let mut battle = Battle::new(g, seed, vec![vec![first], vec![second]])?;
+    while battle.outcome().is_none() && battle.turns() < config.max_turns {
+        if battle.turns() > 0 {
+            battle.set_clock(battle.clock() + config.turn_secs)?;
+        }
+        let current = battle.current();
+        let usable = battle.usable_abilities(current);
+        let choice = if !battle.needs_choice() || usable.is_empty() {
+            None
+        } else if battle.combatants()[current].mob.is_some() {
+            strategy::choose_for_mob(&battle, g, current, &mut rng)
+        } else {
+            let name = &usable[rng.gen_range(0, usable.len())];
+            let ability = &battle.combatants()[current].abilities[name].ability;
+            let candidates = battle.candidates(current, ability);
+            Some(Choice::Ability {
+                name: name.clone(),
+                target: Some(candidates[rng.gen_range(0, candidates.len())]),
+            })
+        };
+        battle.turn(choice)?;
+    }
+    Ok(battle)
+}
+
+/// run a whole lot of battles between two sides, and collect some statistics.
+pub fn simulate(g: &GameData, sides: &[Side; 2], config: &SimConfig) -> Result<SimReport> {
+    let mut report = SimReport {
+        battles: config.battles,
+        draws: 0,
+        timeouts: 0,
+        total_turns: 0,
+        sides: [
+            SideReport {
+                name: sides[0].name(),
+                ..SideReport::default()
+            },
+            SideReport {
+                name: sides[1].name(),
+                ..SideReport::default()
+            },
+        ],
+    };
+    for i in 0..config.battles {
+        let seed = config.seed.wrapping_add(i);
+        let battle = run_battle(g, sides, seed, config)?;
+        if let Some(dir) = &config.replay_dir {
+            battle.replay().save(dir, &seed.to_string())?;
+        }
+        report.total_turns += battle.turns();
+        match battle.outcome() {
+            Some(Outcome::Victory(winner)) => report.sides[winner].wins += 1,
+            Some(Outcome::Draw) => report.draws += 1,
+            None => {
+                report.draws += 1;
+                report.timeouts += 1;
+            }
+        }
+        // charged abilities show up once when they're chosen, and again when
+        // they go off. Only the first one counts as a use.
+        let team = |id: CombatantId| battle.combatants()[id].team;
+        let mut charging = vec![false; battle.combatants().len()];
+        for event in battle.log() {
+            match event {
+                BattleEvent::Damage {
+                    source, dmg, amount, ..
+                }
+                | BattleEvent::Counter {
+                    source, dmg, amount, ..
+                } => {
+                    *report.sides[team(*source)].damage.entry(dmg.clone()).or_insert(0.0) += amount;
+                }
+                BattleEvent::ChargeStart { actor, .. } => charging[*actor] = true,
+                BattleEvent::Ability { actor, .. } | BattleEvent::Fizzled { actor, .. }
+                    if charging[*actor] =>
+                {
+                    charging[*actor] = false
+                }
+                BattleEvent::Ability { actor, ability, .. } => {
+                    *report.sides[team(*actor)]
+                        .abilities
+                        .entry(ability.clone())
+                        .or_insert(0) += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamedata::gamedata::pvp;
+
+    fn config(battles: u64) -> SimConfig {
+        SimConfig {
+            battles,
+            seed: 3,
+            max_turns: 200,
+            turn_secs: 1.0,
+            replay_dir: None,
+        }
+    }
+
+    #[test]
+    fn parses_sides() {
+        let g = pvp();
+        assert_eq!(
+            Side::parse("mob:lowly_ben", &g).unwrap().name(),
+            "lowly_ben"
+        );
+        assert_eq!(Side::parse("item:stick", &g).unwrap().name(), "stick");
+        assert_eq!(
+            Side::parse("loadout:pvp/loadouts/knight.jacl", &g)
+                .unwrap()
+                .name(),
+            "knight"
+        );
+        assert!(Side::parse("mob:nobody", &g).is_err());
+        assert!(Side::parse("item:nothing", &g).is_err());
+        assert!(Side::parse("lowly_ben", &g).is_err());
+    }
+
+    #[test]
+    fn reports_add_up() {
+        let g = pvp();
+        let sides = [
+            Side::parse("loadout:pvp/loadouts/knight.jacl", &g).unwrap(),
+            Side::parse("mob:lowly_ben", &g).unwrap(),
+        ];
+        let report = simulate(&g, &sides, &config(20)).unwrap();
+        assert_eq!(report.battles, 20);
+        assert_eq!(
+            report.sides[0].wins + report.sides[1].wins + report.draws,
+            20
+        );
+        assert!(report.timeouts <= report.draws);
+        assert!(report.total_turns > 0);
+        assert!(report.sides[0].total_damage() > 0.0);
+        assert!(!report.sides[0].abilities.is_empty());
+        let share = report.sides[0].damage_share();
+        assert!((share.iter().map(|(_, s)| s).sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(share.windows(2).all(|w| w[0].1 >= w[1].1));
+        // the same seed gives the same battles
+        let again = simulate(&g, &sides, &config(20)).unwrap();
+        assert_eq!(again.total_turns, report.total_turns);
+        assert_eq!(again.sides[0].wins, report.sides[0].wins);
+        assert_eq!(again.sides[1].damage, report.sides[1].damage);
+    }
+
+    #[test]
+    fn damage_share_of_nothing() {
+        let mut side = SideReport::default();
+        assert!(side.damage_share().is_empty());
+        side.damage
+            .insert(DmgType::from("physical".to_string()), 0.0);
+        assert_eq!(
+            side.damage_share(),
+            vec![(DmgType::from("physical".to_string()), 0.0)]
+        );
+        let report = SimReport {
+            battles: 0,
+            draws: 0,
+            timeouts: 0,
+            total_turns: 0,
+            sides: [side.clone(), side],
+        };
+        assert_eq!(report.win_rate(0), 0.0);
+        assert_eq!(report.avg_turns(), 0.0);
+    }
+}
@@ -1,6 +1,6 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::ops::{Add, Mul, Sub};
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Deserialize)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
 pub struct Vector3 {
     x: usize,
     y: usize,
@@ -35,6 +35,16 @@ impl Vector3 {
     pub fn mag(&self) -> f64 {
         self.sqr_mag().sqrt()
     }
+
+    pub fn sqr_dist(&self, other: &Vector3) -> f64 {
+        (self.x as f64 - other.x as f64).powi(2)
+            + (self.y as f64 - other.y as f64).powi(2)
+            + (self.z as f64 - other.z as f64).powi(2)
+    }
+
+    pub fn dist(&self, other: &Vector3) -> f64 {
+        self.sqr_dist(other).sqrt()
+    }
 }
 
 impl Add for Vector3 {
@@ -0,0 +1,222 @@
+use crate::{gamedata::gamedata::GameData, vector3::Vector3, world::World};
+use anyhow::Result;
+
+// transforms from the first octant into each of the 8 octants
+const MULT: [[i64; 8]; 4] = [
+    [1, 0, 0, -1, -1, 0, 0, 1],
+    [0, 1, -1, 0, 0, -1, 1, 0],
+    [0, 1, 1, 0, 0, -1, -1, 0],
+    [1, 0, 0, 1, -1, 0, 0, -1],
+];
+
+/// the tiles a viewer can see from a point, within a radius.
+/// Only the viewer's own layer is ever visible, and solid blocks block sight
+/// (though the solid blocks themselves can be seen).
+pub struct Visibility {
+    origin: Vector3,
+    radius: usize,
+    side: usize,
+    mask: Vec<bool>,
+}
+
+struct Caster<'a> {
+    world: &'a World,
+    g: &'a GameData,
+    vis: Visibility,
+}
+
+impl<'a> Caster<'a> {
+    fn in_bounds(&self, x: i64, y: i64) -> bool {
+        let dim = self.world.dim();
+        x >= 0 && y >= 0 && (x as usize) < dim.x() && (y as usize) < dim.y()
+    }
+
+    fn opaque(&self, x: i64, y: i64) -> Result<bool> {
+        if !self.in_bounds(x, y) {
+            return Ok(true);
+        }
+        let loc = Vector3::new(x as usize, y as usize, self.vis.origin.z());
+        Ok(self.world.get_block(loc, self.g)?.solid)
+    }
+
+    fn mark(&mut self, x: i64, y: i64) {
+        if self.in_bounds(x, y) {
+            let loc = Vector3::new(x as usize, y as usize, self.vis.origin.z());
+            if let Some(i) = self.vis.index(loc) {
+                self.vis.mask[i] = true;
+            }
+        }
+    }
+
+    // recursive shadowcasting over a single octant, going outwards row by row
+    // and keeping track of the slopes that aren't in shadow yet.
+    fn cast(&mut self, row: i64, mut start: f64, end: f64, oct: usize) -> Result<()> {
+        if start < end {
+            return Ok(());
+        }
+        let radius = self.vis.radius as i64;
+        let (cx, cy) = (self.vis.origin.x() as i64, self.vis.origin.y() as i64);
+        let mut new_start = 0.0;
+        for j in row..=radius {
+            let dy = -j;
+            let mut blocked = false;
+            for dx in -j..=0 {
+                let l_slope = (dx as f64 - 0.5) / (dy as f64 + 0.5);
+                let r_slope = (dx as f64 + 0.5) / (dy as f64 - 0.5);
+                if start < r_slope {
+                    continue;
+                } else if end > l_slope {
+                    break;
+                }
+                let x = cx + dx * MULT[0][oct] + dy * MULT[1][oct];
+                let y = cy + dx * MULT[2][oct] + dy * MULT[3][oct];
+                if dx * dx + dy * dy <= radius * radius {
+                    self.mark(x, y);
+                }
+                let opaque = self.opaque(x, y)?;
+                if blocked {
+                    if opaque {
+                        new_start = r_slope;
+                    } else {
+                        blocked = false;
+                        start = new_start;
+                    }
+                } else if opaque && j < radius {
+                    blocked = true;
+                    self.cast(j + 1, start, l_slope, oct)?;
+                    new_start = r_slope;
+                }
+            }
+            if blocked {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Visibility {
+    /// work out everything that can be seen from `origin` within `radius`
+    /// tiles, usually the viewer's "view" stat.
+    pub fn compute(world: &World, g: &GameData, origin: Vector3, radius: usize) -> Result<Self> {
+        // fail early if the viewer isn't in the world
+        world.get_block(origin, g)?;
+        let side = radius * 2 + 1;
+        let mut caster = Caster {
+            world,
+            g,
+            vis: Visibility {
+                origin,
+                radius,
+                side,
+                mask: vec![false; side * side],
+            },
+        };
+        caster.mark(origin.x() as i64, origin.y() as i64);
+        for oct in 0..8 {
+            caster.cast(1, 1.0, 0.0, oct)?;
+        }
+        Ok(caster.vis)
+    }
+
+    pub fn origin(&self) -> Vector3 {
+        self.origin
+    }
+
+    pub fn radius(&self) -> usize {
+        self.radius
+    }
+
+    fn index(&self, loc: Vector3) -> Option<usize> {
+        if loc.z() != self.origin.z() {
+            return None;
+        }
+        let x = loc.x() as i64 - self.origin.x() as i64 + self.radius as i64;
+        let y = loc.y() as i64 - self.origin.y() as i64 + self.radius as i64;
+        if x < 0 || y < 0 || x as usize >= self.side || y as usize >= self.side {
+            None
+        } else {
+            Some(y as usize * self.side + x as usize)
+        }
+    }
+
+    pub fn is_visible(&self, loc: Vector3) -> bool {
+        match self.index(loc) {
+            Some(i) => self.mask[i],
+            None => false,
+        }
+    }
+
+    /// every tile that can be seen.
+    pub fn visible(&self) -> Vec<Vector3> {
+        (0..self.mask.len())
+            .filter(|i| self.mask[*i])
+            .map(|i| {
+                Vector3::new(
+                    self.origin.x() + i % self.side - self.radius,
+                    self.origin.y() + i / self.side - self.radius,
+                    self.origin.z(),
+                )
+            })
+            .collect()
+    }
+
+    /// the locations of every mob that can be seen.
+    pub fn visible_mobs(&self, world: &World) -> Result<Vec<Vector3>> {
+        let mut mobs = vec![];
+        for loc in self.visible() {
+            if world.has_mob(loc)? {
+                mobs.push(loc);
+            }
+        }
+        Ok(mobs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamedata::gamedata::pvp;
+
+    fn at(x: usize, y: usize) -> Vector3 {
+        Vector3::new(x, y, 0)
+    }
+
+    #[test]
+    fn sees_everything_in_range_on_open_ground() {
+        let g = pvp();
+        let world = World::filled(Vector3::new(11, 11, 2), "grass", &g);
+        let vis = Visibility::compute(&world, &g, at(5, 5), 3).unwrap();
+        assert!(vis.is_visible(at(5, 5)));
+        assert!(vis.is_visible(at(5, 2)));
+        assert!(vis.is_visible(at(8, 5)));
+        assert!(vis.is_visible(at(7, 7)));
+        // out of range, even though nothing is in the way
+        assert!(!vis.is_visible(at(5, 1)));
+        assert!(!vis.is_visible(at(8, 8)));
+        // only the viewer's own layer
+        assert!(!vis.is_visible(Vector3::new(5, 5, 1)));
+    }
+
+    #[test]
+    fn walls_cast_shadows() {
+        let g = pvp();
+        let mut world = World::filled(Vector3::new(11, 11, 1), "grass", &g);
+        world.set_block(at(5, 4), "rock", &g);
+        let vis = Visibility::compute(&world, &g, at(5, 5), 5).unwrap();
+        // the wall itself can be seen, but not what's behind it
+        assert!(vis.is_visible(at(5, 4)));
+        assert!(!vis.is_visible(at(5, 3)));
+        assert!(!vis.is_visible(at(5, 1)));
+        assert!(vis.is_visible(at(5, 6)));
+        assert!(vis.is_visible(at(2, 2)));
+    }
+
+    #[test]
+    fn stops_at_the_edge_of_the_world() {
+        let g = pvp();
+        let world = World::filled(Vector3::new(4, 4, 1), "grass", &g);
+        let vis = Visibility::compute(&world, &g, at(0, 0), 6).unwrap();
+        assert_eq!(vis.visible().len(), 16);
+    }
+}
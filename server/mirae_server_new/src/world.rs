@@ -2,7 +2,7 @@ use anyhow::{anyhow, Result};
 use bimap::BiMap;
 use rand::{prelude::StdRng, Rng, SeedableRng};
 use std::collections::HashMap;
-use crate::{mob::Mob, vector3::Vector3, rgb::RGB, gamedata::{gamedata::GameData, terrain::Biome}, noise};
+use crate::{battle::{battle::Battle, combatant::CombatantId}, mob::Mob, vector3::Vector3, rgb::RGB, gamedata::{block::Block, gamedata::GameData, terrain::Biome}, noise};
 
 pub struct Map<T> {
     dim: Vector3,
@@ -57,7 +57,7 @@ where
         Ok(self.direct_set(self.index(loc)?, val))
     }
 
-    pub fn get(&mut self, loc: Vector3) -> Result<T> {
+    pub fn get(&self, loc: Vector3) -> Result<T> {
         Ok(self.direct_get(self.index(loc)?))
     }
 }
@@ -99,6 +99,16 @@ impl SpawnedMobs {
         self.locs.insert(loc, m.id());
         self.mobs.insert(m.id(), m);
     }
+
+    pub fn len(&self) -> usize {
+        self.mobs.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Vector3, &Mob)> {
+        self.locs
+            .iter()
+            .filter_map(move |(loc, id)| Some((*loc, self.mobs.get(id)?)))
+    }
 }
 
 pub struct World {
@@ -108,9 +118,10 @@ pub struct World {
     color_map: Map<RGB>,
     seed: u64,
     id: u64,
+    rng: StdRng,
 }
 
-fn get_rand(seed: u64) -> StdRng {
+pub fn get_rand(seed: u64) -> StdRng {
     let bytes = seed.to_le_bytes();
     let mut seed_bytes = [0; 32];
     for i in 0..8 {
@@ -241,9 +252,55 @@ impl World {
             color_map,
             seed,
             id: 0,
+            rng,
         })
     }
 
+    pub fn dim(&self) -> Vector3 {
+        self.block_map.dim
+    }
+
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
+    pub fn get_block<'a>(&self, loc: Vector3, g: &'a GameData) -> Result<&'a Block> {
+        g.get_block_by_id(self.block_map.get(loc)?)
+    }
+
+    pub fn get_color(&self, loc: Vector3) -> Result<RGB> {
+        self.color_map.get(loc)
+    }
+
+    pub fn has_mob(&self, loc: Vector3) -> Result<bool> {
+        Ok(self.mob_map.get(loc)?.as_u16().is_some())
+    }
+
+    /// all the mobs that currently have state, along with where they are.
+    pub fn spawned_mobs(&self) -> Vec<(Vector3, Mob)> {
+        self.spawned_mobs
+            .iter()
+            .map(|(loc, mob)| (loc, mob.clone()))
+            .collect()
+    }
+
+    /// instantiate every mob within `radius` tiles of one of the given points,
+    /// on the same layer as it.
+    pub fn spawn_mobs_near(&mut self, points: &[Vector3], radius: usize, g: &GameData) -> Result<()> {
+        let dim = self.dim();
+        for p in points {
+            for y in p.y().saturating_sub(radius)..(p.y() + radius + 1).min(dim.y()) {
+                for x in p.x().saturating_sub(radius)..(p.x() + radius + 1).min(dim.x()) {
+                    let loc = Vector3::new(x, y, p.z());
+                    if self.has_mob(loc)? && self.spawned_mobs.get(loc).is_none() {
+                        self.spawn_mob(loc, g)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_mob(&mut self, loc: Vector3, g: &GameData) -> Result<Mob> {
         if let Some(mob) = self.spawned_mobs.get(loc) {
             Ok(mob.clone())
@@ -269,6 +326,74 @@ impl World {
         self.delete_mob_by_loc(loc)
     }
 
+    /// write back the state of a mob obtained from `get_mob`.
+    pub fn update_mob(&mut self, mob: Mob) -> Result<()> {
+        let loc = self
+            .spawned_mobs
+            .get_posn(&mob)
+            .ok_or_else(|| anyhow!(format!("the mob {:?} doesn't exist", mob)))?;
+        self.spawned_mobs.insert(loc, mob);
+        Ok(())
+    }
+
+    pub fn num_spawned_mobs(&self) -> usize {
+        self.spawned_mobs.len()
+    }
+
+    /// drop every spawned mob that is still in its freshly spawned state and
+    /// has no player within `radius`. Only the mob's marker in the mob map is
+    /// kept, moved back to the mob's home, so the mob will be instantiated
+    /// there again the next time someone asks for it. Mobs that have been
+    /// damaged, looted or are busy chasing someone are kept around, as are
+    /// mobs that wandered off and can't go home because something else is
+    /// there. Returns the number of mobs that were despawned.
+    pub fn despawn_idle_mobs(&mut self, players: &[Vector3], radius: f64) -> Result<usize> {
+        let sqr_radius = radius * radius;
+        let mut idle: Vec<(Vector3, Vector3)> = self
+            .spawned_mobs
+            .iter()
+            .filter(|(loc, mob)| {
+                mob.is_pristine() && players.iter().all(|p| p.sqr_dist(loc) > sqr_radius)
+            })
+            .map(|(loc, mob)| (loc, mob.home()))
+            .collect();
+        idle.sort_by_key(|(loc, _)| (loc.z(), loc.y(), loc.x()));
+        let mut despawned = 0;
+        for (loc, home) in idle {
+            if loc != home {
+                if self.has_mob(home)? {
+                    continue;
+                }
+                self.move_mob(loc, home)?;
+            }
+            self.spawned_mobs.remove_loc(home);
+            despawned += 1;
+        }
+        Ok(despawned)
+    }
+
+    /// write back what a battle did to the world's mob at `loc`, which
+    /// fought in `battle` as combatant `who`. A defeated mob is gone for good,
+    /// and one that was hurt or looted is remembered as it is.
+    pub fn record_battle(
+        &mut self,
+        loc: Vector3,
+        g: &GameData,
+        battle: &Battle,
+        who: CombatantId,
+    ) -> Result<()> {
+        let (start, end) = match (battle.started_as(who), battle.combatant(who)) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return Err(anyhow!(format!("there is no combatant {} in the battle", who))),
+        };
+        if end.defeated {
+            return self.delete_mob_by_loc(loc);
+        }
+        let mut mob = self.get_mob(loc, g)?;
+        mob.fought(start, end);
+        self.update_mob(mob)
+    }
+
     pub fn move_mob(&mut self, start: Vector3, end: Vector3) -> Result<()> {
         if let Some(_) = self.mob_map.get(end)?.as_u16() {
             return Err(anyhow!(format!("there's already a mob at {:?}", end)));
@@ -298,3 +423,109 @@ impl World {
         Ok(())
     }
 }
+
+#[cfg(test)]
+impl World {
+    /// a world made of nothing but `block`, with no mobs, for tests.
+    pub(crate) fn filled(dim: Vector3, block: &str, g: &GameData) -> World {
+        let id = g.get_block_id_by_name(block).unwrap();
+        let color = g.get_block_by_id(id).unwrap().color;
+        World {
+            spawned_mobs: SpawnedMobs::new(),
+            mob_map: Map::new(dim, MobU16::empty()),
+            block_map: Map::new(dim, id),
+            color_map: Map::new(dim, color),
+            seed: 0,
+            id: 0,
+            rng: get_rand(0),
+        }
+    }
+
+    pub(crate) fn set_block(&mut self, loc: Vector3, block: &str, g: &GameData) {
+        let id = g.get_block_id_by_name(block).unwrap();
+        self.block_map.set(loc, id).unwrap();
+        self.color_map
+            .set(loc, g.get_block_by_id(id).unwrap().color)
+            .unwrap();
+    }
+
+    /// put a mob that hasn't been spawned yet at `loc`.
+    pub(crate) fn put_mob(&mut self, loc: Vector3, mob: &str, g: &GameData) {
+        let id = g.mob_id_map.get_by_right(&mob.to_string().into()).unwrap();
+        self.mob_map.set(loc, MobU16(*id)).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gamedata::gamedata::pvp, mob_ai::MobState};
+
+    fn world_with_mob(g: &GameData, home: Vector3) -> World {
+        let mut world = World::filled(Vector3::new(20, 20, 1), "grass", g);
+        world.put_mob(home, "lowly_ben", g);
+        world.get_mob(home, g).unwrap();
+        world
+    }
+
+    #[test]
+    fn despawns_pristine_mobs_away_from_players() {
+        let g = pvp();
+        let home = Vector3::new(2, 2, 0);
+        let mut world = world_with_mob(&g, home);
+        let near = [Vector3::new(4, 2, 0)];
+        assert_eq!(world.despawn_idle_mobs(&near, 5.0).unwrap(), 0);
+        let far = [Vector3::new(19, 19, 0)];
+        assert_eq!(world.despawn_idle_mobs(&far, 5.0).unwrap(), 1);
+        assert_eq!(world.num_spawned_mobs(), 0);
+        // the mob is still there, ready to be spawned again
+        assert!(world.has_mob(home).unwrap());
+        assert_eq!(world.get_mob(home, &g).unwrap().home(), home);
+    }
+
+    #[test]
+    fn keeps_mobs_that_something_happened_to() {
+        let g = pvp();
+        let far = [Vector3::new(19, 19, 0)];
+        let home = Vector3::new(2, 2, 0);
+
+        let mut world = world_with_mob(&g, home);
+        let mut mob = world.get_mob(home, &g).unwrap();
+        mob.damage(1.0);
+        world.update_mob(mob).unwrap();
+        assert_eq!(world.despawn_idle_mobs(&far, 5.0).unwrap(), 0);
+
+        let mut world = world_with_mob(&g, home);
+        let mut mob = world.get_mob(home, &g).unwrap();
+        mob.loot();
+        world.update_mob(mob).unwrap();
+        assert_eq!(world.despawn_idle_mobs(&far, 5.0).unwrap(), 0);
+
+        let mut world = world_with_mob(&g, home);
+        let mut mob = world.get_mob(home, &g).unwrap();
+        mob.set_state(MobState::Chasing);
+        world.update_mob(mob).unwrap();
+        assert_eq!(world.despawn_idle_mobs(&far, 5.0).unwrap(), 0);
+        assert_eq!(world.num_spawned_mobs(), 1);
+    }
+
+    #[test]
+    fn despawned_mobs_go_home() {
+        let g = pvp();
+        let far = [Vector3::new(19, 19, 0)];
+        let home = Vector3::new(2, 2, 0);
+        let wandered = Vector3::new(3, 2, 0);
+        let mut world = world_with_mob(&g, home);
+        world.move_mob(home, wandered).unwrap();
+        assert_eq!(world.despawn_idle_mobs(&far, 5.0).unwrap(), 1);
+        assert!(world.has_mob(home).unwrap());
+        assert!(!world.has_mob(wandered).unwrap());
+
+        // unless someone else is in the way
+        let mut world = world_with_mob(&g, home);
+        world.move_mob(home, wandered).unwrap();
+        world.put_mob(home, "lowly_ben", &g);
+        assert_eq!(world.despawn_idle_mobs(&far, 5.0).unwrap(), 0);
+        assert!(world.has_mob(wandered).unwrap());
+    }
+}